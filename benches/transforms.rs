@@ -10,30 +10,70 @@ use bencher::Bencher;
 use std::f32::consts::PI;
 
 const SAMPLE_RATE: f32 = 44100.0;
-const FRAMES: u16 = 512;
 
 
 fn sin_arg(f: f32, t: f32, phase: f32) -> f32 {
     2.0 as f32 * PI * f * t + phase
 }
 
-fn sample_sinusoud(amplitude: f32, frequency: f32, phase: f32) -> Vec<f32> {
-    (0..FRAMES)
+fn sample_sinusoud(amplitude: f32, frequency: f32, phase: f32, frames: u16) -> Vec<f32> {
+    (0..frames)
         .map(|x| {
             let t = f32::from(x) / SAMPLE_RATE;
             sin_arg(frequency, t, phase).sin() * amplitude
         }).collect()
 }
 
-fn bench_correlation_on_sine_wave(b: &mut Bencher) {
-    let signal = Signal::new(
-        &sample_sinusoud(1.0, 440.0f32, 0.0),
+fn sine_wave_signal(frames: u16) -> Signal {
+    Signal::new(
+        &sample_sinusoud(1.0, 440.0f32, 0.0, frames),
         SAMPLE_RATE
-    );
-    
+    )
+}
+
+fn bench_fft_correlation(b: &mut Bencher, frames: u16) {
+    let signal = sine_wave_signal(frames);
     b.iter(|| {
         Correlation::from_signal(&signal);
     })
 }
-benchmark_group!(transforms, bench_correlation_on_sine_wave);
+
+fn bench_direct_correlation(b: &mut Bencher, frames: u16) {
+    let signal = sine_wave_signal(frames);
+    b.iter(|| {
+        Correlation::from_signal_direct(&signal);
+    })
+}
+
+fn bench_correlation_on_sine_wave(b: &mut Bencher) {
+    bench_fft_correlation(b, 512)
+}
+
+fn bench_fft_correlation_2048(b: &mut Bencher) {
+    bench_fft_correlation(b, 2048)
+}
+
+fn bench_fft_correlation_8192(b: &mut Bencher) {
+    bench_fft_correlation(b, 8192)
+}
+
+fn bench_direct_correlation_512(b: &mut Bencher) {
+    bench_direct_correlation(b, 512)
+}
+
+fn bench_direct_correlation_2048(b: &mut Bencher) {
+    bench_direct_correlation(b, 2048)
+}
+
+fn bench_direct_correlation_8192(b: &mut Bencher) {
+    bench_direct_correlation(b, 8192)
+}
+
+benchmark_group!(transforms,
+                 bench_correlation_on_sine_wave,
+                 bench_fft_correlation_2048,
+                 bench_fft_correlation_8192,
+                 bench_direct_correlation_512,
+                 bench_direct_correlation_2048,
+                 bench_direct_correlation_8192);
 benchmark_main!(transforms);
@@ -0,0 +1,52 @@
+/* Written out by rusty_microphone::c_api::header. The function
+ * declarations are maintained by hand in src/c_api.rs, and the tests
+ * check them against the Rust functions. Make changes there, then run
+ * `RUSTY_MICROPHONE_WRITE_HEADER=1 cargo test` to rewrite this file. */
+#ifndef RUSTY_MICROPHONE_H
+#define RUSTY_MICROPHONE_H
+
+#include <stddef.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct RmAnalyzer RmAnalyzer;
+
+/* Every int returned is one of these. After RM_INTERNAL_ERROR, the
+ * analyzer should be freed. */
+#define RM_OK 0
+#define RM_NULL_POINTER 1
+#define RM_INVALID_ARGUMENT 2
+#define RM_NO_PITCH 3
+#define RM_BUFFER_TOO_SMALL 4
+#define RM_INTERNAL_ERROR 5
+
+/* Returns NULL if the sample rate or reference aren't positive. */
+RmAnalyzer *rm_analyzer_new(float sample_rate, float a4_hz);
+void rm_analyzer_free(RmAnalyzer *analyzer);
+int rm_analyzer_set_reference(RmAnalyzer *analyzer, float a4_hz);
+
+/* Blocks of samples should follow on from each other without gaps or
+ * overlaps. */
+int rm_analyzer_feed(RmAnalyzer *analyzer, const float *samples, size_t length);
+
+/* Either output may be NULL if it isn't wanted. Without a pitch, hz is
+ * set to NaN and RM_NO_PITCH is returned, but clarity is still set. */
+int rm_analyzer_pitch(const RmAnalyzer *analyzer, float *hz, float *clarity);
+int rm_analyzer_cents(const RmAnalyzer *analyzer, float *cents);
+
+/* Writes the note name, like "C♯4", as 0 terminated UTF-8. It's
+ * written for an instrument that sounds transposition_semitones below
+ * its written pitch, so 2 for a B♭ trumpet. Without a pitch the name is
+ * empty and RM_NO_PITCH is returned. 16 bytes is always enough. */
+int rm_analyzer_note_name(const RmAnalyzer *analyzer, int transposition_semitones, char *buffer, size_t buffer_length);
+
+/* The string is static, so it must not be freed. */
+const char *rm_status_message(int status);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
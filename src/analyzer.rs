@@ -0,0 +1,118 @@
+use model::{Model, AnalysisSettings};
+use detector::{PitchEstimate, Rejection};
+use frames::SlidingWindow;
+use smoothing::PitchSmoother;
+use pitch::Pitch;
+use signal::MIN_DBFS;
+
+// What the analyzer made of the newest frame.
+#[derive(Debug, Clone, Copy)]
+pub struct Reading {
+    pub estimate: PitchEstimate,
+    // smoothed, so it may be from a frame or two before the estimate
+    pub pitch: Option<Pitch>,
+    pub level_db: f32
+}
+
+impl Reading {
+    fn silence() -> Reading {
+        Reading {
+            estimate: PitchEstimate::rejected(Rejection::Silence),
+            pitch: None,
+            level_db: MIN_DBFS
+        }
+    }
+}
+
+// Analyzes audio that arrives a block at a time, for callers outside
+// Rust that can't hold on to a sliding window and smoother themselves.
+// Blocks should follow on from each other without gaps or overlaps.
+pub struct Analyzer {
+    pub settings: AnalysisSettings,
+    sample_rate: f32,
+    window: SlidingWindow,
+    smoother: PitchSmoother,
+    reading: Reading
+}
+
+impl Analyzer {
+    pub fn new(sample_rate: f32, settings: AnalysisSettings) -> Analyzer {
+        Analyzer {
+            settings: settings,
+            sample_rate: sample_rate,
+            window: SlidingWindow::default(),
+            smoother: PitchSmoother::new(settings.smoothing),
+            reading: Reading::silence()
+        }
+    }
+
+    // Analyzes the newest frame if a full one has arrived since the
+    // last call, and returns whether the reading changed. Older frames
+    // are skipped, as the GUI does, since only the newest is shown.
+    pub fn feed(&mut self, samples: &[f32]) -> bool {
+        self.window.push(samples);
+        let frame = match self.window.latest_frame(self.sample_rate) {
+            Some(frame) => frame,
+            None => return false
+        };
+
+        let time = frame.centre_time();
+        let model = Model::from_signal(frame.signal, &self.settings);
+        self.smoother.set_smoothing(self.settings.smoothing);
+        let pitch = self.smoother.push(time, model.pitch)
+            .and_then(|smoothed| smoothed.pitch)
+            // settings may have changed since the pitch was found
+            .map(|pitch| Pitch::with_reference(pitch.hz, self.settings.reference));
+        self.reading = Reading {
+            estimate: model.estimate,
+            pitch: pitch,
+            level_db: model.signal.rms_dbfs()
+        };
+        true
+    }
+
+    pub fn reading(&self) -> &Reading {
+        &self.reading
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::PI;
+    use pitch::TuningReference;
+
+    const SAMPLE_RATE: f32 = 44100.0;
+
+    fn sine(frequency: f32, start: usize, length: usize) -> Vec<f32> {
+        (start..start + length)
+            .map(|i| 0.5 * (2.0 * PI * frequency * i as f32 / SAMPLE_RATE).sin())
+            .collect()
+    }
+
+    #[test]
+    fn pitch_is_found_across_blocks() {
+        let mut analyzer = Analyzer::new(SAMPLE_RATE, AnalysisSettings::default());
+        // smaller than a frame, so nothing can be found from one block
+        assert!(!analyzer.feed(&sine(440.0, 0, 1000)));
+        assert!(analyzer.reading().pitch.is_none());
+
+        assert!(analyzer.feed(&sine(440.0, 1000, 1100)));
+        let pitch = analyzer.reading().pitch.expect("no pitch");
+        assert!((pitch.hz - 440.0).abs() < 1.0, "hz={}", pitch.hz);
+        assert!(analyzer.reading().level_db > -10.0);
+    }
+
+    #[test]
+    fn reference_changes_apply_to_the_next_reading() {
+        let mut analyzer = Analyzer::new(SAMPLE_RATE, AnalysisSettings::default());
+        analyzer.feed(&sine(442.0, 0, 2048));
+        let cents = analyzer.reading().pitch.unwrap().cents_error();
+        assert!((cents - 7.85).abs() < 1.0, "cents={}", cents);
+
+        analyzer.settings.reference = TuningReference::new(442.0);
+        analyzer.feed(&sine(442.0, 2048, 512));
+        let cents = analyzer.reading().pitch.unwrap().cents_error();
+        assert!(cents.abs() < 1.0, "cents={}", cents);
+    }
+}
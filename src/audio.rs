@@ -1,8 +1,11 @@
 use portaudio as pa;
 
+use std::error;
+use std::fmt;
 use std::sync::mpsc::*;
 
 use signal::Signal;
+use tone::ToneGenerator;
 
 pub const SAMPLE_RATE: f32 = 44100.0;
 // I want to use the frames constant in contexts where I need to cast
@@ -10,11 +13,42 @@ pub const SAMPLE_RATE: f32 = 44100.0;
 // be convertable to f32 losslessly. Hence the type of u16.
 pub const FRAMES: u16 = 512;
 
-pub fn init() -> Result<pa::PortAudio, pa::Error> {
-    pa::PortAudio::new()
+#[derive(Debug)]
+pub enum AudioError {
+    PortAudio(pa::Error),
+    // no input device by this name is connected
+    DeviceNotFound(String),
+    // The stream is open but audio has stopped arriving, which is
+    // what most devices do when they're unplugged.
+    StreamDied
 }
 
-pub fn get_device_list(pa: &pa::PortAudio) -> Result<Vec<(u32, String)>, pa::Error> {
+impl fmt::Display for AudioError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            AudioError::PortAudio(ref e) => write!(f, "{}", e),
+            AudioError::DeviceNotFound(ref name) => write!(f, "{} is not connected", name),
+            AudioError::StreamDied => write!(f, "The input device stopped sending audio")
+        }
+    }
+}
+
+impl error::Error for AudioError {}
+
+impl From<pa::Error> for AudioError {
+    fn from(e: pa::Error) -> AudioError {
+        AudioError::PortAudio(e)
+    }
+}
+
+// PortAudio only looks for devices when it's initialised, so seeing
+// devices that have been plugged in since means dropping every stream
+// and the PortAudio instance, then calling this again.
+pub fn init() -> Result<pa::PortAudio, AudioError> {
+    Ok(pa::PortAudio::new()?)
+}
+
+pub fn get_device_list(pa: &pa::PortAudio) -> Result<Vec<(u32, String)>, AudioError> {
     // This pa.devices gives a Result of devices, each of which is
     // also a Result. So a Result<Collection<Result<Device>>>. We
     // mould it into devices: a Vec<(index, DeviceInfo)>.
@@ -28,18 +62,18 @@ pub fn get_device_list(pa: &pa::PortAudio) -> Result<Vec<(u32, String)>, pa::Err
     Ok(list)
 }
 
-pub fn get_default_device(pa: &pa::PortAudio) -> Result<u32, pa::Error> {
+pub fn get_default_device(pa: &pa::PortAudio) -> Result<u32, AudioError> {
     let pa::DeviceIndex(default_input_index) = pa.default_input_device()?;
     Ok(default_input_index)
 }
 
-pub fn start_listening_default(pa: &pa::PortAudio, sender: Sender<Signal>) -> Result<pa::Stream<pa::NonBlocking, pa::Input<f32>>, pa::Error> {
+pub fn start_listening_default(pa: &pa::PortAudio, sender: Sender<Signal>) -> Result<pa::Stream<pa::NonBlocking, pa::Input<f32>>, AudioError> {
     let default = get_default_device(pa)?;
     start_listening(pa, default, sender)
 }
 
 pub fn start_listening(pa: &pa::PortAudio, device_index: u32,
-                       sender: Sender<Signal>) -> Result<pa::Stream<pa::NonBlocking, pa::Input<f32>>, pa::Error> {
+                       sender: Sender<Signal>) -> Result<pa::Stream<pa::NonBlocking, pa::Input<f32>>, AudioError> {
     let device_info = try!(pa.device_info(pa::DeviceIndex(device_index)));
     let latency = device_info.default_low_input_latency;
 
@@ -67,6 +101,38 @@ pub fn start_listening(pa: &pa::PortAudio, device_index: u32,
     Ok(stream)
 }
 
+pub fn get_default_output_device(pa: &pa::PortAudio) -> Result<u32, AudioError> {
+    let pa::DeviceIndex(default_output_index) = pa.default_output_device()?;
+    Ok(default_output_index)
+}
+
+pub fn start_playing_default(pa: &pa::PortAudio, generator: ToneGenerator) -> Result<pa::Stream<pa::NonBlocking, pa::Output<f32>>, AudioError> {
+    let default = get_default_output_device(pa)?;
+    start_playing(pa, default, generator)
+}
+
+pub fn start_playing(pa: &pa::PortAudio, device_index: u32,
+                     mut generator: ToneGenerator) -> Result<pa::Stream<pa::NonBlocking, pa::Output<f32>>, AudioError> {
+    let device_info = pa.device_info(pa::DeviceIndex(device_index))?;
+    let latency = device_info.default_low_output_latency;
+
+    let output_params = pa::StreamParameters::<f32>::new(pa::DeviceIndex(device_index), 1, true, latency);
+    pa.is_output_format_supported(output_params, f64::from(generator.sample_rate))?;
+    let stream_settings = pa::OutputStreamSettings::new(output_params, f64::from(generator.sample_rate), u32::from(FRAMES));
+
+    // The generator keeps its phase between callbacks, so the tone
+    // carries on smoothly from one buffer to the next.
+    let callback = move |pa::OutputStreamCallbackArgs { buffer, .. }| {
+        generator.fill(buffer);
+        pa::Continue
+    };
+
+    let mut stream = pa.open_non_blocking_stream(stream_settings, callback)?;
+    stream.start()?;
+
+    Ok(stream)
+}
+
 #[test]
 #[ignore] //ignored because TravisCI doesn't have any audio devices to test with
 fn start_listening_returns_successfully() {
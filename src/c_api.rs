@@ -0,0 +1,394 @@
+// The C API, for linking the tuner into native programs. Everything is
+// prefixed with rm_ so that it can't clash with the host program's
+// symbols. The matching header is include/rusty_microphone.h, which is
+// written out by `header` below, and the tests check it's up to date.
+// The status codes in it come from RmStatus, but the function
+// declarations are maintained by hand in FUNCTIONS. The tests check
+// each one against the Rust signature of its function, so a changed
+// signature fails them until FUNCTIONS is changed to match.
+//
+// The safety contract is the same for every function, so it's given
+// once here: pointers must be NULL or valid for the lengths given, and
+// analyzers must come from rm_analyzer_new and not be used after
+// rm_analyzer_free. A panic is caught before it reaches C, and reported
+// as RM_INTERNAL_ERROR; the analyzer should be freed after one.
+#![allow(clippy::missing_safety_doc)]
+
+use model::AnalysisSettings;
+use analyzer::Analyzer;
+use pitch::TuningReference;
+
+use std::os::raw::{c_char, c_int};
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+use std::slice;
+use std::f32;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RmStatus {
+    Ok = 0,
+    NullPointer = 1,
+    InvalidArgument = 2,
+    NoPitch = 3,
+    BufferTooSmall = 4,
+    InternalError = 5
+}
+
+impl RmStatus {
+    pub fn all() -> Vec<RmStatus> {
+        vec!(
+            RmStatus::Ok,
+            RmStatus::NullPointer,
+            RmStatus::InvalidArgument,
+            RmStatus::NoPitch,
+            RmStatus::BufferTooSmall,
+            RmStatus::InternalError
+        )
+    }
+
+    pub fn c_name(&self) -> &'static str {
+        match *self {
+            RmStatus::Ok => "RM_OK",
+            RmStatus::NullPointer => "RM_NULL_POINTER",
+            RmStatus::InvalidArgument => "RM_INVALID_ARGUMENT",
+            RmStatus::NoPitch => "RM_NO_PITCH",
+            RmStatus::BufferTooSmall => "RM_BUFFER_TOO_SMALL",
+            RmStatus::InternalError => "RM_INTERNAL_ERROR"
+        }
+    }
+
+    // 0 terminated, so it can be handed straight to C
+    fn message(&self) -> &'static str {
+        match *self {
+            RmStatus::Ok => "ok\0",
+            RmStatus::NullPointer => "a required pointer was NULL\0",
+            RmStatus::InvalidArgument => "an argument was out of range\0",
+            RmStatus::NoPitch => "no pitch was found in the latest audio\0",
+            RmStatus::BufferTooSmall => "the buffer is too small for the result\0",
+            RmStatus::InternalError => "an internal error stopped the call\0"
+        }
+    }
+}
+
+// Opaque to C
+pub struct RmAnalyzer {
+    analyzer: Analyzer
+}
+
+fn is_positive(value: f32) -> bool {
+    value.is_finite() && value > 0.0
+}
+
+// Unwinding into C is undefined behaviour, so every function's body
+// runs in here, and returns on_panic if it panics.
+fn catch_panic<T, F: FnOnce() -> T>(on_panic: T, body: F) -> T {
+    panic::catch_unwind(AssertUnwindSafe(body)).unwrap_or(on_panic)
+}
+
+// Returns NULL if the sample rate or reference aren't positive.
+#[no_mangle]
+pub extern "C" fn rm_analyzer_new(sample_rate: f32, a4_hz: f32) -> *mut RmAnalyzer {
+    catch_panic(ptr::null_mut(), || {
+        if !is_positive(sample_rate) || !is_positive(a4_hz) {
+            return ptr::null_mut();
+        }
+        let settings = AnalysisSettings {
+            reference: TuningReference::new(a4_hz),
+            ..AnalysisSettings::default()
+        };
+        Box::into_raw(Box::new(RmAnalyzer {
+            analyzer: Analyzer::new(sample_rate, settings)
+        }))
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn rm_analyzer_free(analyzer: *mut RmAnalyzer) {
+    catch_panic((), || {
+        if !analyzer.is_null() {
+            drop(Box::from_raw(analyzer));
+        }
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn rm_analyzer_set_reference(analyzer: *mut RmAnalyzer, a4_hz: f32) -> c_int {
+    catch_panic(RmStatus::InternalError as c_int, || {
+        let analyzer = match analyzer.as_mut() {
+            Some(analyzer) => analyzer,
+            None => return RmStatus::NullPointer as c_int
+        };
+        if !is_positive(a4_hz) {
+            return RmStatus::InvalidArgument as c_int;
+        }
+        analyzer.analyzer.settings.reference = TuningReference::new(a4_hz);
+        RmStatus::Ok as c_int
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn rm_analyzer_feed(analyzer: *mut RmAnalyzer, samples: *const f32, length: usize) -> c_int {
+    catch_panic(RmStatus::InternalError as c_int, || {
+        let analyzer = match analyzer.as_mut() {
+            Some(analyzer) => analyzer,
+            None => return RmStatus::NullPointer as c_int
+        };
+        if length == 0 {
+            return RmStatus::Ok as c_int;
+        }
+        if samples.is_null() {
+            return RmStatus::NullPointer as c_int;
+        }
+        analyzer.analyzer.feed(slice::from_raw_parts(samples, length));
+        RmStatus::Ok as c_int
+    })
+}
+
+// Either output may be NULL if it isn't wanted. Without a pitch, hz is
+// set to NaN and RM_NO_PITCH is returned, but clarity is still set.
+#[no_mangle]
+pub unsafe extern "C" fn rm_analyzer_pitch(analyzer: *const RmAnalyzer, hz: *mut f32, clarity: *mut f32) -> c_int {
+    catch_panic(RmStatus::InternalError as c_int, || {
+        let reading = match analyzer.as_ref() {
+            Some(analyzer) => analyzer.analyzer.reading(),
+            None => return RmStatus::NullPointer as c_int
+        };
+        if let Some(clarity) = clarity.as_mut() {
+            *clarity = reading.estimate.clarity;
+        }
+        if let Some(hz) = hz.as_mut() {
+            *hz = reading.pitch.map_or(f32::NAN, |p| p.hz);
+        }
+        match reading.pitch {
+            Some(_) => RmStatus::Ok as c_int,
+            None => RmStatus::NoPitch as c_int
+        }
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn rm_analyzer_cents(analyzer: *const RmAnalyzer, cents: *mut f32) -> c_int {
+    catch_panic(RmStatus::InternalError as c_int, || {
+        let reading = match (analyzer.as_ref(), cents.as_mut()) {
+            (Some(analyzer), Some(cents)) => {
+                let reading = analyzer.analyzer.reading();
+                *cents = reading.pitch.map_or(f32::NAN, |p| p.cents_error());
+                reading
+            },
+            _ => return RmStatus::NullPointer as c_int
+        };
+        match reading.pitch {
+            Some(_) => RmStatus::Ok as c_int,
+            None => RmStatus::NoPitch as c_int
+        }
+    })
+}
+
+// Writes the note name, like "C♯4", as 0 terminated UTF-8. It's
+// written for an instrument that sounds `transposition_semitones`
+// below its written pitch, so 2 for a B♭ trumpet. Without a pitch the
+// name is empty and RM_NO_PITCH is returned. 16 bytes is always
+// enough.
+#[no_mangle]
+pub unsafe extern "C" fn rm_analyzer_note_name(analyzer: *const RmAnalyzer, transposition_semitones: c_int, buffer: *mut c_char, buffer_length: usize) -> c_int {
+    catch_panic(RmStatus::InternalError as c_int, || {
+        let reading = match (analyzer.as_ref(), buffer.is_null()) {
+            (Some(analyzer), false) => analyzer.analyzer.reading(),
+            _ => return RmStatus::NullPointer as c_int
+        };
+        let name = reading.pitch
+            .map_or(String::new(), |p| format!("{}", p.transposed(transposition_semitones)));
+        if name.len() + 1 > buffer_length {
+            if buffer_length > 0 {
+                *buffer = 0;
+            }
+            return RmStatus::BufferTooSmall as c_int;
+        }
+        let buffer = slice::from_raw_parts_mut(buffer as *mut u8, buffer_length);
+        buffer[..name.len()].copy_from_slice(name.as_bytes());
+        buffer[name.len()] = 0;
+        match reading.pitch {
+            Some(_) => RmStatus::Ok as c_int,
+            None => RmStatus::NoPitch as c_int
+        }
+    })
+}
+
+// A description of a status code. The string is static, so it must not
+// be freed.
+#[no_mangle]
+pub extern "C" fn rm_status_message(status: c_int) -> *const c_char {
+    catch_panic("unknown status\0", || {
+        RmStatus::all().into_iter()
+            .find(|&s| s as c_int == status)
+            .map_or("unknown status\0", |s| s.message())
+    }).as_ptr() as *const c_char
+}
+
+const FUNCTIONS: &str = "\
+/* Returns NULL if the sample rate or reference aren't positive. */
+RmAnalyzer *rm_analyzer_new(float sample_rate, float a4_hz);
+void rm_analyzer_free(RmAnalyzer *analyzer);
+int rm_analyzer_set_reference(RmAnalyzer *analyzer, float a4_hz);
+
+/* Blocks of samples should follow on from each other without gaps or
+ * overlaps. */
+int rm_analyzer_feed(RmAnalyzer *analyzer, const float *samples, size_t length);
+
+/* Either output may be NULL if it isn't wanted. Without a pitch, hz is
+ * set to NaN and RM_NO_PITCH is returned, but clarity is still set. */
+int rm_analyzer_pitch(const RmAnalyzer *analyzer, float *hz, float *clarity);
+int rm_analyzer_cents(const RmAnalyzer *analyzer, float *cents);
+
+/* Writes the note name, like \"C♯4\", as 0 terminated UTF-8. It's
+ * written for an instrument that sounds transposition_semitones below
+ * its written pitch, so 2 for a B♭ trumpet. Without a pitch the name is
+ * empty and RM_NO_PITCH is returned. 16 bytes is always enough. */
+int rm_analyzer_note_name(const RmAnalyzer *analyzer, int transposition_semitones, char *buffer, size_t buffer_length);
+
+/* The string is static, so it must not be freed. */
+const char *rm_status_message(int status);
+";
+
+pub fn header() -> String {
+    let statuses: Vec<String> = RmStatus::all().into_iter()
+        .map(|s| format!("#define {} {}", s.c_name(), s as c_int))
+        .collect();
+    format!("\
+/* Written out by rusty_microphone::c_api::header. The function
+ * declarations are maintained by hand in src/c_api.rs, and the tests
+ * check them against the Rust functions. Make changes there, then run
+ * `RUSTY_MICROPHONE_WRITE_HEADER=1 cargo test` to rewrite this file. */
+#ifndef RUSTY_MICROPHONE_H
+#define RUSTY_MICROPHONE_H
+
+#include <stddef.h>
+
+#ifdef __cplusplus
+extern \"C\" {{
+#endif
+
+typedef struct RmAnalyzer RmAnalyzer;
+
+/* Every int returned is one of these. After RM_INTERNAL_ERROR, the
+ * analyzer should be freed. */
+{}
+
+{}
+#ifdef __cplusplus
+}}
+#endif
+
+#endif
+", statuses.join("\n"), FUNCTIONS)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::ffi::CStr;
+    use std::fs;
+    use std::path::Path;
+
+    #[test]
+    fn header_is_up_to_date() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("include").join("rusty_microphone.h");
+        if env::var_os("RUSTY_MICROPHONE_WRITE_HEADER").is_some() {
+            fs::write(&path, header()).unwrap();
+        }
+        let existing = fs::read_to_string(&path).unwrap_or_default();
+        assert!(existing == header(), "{} is out of date", path.display());
+    }
+
+    // The C for a Rust type in an exported function's signature.
+    fn c_type(rust: &str) -> String {
+        if let Some(pointee) = rust.strip_prefix("*const ") {
+            return format!("const {} *", c_type(pointee));
+        }
+        if let Some(pointee) = rust.strip_prefix("*mut ") {
+            return format!("{} *", c_type(pointee));
+        }
+        match rust {
+            "f32" => "float",
+            "c_int" => "int",
+            "c_char" => "char",
+            "usize" => "size_t",
+            "RmAnalyzer" => "RmAnalyzer",
+            other => panic!("No C type for {}", other)
+        }.to_string()
+    }
+
+    // A pointer's * goes against the name, as in "char *buffer".
+    fn c_declarator(c_type: &str, name: &str) -> String {
+        if c_type.ends_with('*') {
+            format!("{}{}", c_type, name)
+        }
+        else {
+            format!("{} {}", c_type, name)
+        }
+    }
+
+    // What the header should declare for a line of this file that
+    // starts an exported function.
+    fn c_declaration(signature: &str) -> String {
+        let start = signature.find("fn ").unwrap() + "fn ".len();
+        let open = signature.find('(').unwrap();
+        let close = signature.find(')').unwrap();
+        let parameters: Vec<String> = signature[open + 1..close].split(", ")
+            .map(|parameter| {
+                let mut parts = parameter.splitn(2, ": ");
+                let name = parts.next().unwrap();
+                c_declarator(&c_type(parts.next().unwrap()), name)
+            })
+            .collect();
+        let returns = signature[close..].find("-> ")
+            .map_or("void".to_string(), |arrow| c_type(signature[close + arrow + "-> ".len()..].trim_end_matches(" {")));
+        let function = format!("{}({})", &signature[start..open], parameters.join(", "));
+        format!("{};", c_declarator(&returns, &function))
+    }
+
+    #[test]
+    fn declarations_match_the_functions() {
+        let declarations: Vec<String> = include_str!("c_api.rs").lines()
+            .filter(|line| line.starts_with("pub ") && line.contains(" extern \"C\" fn "))
+            .map(c_declaration)
+            .collect();
+        let declared: Vec<&str> = FUNCTIONS.lines().filter(|line| line.ends_with(");")).collect();
+        for declaration in &declarations {
+            assert!(declared.contains(&declaration.as_str()), "FUNCTIONS should declare {}", declaration);
+        }
+        assert_eq!(declared.len(), declarations.len(), "FUNCTIONS declares functions that aren't exported: {:?}", declared);
+    }
+
+    #[test]
+    fn every_status_has_a_message() {
+        for status in RmStatus::all() {
+            let message = unsafe { CStr::from_ptr(rm_status_message(status as c_int)) };
+            assert!(!message.to_bytes().is_empty());
+        }
+    }
+
+    #[test]
+    fn bad_arguments_are_reported() {
+        assert!(rm_analyzer_new(0.0, 440.0).is_null());
+        assert!(rm_analyzer_new(44100.0, f32::NAN).is_null());
+        unsafe {
+            let mut hz = 0.0;
+            assert_eq!(rm_analyzer_pitch(ptr::null(), &mut hz, ptr::null_mut()), RmStatus::NullPointer as c_int);
+
+            let analyzer = rm_analyzer_new(44100.0, 440.0);
+            assert_eq!(rm_analyzer_set_reference(analyzer, -1.0), RmStatus::InvalidArgument as c_int);
+            assert_eq!(rm_analyzer_pitch(analyzer, &mut hz, ptr::null_mut()), RmStatus::NoPitch as c_int);
+            assert!(hz.is_nan());
+            rm_analyzer_free(analyzer);
+        }
+    }
+
+    #[test]
+    fn panics_become_internal_errors() {
+        let status = catch_panic(RmStatus::InternalError as c_int, || -> c_int { panic!("in the analyzer") });
+        assert_eq!(status, RmStatus::InternalError as c_int);
+        assert_eq!(catch_panic(RmStatus::InternalError as c_int, || RmStatus::Ok as c_int), RmStatus::Ok as c_int);
+    }
+}
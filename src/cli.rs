@@ -0,0 +1,552 @@
+use portaudio as pa;
+
+use std::error;
+use std::fmt;
+use std::io;
+use std::io::Write;
+use std::sync::mpsc::*;
+use std::time::Duration;
+
+use model::{Model, AnalysisSettings};
+use pitch::{self, Transposition, TuningReference, NoteNaming, Accidentals};
+use track;
+use midi;
+use frames::{self, SlidingWindow};
+use smoothing::{PitchSmoother, MAX_LATENCY};
+use wav::{Wav, WavError};
+use audio::AudioError;
+use scala::{ScalaTuning, ScalaError};
+
+// Exit codes follow the BSD sysexits.h conventions, so that scripts
+// can tell a typo apart from a missing microphone.
+pub const EXIT_USAGE: i32 = 64;
+pub const EXIT_DATA_ERROR: i32 = 65;
+pub const EXIT_NO_INPUT: i32 = 66;
+pub const EXIT_UNAVAILABLE: i32 = 69;
+pub const EXIT_IO_ERROR: i32 = 74;
+
+const USAGE: &str = "Usage: rusty_microphone [COMMAND] [OPTIONS]
+
+Commands:
+    gui                 Open the tuner window (the default)
+    devices             List the available input devices
+    tune                Run a tuner in the terminal
+    analyze <FILE>      Print a pitch track for a WAV file
+    help                Show this message
+
+Options:
+    --reference <HZ>    Frequency of A4 (default 440)
+    --temperament <NAME>
+                        Tune the other notes around A4 in equal (the
+                        default), just-major, just-minor, pythagorean,
+                        meantone, werckmeister, kirnberger or vallotti
+    --tonic <NOTE>      Key the temperament is built on, like C or F#
+                        (default C)
+    --scl <FILE>        Name notes and measure cents in a Scala scale file,
+                        instead of the reference and temperament
+    --kbm <FILE>        Scala keyboard mapping for --scl (default degree 0 on
+                        middle C, with A4 at 440)
+    --transpose <KEY>   Show written notes for an instrument in c, bb, a, f, eb,
+                        bb-tenor (tenor sax) or eb-baritone (baritone sax)
+    --names <NAME>      Note names: letters (the default), german or solfege
+    --spelling <NAME>   Name black keys as sharps, flats, mixed (the default,
+                        C# Eb F# G# Bb) or by key signature
+    --key <NOTE>        Major key for --spelling key, like Eb (default C)
+    --octaves <NAME>    Octave notation: scientific (the default, A4) or
+                        helmholtz (a')
+    --ascii             Write sharps and flats as # and b
+    --detector <NAME>   Pitch detector: autocorrelation, yin or mpm
+    --instrument <NAME> Only look for notes in an instrument's range: voice,
+                        flute, clarinet, saxophone, trumpet, horn, trombone,
+                        tuba, violin, cello or any (the default)
+    --noise-gate <DB>   Treat peaks quieter than this many dBFS as silence
+                        (default -26)
+    --smoothing <NAME>  Correct octave errors and jitter using the frames
+                        around each one: off (the default), median or viterbi
+    --latency <N>       Frames either side that smoothing looks at, up to 50
+                        (default 3)
+    --device <DEVICE>   Input device index or name, for tune
+    --frame-size <N>    Samples per analysis frame (default 2048)
+    --hop <N>           Samples between the starts of analysis frames
+                        (default 512, or the frame size if that's smaller)
+    --midi <FILE>       Also save the notes found by analyze as a MIDI file";
+
+#[derive(Debug)]
+pub enum CliError {
+    Usage(String),
+    Audio(AudioError),
+    Gui(String),
+    Wav(WavError),
+    Scala(ScalaError),
+    Io(io::Error)
+}
+
+impl CliError {
+    pub fn exit_code(&self) -> i32 {
+        match *self {
+            CliError::Usage(_) => EXIT_USAGE,
+            CliError::Audio(_) | CliError::Gui(_) => EXIT_UNAVAILABLE,
+            CliError::Wav(WavError::Io(ref e)) if e.kind() == io::ErrorKind::NotFound => EXIT_NO_INPUT,
+            CliError::Wav(WavError::Io(_)) => EXIT_IO_ERROR,
+            CliError::Wav(_) => EXIT_DATA_ERROR,
+            CliError::Scala(ScalaError::Io(ref e)) if e.kind() == io::ErrorKind::NotFound => EXIT_NO_INPUT,
+            CliError::Scala(ScalaError::Io(_)) => EXIT_IO_ERROR,
+            CliError::Scala(_) => EXIT_DATA_ERROR,
+            CliError::Io(_) => EXIT_IO_ERROR
+        }
+    }
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CliError::Usage(ref message) => write!(f, "{}\n\n{}", message, USAGE),
+            CliError::Audio(ref e) => write!(f, "Audio error: {}", e),
+            CliError::Gui(ref e) => write!(f, "Failed to start the GUI: {}", e),
+            CliError::Wav(ref e) => write!(f, "Could not read WAV file: {}", e),
+            CliError::Scala(ref e) => write!(f, "Could not read Scala tuning: {}", e),
+            CliError::Io(ref e) => write!(f, "{}", e)
+        }
+    }
+}
+
+impl error::Error for CliError {}
+
+impl From<AudioError> for CliError {
+    fn from(e: AudioError) -> CliError {
+        CliError::Audio(e)
+    }
+}
+
+impl From<WavError> for CliError {
+    fn from(e: WavError) -> CliError {
+        CliError::Wav(e)
+    }
+}
+
+impl From<ScalaError> for CliError {
+    fn from(e: ScalaError) -> CliError {
+        CliError::Scala(e)
+    }
+}
+
+impl From<io::Error> for CliError {
+    fn from(e: io::Error) -> CliError {
+        CliError::Io(e)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Gui,
+    Devices,
+    Tune,
+    Analyze(String),
+    Help
+}
+
+#[derive(Debug, Clone)]
+pub struct Options {
+    pub command: Command,
+    pub settings: AnalysisSettings,
+    pub transposition: Transposition,
+    pub naming: NoteNaming,
+    pub scale: Option<String>,
+    pub keyboard_mapping: Option<String>,
+    pub device: Option<String>,
+    pub frame_size: usize,
+    pub hop_size: usize,
+    pub midi_output: Option<String>
+}
+
+impl Default for Options {
+    fn default() -> Options {
+        Options {
+            command: Command::Gui,
+            settings: AnalysisSettings::default(),
+            transposition: Transposition::default(),
+            naming: NoteNaming::default(),
+            scale: None,
+            keyboard_mapping: None,
+            device: None,
+            frame_size: frames::DEFAULT_FRAME_SIZE,
+            hop_size: frames::DEFAULT_HOP_SIZE,
+            midi_output: None
+        }
+    }
+}
+
+pub fn parse_args(args: &[String]) -> Result<Options, CliError> {
+    let mut options = Options::default();
+    let mut command = None;
+    let mut hop_size = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| args.next()
+            .cloned()
+            .ok_or_else(|| CliError::Usage(format!("{} needs a value", name)));
+
+        match arg.as_ref() {
+            "--reference" => options.settings.reference.a4_hz = parse_value("--reference", &value(arg)?)?,
+            "--temperament" => options.settings.reference.temperament = parse_value("--temperament", &value(arg)?)?,
+            "--tonic" => {
+                let tonic = value(arg)?;
+                options.settings.reference.tonic = pitch::parse_note_name(&tonic)
+                    .ok_or_else(|| CliError::Usage(format!("Invalid value for --tonic: {}", tonic)))?;
+            },
+            "--names" => options.naming.system = parse_value("--names", &value(arg)?)?,
+            "--spelling" => options.naming.spelling = parse_value("--spelling", &value(arg)?)?,
+            "--key" => {
+                let key = value(arg)?;
+                options.naming.key = pitch::parse_note_name(&key)
+                    .ok_or_else(|| CliError::Usage(format!("Invalid value for --key: {}", key)))?;
+            },
+            "--octaves" => options.naming.octaves = parse_value("--octaves", &value(arg)?)?,
+            "--ascii" => options.naming.accidentals = Accidentals::Ascii,
+            "--scl" => options.scale = Some(value(arg)?),
+            "--kbm" => options.keyboard_mapping = Some(value(arg)?),
+            "--transpose" => options.transposition = parse_value("--transpose", &value(arg)?)?,
+            "--detector" => options.settings.detector = parse_value("--detector", &value(arg)?)?,
+            "--instrument" => options.settings.instrument = parse_value("--instrument", &value(arg)?)?,
+            "--smoothing" => options.settings.smoothing.method = parse_value("--smoothing", &value(arg)?)?,
+            "--latency" => {
+                let latency = parse_value("--latency", &value(arg)?)?;
+                if latency > MAX_LATENCY {
+                    return Err(CliError::Usage(format!("--latency can be at most {} frames", MAX_LATENCY)));
+                }
+                options.settings.smoothing.latency = latency;
+            },
+            "--noise-gate" => options.settings.noise_gate_db = parse_value("--noise-gate", &value(arg)?)?,
+            "--device" => options.device = Some(value(arg)?),
+            "--frame-size" => options.frame_size = parse_value("--frame-size", &value(arg)?)?,
+            "--hop" => hop_size = Some(parse_value("--hop", &value(arg)?)?),
+            "--midi" => options.midi_output = Some(value(arg)?),
+            "-h" | "--help" => command = Some(Command::Help),
+            flag if flag.starts_with('-') => return Err(CliError::Usage(format!("Unknown option: {}", flag))),
+            word => match command {
+                None => command = Some(parse_command(word)?),
+                Some(Command::Analyze(ref mut path)) if path.is_empty() => *path = word.to_string(),
+                _ => return Err(CliError::Usage(format!("Unexpected argument: {}", word)))
+            }
+        }
+    }
+
+    options.command = match command {
+        Some(Command::Analyze(ref path)) if path.is_empty() => return Err(CliError::Usage("analyze needs a WAV file".to_string())),
+        Some(command) => command,
+        None => Command::Gui
+    };
+
+    if !TuningReference::is_valid_a4_hz(options.settings.reference.a4_hz) {
+        return Err(CliError::Usage("--reference must be a positive frequency".to_string()));
+    }
+    if options.keyboard_mapping.is_some() && options.scale.is_none() {
+        return Err(CliError::Usage("--kbm needs a scale from --scl".to_string()));
+    }
+    // Scala scales needn't have twelve notes, so there's no general way
+    // to shift them by semitones.
+    if options.scale.is_some() && options.transposition != Transposition::C {
+        return Err(CliError::Usage("--transpose can't be used with --scl".to_string()));
+    }
+    if options.frame_size < 64 {
+        return Err(CliError::Usage("--frame-size must be at least 64".to_string()));
+    }
+    // the same overlap as the GUI, so both give the same results
+    options.hop_size = hop_size.unwrap_or_else(|| frames::DEFAULT_HOP_SIZE.min(options.frame_size));
+    if options.hop_size == 0 {
+        return Err(CliError::Usage("--hop must be at least 1".to_string()));
+    }
+    Ok(options)
+}
+
+fn parse_command(word: &str) -> Result<Command, CliError> {
+    match word {
+        "gui" => Ok(Command::Gui),
+        "devices" => Ok(Command::Devices),
+        "tune" => Ok(Command::Tune),
+        "analyze" | "analyse" => Ok(Command::Analyze(String::new())),
+        "help" => Ok(Command::Help),
+        _ => Err(CliError::Usage(format!("Unknown command: {}", word)))
+    }
+}
+
+fn parse_value<T: ::std::str::FromStr>(name: &str, value: &str) -> Result<T, CliError> {
+    value.parse().map_err(|_| CliError::Usage(format!("Invalid value for {}: {}", name, value)))
+}
+
+pub fn run(args: &[String]) -> Result<(), CliError> {
+    let options = parse_args(args)?;
+    match options.command {
+        Command::Gui => ::gui::start_gui().map_err(CliError::Gui),
+        Command::Devices => list_devices(),
+        Command::Tune => tune(&options),
+        Command::Analyze(ref path) => analyze(path, &options),
+        Command::Help => {
+            println!("{}", USAGE);
+            Ok(())
+        }
+    }
+}
+
+fn list_devices() -> Result<(), CliError> {
+    let pa = ::audio::init()?;
+    let default = ::audio::get_default_device(&pa).ok();
+    for (index, name) in ::audio::get_device_list(&pa)? {
+        let marker = if Some(index) == default { "*" } else { " " };
+        println!("{}{}\t{}", marker, index, name);
+    }
+    Ok(())
+}
+
+fn find_device(pa: &pa::PortAudio, device: &Option<String>) -> Result<u32, AudioError> {
+    let device = match *device {
+        Some(ref device) => device,
+        None => return ::audio::get_default_device(pa)
+    };
+
+    let devices = ::audio::get_device_list(pa)?;
+    let by_index = device.parse().ok().and_then(|index| devices.iter().find(|&&(i, _)| i == index));
+    let by_name = devices.iter().find(|(_, name)| name.to_lowercase().contains(&device.to_lowercase()));
+    by_index.or(by_name)
+        .map(|&(index, _)| index)
+        .ok_or_else(|| AudioError::DeviceNotFound(device.clone()))
+}
+
+fn load_tuning(options: &Options) -> Result<Option<ScalaTuning>, CliError> {
+    match options.scale {
+        Some(ref scale) => Ok(Some(ScalaTuning::load(scale, options.keyboard_mapping.as_ref())?)),
+        None => Ok(None)
+    }
+}
+
+fn tune(options: &Options) -> Result<(), CliError> {
+    let tuning = load_tuning(options)?;
+    let pa = ::audio::init()?;
+    let device = find_device(&pa, &options.device)?;
+    let (sender, receiver) = channel();
+    let _stream = ::audio::start_listening(&pa, device, sender)?;
+
+    let mut window = SlidingWindow::new(options.frame_size, options.hop_size);
+    let mut smoother = PitchSmoother::new(options.settings.smoothing);
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+    loop {
+        let signal = receiver.recv_timeout(Duration::from_secs(2)).map_err(|_| AudioError::StreamDied)?;
+        window.push(&signal.samples);
+        while let Ok(signal) = receiver.try_recv() {
+            window.push(&signal.samples);
+        }
+
+        //keep up with the microphone, rather than falling behind
+        let frame = match window.latest_frame(signal.sample_rate) {
+            Some(frame) => frame,
+            None => continue
+        };
+        let time = frame.centre_time();
+        let model = Model::from_signal(frame.signal, &options.settings);
+        let pitch = match smoother.push(time, model.pitch) {
+            Some(smoothed) => smoothed.pitch,
+            None => continue
+        };
+        let note = match (pitch, &tuning) {
+            (Some(pitch), Some(tuning)) => pitch.nearest_in(tuning).map(|note| (note.name(&options.naming), note.cents_error)),
+            (Some(pitch), None) => Some((pitch.written(options.transposition).name(&options.naming), pitch.cents_error())),
+            (None, _) => None
+        };
+        let line = match note {
+            Some((name, cents)) => format!("{:<4}{:>+6.1}c {}", name, cents, cents_bar(cents, 41)),
+            None => String::new()
+        };
+        write!(stdout, "\r{:<60}", line)?;
+        stdout.flush()?;
+    }
+}
+
+fn analyze(path: &str, options: &Options) -> Result<(), CliError> {
+    let tuning = load_tuning(options)?;
+    let wav = Wav::open(path)?;
+    let track = track::analyze_wav(&wav, options.frame_size, options.hop_size, &options.settings);
+
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+    writeln!(stdout, "{}", track::TRACK_HEADER)?;
+    for point in &track {
+        let row = match tuning {
+            Some(ref tuning) => point.row_in(tuning, &options.naming),
+            None => point.row(options.transposition, &options.naming)
+        };
+        writeln!(stdout, "{}", row)?;
+    }
+
+    if let Some(ref midi_path) = options.midi_output {
+        midi::save(midi_path, &midi::events_from_track(&track))?;
+    }
+    Ok(())
+}
+
+// A plain text version of the GUI's pitch error indicator, flat on
+// the left and sharp on the right, with the width covering -50 to +50
+// cents.
+pub fn cents_bar(cents: f32, width: usize) -> String {
+    // odd, so there's a middle
+    let width = width | 1;
+    let middle = width / 2;
+    let position = if cents.is_finite() {
+        let offset = (cents.clamp(-50.0, 50.0) / 50.0 * middle as f32).round() as i32;
+        Some((middle as i32 + offset) as usize)
+    } else {
+        None
+    };
+
+    let bar: String = (0..width).map(|i| {
+        if Some(i) == position { '#' }
+        else if i == middle { '|' }
+        else { '-' }
+    }).collect();
+    format!("[{}]", bar)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use detector::Detector;
+    use instrument::Instrument;
+    use smoothing::SmoothingMethod;
+    use pitch::{Temperament, NamingSystem, Spelling, OctaveNotation};
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|a| a.to_string()).collect()
+    }
+
+    #[test]
+    fn no_arguments_opens_gui() {
+        assert_eq!(parse_args(&[]).unwrap().command, Command::Gui);
+    }
+
+    #[test]
+    fn analyze_takes_a_file_and_options() {
+        let options = parse_args(&args(&["analyze", "lesson.wav", "--reference", "442", "--detector", "yin", "--transpose", "bb"])).unwrap();
+        assert_eq!(options.command, Command::Analyze("lesson.wav".to_string()));
+        assert_eq!(options.settings.reference, TuningReference::new(442.0));
+        assert_eq!(options.settings.detector, Detector::Yin);
+        assert_eq!(options.transposition, Transposition::BFlat);
+    }
+
+    #[test]
+    fn instrument_bounds_the_detector() {
+        assert_eq!(parse_args(&args(&["tune"])).unwrap().settings.instrument, Instrument::Any);
+        let options = parse_args(&args(&["tune", "--instrument", "Tuba"])).unwrap();
+        assert_eq!(options.settings.instrument, Instrument::Tuba);
+        assert!(parse_args(&args(&["tune", "--instrument", "kazoo"])).is_err());
+    }
+
+    #[test]
+    fn smoothing_is_off_unless_asked_for() {
+        assert_eq!(parse_args(&args(&["tune"])).unwrap().settings.smoothing.method, SmoothingMethod::Off);
+        let options = parse_args(&args(&["analyze", "a.wav", "--smoothing", "viterbi", "--latency", "5"])).unwrap();
+        assert_eq!(options.settings.smoothing.method, SmoothingMethod::Viterbi);
+        assert_eq!(options.settings.smoothing.latency, 5);
+    }
+
+    #[test]
+    fn latency_is_limited() {
+        assert!(parse_args(&args(&["tune", "--latency", "50"])).is_ok());
+        for latency in &["51", "18446744073709551615"] {
+            match parse_args(&args(&["tune", "--latency", latency])) {
+                Err(CliError::Usage(_)) => {},
+                other => panic!("Expected a usage error for {}, got {:?}", latency, other)
+            }
+        }
+    }
+
+    #[test]
+    fn temperament_is_built_on_the_tonic() {
+        let options = parse_args(&args(&["tune", "--tonic", "Eb", "--temperament", "meantone", "--reference", "415"])).unwrap();
+        assert_eq!(options.settings.reference, TuningReference::new(415.0).with_temperament(Temperament::Meantone, 3));
+        assert!(parse_args(&args(&["tune", "--tonic", "X"])).is_err());
+        assert!(parse_args(&args(&["tune", "--temperament", "mystery"])).is_err());
+    }
+
+    #[test]
+    fn hop_defaults_to_the_gui_hop() {
+        assert_eq!(parse_args(&args(&["analyze", "a.wav"])).unwrap().hop_size, frames::DEFAULT_HOP_SIZE);
+        let options = parse_args(&args(&["analyze", "a.wav", "--frame-size", "4096"])).unwrap();
+        assert_eq!(options.hop_size, frames::DEFAULT_HOP_SIZE);
+        let options = parse_args(&args(&["analyze", "a.wav", "--frame-size", "256"])).unwrap();
+        assert_eq!(options.hop_size, 256);
+    }
+
+    #[test]
+    fn noise_gate_is_in_dbfs() {
+        assert_eq!(parse_args(&args(&["tune"])).unwrap().settings.noise_gate_db, -26.0);
+        assert_eq!(parse_args(&args(&["tune", "--noise-gate", "-45"])).unwrap().settings.noise_gate_db, -45.0);
+    }
+
+    #[test]
+    fn midi_output_is_optional() {
+        assert_eq!(parse_args(&args(&["analyze", "a.wav"])).unwrap().midi_output, None);
+        let options = parse_args(&args(&["analyze", "a.wav", "--midi", "a.mid"])).unwrap();
+        assert_eq!(options.midi_output, Some("a.mid".to_string()));
+    }
+
+    #[test]
+    fn usage_errors_have_usage_exit_code() {
+        for bad in &[vec!("frobnicate"), vec!("analyze"), vec!("tune", "--reference"), vec!("tune", "--reference", "abc"), vec!("tune", "--reference", "inf"), vec!("tune", "--bogus")] {
+            match parse_args(&args(bad)) {
+                Err(e) => assert_eq!(e.exit_code(), EXIT_USAGE, "args={:?}", bad),
+                Ok(_) => panic!("Expected a usage error for {:?}", bad)
+            }
+        }
+    }
+
+    #[test]
+    fn missing_file_has_no_input_exit_code() {
+        let error = analyze("/this/file/does/not/exist.wav", &Options::default()).unwrap_err();
+        assert_eq!(error.exit_code(), EXIT_NO_INPUT);
+    }
+
+    #[test]
+    fn note_naming_options() {
+        assert_eq!(parse_args(&args(&["tune"])).unwrap().naming, NoteNaming::default());
+        let options = parse_args(&args(&["tune", "--names", "german", "--spelling", "key", "--key", "Eb", "--octaves", "helmholtz", "--ascii"])).unwrap();
+        assert_eq!(options.naming, NoteNaming {
+            system: NamingSystem::German,
+            spelling: Spelling::Key,
+            key: 3,
+            accidentals: Accidentals::Ascii,
+            octaves: OctaveNotation::Helmholtz
+        });
+        assert!(parse_args(&args(&["tune", "--key", "Q"])).is_err());
+        assert!(parse_args(&args(&["tune", "--octaves", "midi"])).is_err());
+    }
+
+    #[test]
+    fn scala_files_replace_the_temperament() {
+        let options = parse_args(&args(&["analyze", "a.wav", "--scl", "31-edo.scl", "--kbm", "a415.kbm"])).unwrap();
+        assert_eq!(options.scale, Some("31-edo.scl".to_string()));
+        assert_eq!(options.keyboard_mapping, Some("a415.kbm".to_string()));
+        assert_eq!(parse_args(&args(&["tune", "--kbm", "a415.kbm"])).unwrap_err().exit_code(), EXIT_USAGE);
+        assert_eq!(parse_args(&args(&["tune", "--scl", "a.scl", "--transpose", "bb"])).unwrap_err().exit_code(), EXIT_USAGE);
+
+        let options = Options {
+            scale: Some("/this/file/does/not/exist.scl".to_string()),
+            ..Options::default()
+        };
+        assert_eq!(analyze("a.wav", &options).unwrap_err().exit_code(), EXIT_NO_INPUT);
+        let corpus = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/scala/");
+        let options = Options {
+            scale: Some(format!("{}bad-ratio.scl", corpus)),
+            ..Options::default()
+        };
+        assert_eq!(analyze("a.wav", &options).unwrap_err().exit_code(), EXIT_DATA_ERROR);
+    }
+
+    #[test]
+    fn cents_bar_marks_error() {
+        assert_eq!(cents_bar(0.0, 11), "[-----#-----]");
+        assert_eq!(cents_bar(-50.0, 11), "[#----|-----]");
+        assert_eq!(cents_bar(25.0, 11), "[-----|--#--]");
+        assert_eq!(cents_bar(f32::NAN, 11), "[-----|-----]");
+    }
+}
@@ -0,0 +1,433 @@
+use std::env;
+use std::error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use model::AnalysisSettings;
+use smoothing::{Smoothing, SmoothingMethod, MAX_LATENCY};
+use pitch::{self, Transposition, NoteNaming};
+
+const APPLICATION_DIRECTORY: &str = "rusty_microphone";
+const FILE_NAME: &str = "settings.conf";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Chart {
+    Oscilloscope,
+    Correlation,
+    History,
+    Dynamics,
+    Spectrum,
+    Spectrogram
+}
+
+impl Chart {
+    pub fn all() -> Vec<Chart> {
+        vec!(Chart::Oscilloscope, Chart::Correlation, Chart::History, Chart::Dynamics, Chart::Spectrum, Chart::Spectrogram)
+    }
+
+    pub fn id(&self) -> &'static str {
+        match *self {
+            Chart::Oscilloscope => "oscilloscope",
+            Chart::Correlation => "correlation",
+            Chart::History => "history",
+            Chart::Dynamics => "dynamics",
+            Chart::Spectrum => "spectrum",
+            Chart::Spectrogram => "spectrogram"
+        }
+    }
+}
+
+impl FromStr for Chart {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Chart, String> {
+        Chart::all().into_iter()
+            .find(|c| c.id() == s.to_lowercase())
+            .ok_or_else(|| format!("Unknown chart: {}", s))
+    }
+}
+
+// A problem with the settings file. None of them are fatal: whatever
+// couldn't be read is left at its default.
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(io::Error),
+    InvalidLine { line: usize, message: String }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ConfigError::Io(ref e) => write!(f, "{}", e),
+            ConfigError::InvalidLine { line, ref message } => write!(f, "Line {}: {}", line, message)
+        }
+    }
+}
+
+impl error::Error for ConfigError {}
+
+impl From<io::Error> for ConfigError {
+    fn from(e: io::Error) -> ConfigError {
+        ConfigError::Io(e)
+    }
+}
+
+// Everything the GUI remembers between launches. It's saved as
+// "key = value" lines, so it can be edited by hand.
+#[derive(Debug, Clone)]
+pub struct Config {
+    // PortAudio's device indices change as devices come and go, so the
+    // name is kept instead
+    pub device: Option<String>,
+    pub settings: AnalysisSettings,
+    pub transposition: Transposition,
+    pub naming: NoteNaming,
+    pub visible_charts: Vec<Chart>,
+    pub window_size: Option<(i32, i32)>
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            device: None,
+            // The window shows every frame as it comes, so without
+            // smoothing an octave error flickers the note name. The
+            // CLI's pitch track stays raw unless asked.
+            settings: AnalysisSettings {
+                smoothing: Smoothing {
+                    method: SmoothingMethod::Median,
+                    ..Smoothing::default()
+                },
+                ..AnalysisSettings::default()
+            },
+            transposition: Transposition::default(),
+            naming: NoteNaming::default(),
+            // The correlation chart is only really useful for
+            // debugging, and the rest are for looking at tone or
+            // dynamics rather than tuning, so they're opt in.
+            visible_charts: vec!(Chart::Oscilloscope, Chart::History),
+            window_size: None
+        }
+    }
+}
+
+impl Config {
+    // Reads as much of the text as makes sense. Lines that don't are
+    // skipped and reported, rather than losing the whole file.
+    pub fn parse(text: &str) -> (Config, Vec<ConfigError>) {
+        let mut config = Config::default();
+        let mut errors = Vec::new();
+        for (index, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let result = match line.find('=') {
+                Some(equals) => config.set(line[..equals].trim(), line[equals + 1..].trim()),
+                None => Err(format!("Expected \"key = value\", found \"{}\"", line))
+            };
+            if let Err(message) = result {
+                errors.push(ConfigError::InvalidLine {
+                    line: index + 1,
+                    message: message
+                });
+            }
+        }
+        (config, errors)
+    }
+
+    fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        match key {
+            "device" => self.device = if value.is_empty() { None } else { Some(value.to_string()) },
+            "reference" => {
+                let a4_hz = parse_finite(key, value)?;
+                if !pitch::TuningReference::is_valid_a4_hz(a4_hz) {
+                    return Err(format!("reference must be a positive frequency, found {}", value));
+                }
+                self.settings.reference.a4_hz = a4_hz;
+            },
+            "temperament" => self.settings.reference.temperament = value.parse()?,
+            "tonic" => {
+                self.settings.reference.tonic = pitch::parse_note_name(value)
+                    .ok_or_else(|| format!("Unknown tonic: {}", value))?;
+            },
+            "transposition" => self.transposition = value.parse()?,
+            "note_names" => self.naming.system = value.parse()?,
+            "spelling" => self.naming.spelling = value.parse()?,
+            "key" => {
+                self.naming.key = pitch::parse_note_name(value)
+                    .ok_or_else(|| format!("Unknown key: {}", value))?;
+            },
+            "accidentals" => self.naming.accidentals = value.parse()?,
+            "octaves" => self.naming.octaves = value.parse()?,
+            "detector" => self.settings.detector = value.parse()?,
+            "instrument" => self.settings.instrument = value.parse()?,
+            "noise_gate" => self.settings.noise_gate_db = parse_finite(key, value)?,
+            "smoothing" => self.settings.smoothing.method = value.parse()?,
+            "latency" => {
+                let latency = parse_value(key, value)?;
+                if latency > MAX_LATENCY {
+                    return Err(format!("latency can be at most {} frames, found {}", MAX_LATENCY, value));
+                }
+                self.settings.smoothing.latency = latency;
+            },
+            "charts" => {
+                self.visible_charts = value.split(',')
+                    .map(|id| id.trim())
+                    .filter(|id| !id.is_empty())
+                    .map(|id| id.parse())
+                    .collect::<Result<Vec<Chart>, String>>()?;
+            },
+            "window" => {
+                let mut parts = value.splitn(2, 'x');
+                let width = parts.next().and_then(|w| w.trim().parse().ok());
+                let height = parts.next().and_then(|h| h.trim().parse().ok());
+                self.window_size = match (width, height) {
+                    (Some(width), Some(height)) if width > 0 && height > 0 => Some((width, height)),
+                    _ => return Err(format!("window should be WIDTHxHEIGHT, found {}", value))
+                };
+            },
+            _ => return Err(format!("Unknown setting: {}", key))
+        }
+        Ok(())
+    }
+
+    // A missing file isn't an error, it just hasn't been saved yet.
+    pub fn load(path: &Path) -> (Config, Vec<ConfigError>) {
+        match fs::read_to_string(path) {
+            Ok(text) => Config::parse(&text),
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => (Config::default(), Vec::new()),
+            Err(e) => (Config::default(), vec!(ConfigError::Io(e)))
+        }
+    }
+
+    // Writes to a temporary file first and moves it into place, so a
+    // crash part way through can't leave a half written file.
+    pub fn save(&self, path: &Path) -> Result<(), ConfigError> {
+        if let Some(directory) = path.parent() {
+            fs::create_dir_all(directory)?;
+        }
+        let temporary = path.with_extension("tmp");
+        fs::write(&temporary, self.to_string())?;
+        fs::rename(&temporary, path)?;
+        Ok(())
+    }
+
+    pub fn is_visible(&self, chart: Chart) -> bool {
+        self.visible_charts.contains(&chart)
+    }
+}
+
+impl fmt::Display for Config {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "# Rusty Microphone settings, saved when the window closes")?;
+        if let Some(ref device) = self.device {
+            writeln!(f, "device = {}", device)?;
+        }
+        writeln!(f, "reference = {}", self.settings.reference.a4_hz)?;
+        writeln!(f, "temperament = {}", self.settings.reference.temperament.id())?;
+        // Both are public, so they may not have been reduced to an octave.
+        writeln!(f, "tonic = {}", pitch::NOTE_NAMES[self.settings.reference.tonic.rem_euclid(12) as usize])?;
+        writeln!(f, "transposition = {}", self.transposition.id())?;
+        writeln!(f, "note_names = {}", self.naming.system.id())?;
+        writeln!(f, "spelling = {}", self.naming.spelling.id())?;
+        writeln!(f, "key = {}", pitch::NOTE_NAMES[self.naming.key.rem_euclid(12) as usize])?;
+        writeln!(f, "accidentals = {}", self.naming.accidentals.id())?;
+        writeln!(f, "octaves = {}", self.naming.octaves.id())?;
+        writeln!(f, "detector = {}", self.settings.detector.id())?;
+        writeln!(f, "instrument = {}", self.settings.instrument.id())?;
+        writeln!(f, "noise_gate = {}", self.settings.noise_gate_db)?;
+        writeln!(f, "smoothing = {}", self.settings.smoothing.method.id())?;
+        writeln!(f, "latency = {}", self.settings.smoothing.latency)?;
+        let charts: Vec<&str> = self.visible_charts.iter().map(|c| c.id()).collect();
+        writeln!(f, "charts = {}", charts.join(", "))?;
+        if let Some((width, height)) = self.window_size {
+            writeln!(f, "window = {}x{}", width, height)?;
+        }
+        Ok(())
+    }
+}
+
+fn parse_value<T: FromStr>(key: &str, value: &str) -> Result<T, String> {
+    value.parse().map_err(|_| format!("Invalid value for {}: {}", key, value))
+}
+
+// "nan" and "inf" parse as floats, but no setting means either.
+fn parse_finite(key: &str, value: &str) -> Result<f32, String> {
+    let number: f32 = parse_value(key, value)?;
+    if number.is_finite() {
+        Ok(number)
+    }
+    else {
+        Err(format!("Invalid value for {}: {}", key, value))
+    }
+}
+
+// $XDG_CONFIG_HOME/rusty_microphone/settings.conf, falling back to
+// ~/.config as the XDG base directory spec says. Relative paths in
+// XDG_CONFIG_HOME are to be ignored.
+pub fn default_path() -> Option<PathBuf> {
+    let config_home = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .filter(|path| path.is_absolute())
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(config_home.join(APPLICATION_DIRECTORY).join(FILE_NAME))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use detector::Detector;
+    use instrument::Instrument;
+    use pitch::{Temperament, TuningReference, NamingSystem, Spelling, Accidentals, OctaveNotation};
+
+    fn temporary_path(name: &str) -> PathBuf {
+        env::temp_dir().join(format!("rusty_microphone_config_test_{}", name)).join(FILE_NAME)
+    }
+
+    fn example() -> Config {
+        Config {
+            device: Some("USB Audio: Mic = 1".to_string()),
+            settings: AnalysisSettings {
+                reference: TuningReference::new(442.5).with_temperament(Temperament::Werckmeister, 3),
+                detector: Detector::Mcleod,
+                instrument: Instrument::Tuba,
+                noise_gate_db: -40.0,
+                smoothing: Smoothing {
+                    method: SmoothingMethod::Viterbi,
+                    latency: 5
+                }
+            },
+            transposition: Transposition::EFlat,
+            naming: NoteNaming {
+                system: NamingSystem::Solfege,
+                spelling: Spelling::Key,
+                key: 10,
+                accidentals: Accidentals::Ascii,
+                octaves: OctaveNotation::Helmholtz
+            },
+            visible_charts: vec!(Chart::Spectrum, Chart::Dynamics),
+            window_size: Some((1024, 768))
+        }
+    }
+
+    fn assert_same(a: &Config, b: &Config) {
+        assert_eq!(a.device, b.device);
+        assert_eq!(a.settings.reference, b.settings.reference);
+        assert_eq!(a.settings.detector, b.settings.detector);
+        assert_eq!(a.settings.instrument, b.settings.instrument);
+        assert_eq!(a.settings.noise_gate_db, b.settings.noise_gate_db);
+        assert_eq!(a.settings.smoothing, b.settings.smoothing);
+        assert_eq!(a.transposition, b.transposition);
+        assert_eq!(a.naming, b.naming);
+        assert_eq!(a.visible_charts, b.visible_charts);
+        assert_eq!(a.window_size, b.window_size);
+    }
+
+    #[test]
+    fn config_survives_a_round_trip() {
+        let config = example();
+        let (parsed, errors) = Config::parse(&config.to_string());
+        assert!(errors.is_empty(), "{:?}", errors);
+        assert_same(&parsed, &config);
+    }
+
+    #[test]
+    fn config_saves_and_loads() {
+        let path = temporary_path("round_trip");
+        let config = example();
+        config.save(&path).unwrap();
+        let (loaded, errors) = Config::load(&path);
+        assert!(errors.is_empty(), "{:?}", errors);
+        assert_same(&loaded, &config);
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn missing_file_is_the_defaults() {
+        let (config, errors) = Config::load(&temporary_path("missing"));
+        assert!(errors.is_empty());
+        assert_same(&config, &Config::default());
+    }
+
+    #[test]
+    fn bad_lines_are_skipped_and_reported() {
+        let text = "reference = 442\n\
+                    detector = fft\n\
+                    this isn't a setting\n\
+                    \n\
+                    # comments are fine\n\
+                    window = wide\n\
+                    transposition = bb\n";
+        let (config, errors) = Config::parse(text);
+        assert_eq!(config.settings.reference, TuningReference::new(442.0));
+        assert_eq!(config.transposition, Transposition::BFlat);
+        assert_eq!(config.settings.detector, Detector::default());
+        assert_eq!(config.window_size, None);
+        let lines: Vec<usize> = errors.iter()
+            .map(|e| match *e {
+                ConfigError::InvalidLine { line, .. } => line,
+                ConfigError::Io(_) => 0
+            })
+            .collect();
+        assert_eq!(lines, vec!(2, 3, 6));
+    }
+
+    #[test]
+    fn binary_garbage_is_the_defaults() {
+        let path = temporary_path("garbage");
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, [0xff, 0xfe, 0x00, 0x80, 0x3d]).unwrap();
+        let (config, errors) = Config::load(&path);
+        assert_same(&config, &Config::default());
+        assert_eq!(errors.len(), 1);
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn nonsense_values_are_rejected() {
+        for line in &["reference = -440", "reference = nan", "reference = inf", "noise_gate = nan", "noise_gate = -inf", "latency = -1", "charts = oscilloscope, radar", "window = 0x600"] {
+            let (_, errors) = Config::parse(line);
+            assert_eq!(errors.len(), 1, "{}", line);
+        }
+    }
+
+    #[test]
+    fn notes_outside_an_octave_are_saved_within_it() {
+        let mut config = Config::default();
+        config.settings.reference.tonic = -2;
+        config.naming.key = 15;
+        let (parsed, errors) = Config::parse(&config.to_string());
+        assert!(errors.is_empty(), "{:?}", errors);
+        assert_eq!(parsed.settings.reference.tonic, 10);
+        assert_eq!(parsed.naming.key, 3);
+    }
+
+    #[test]
+    fn the_window_smooths_by_default() {
+        assert_eq!(Config::default().settings.smoothing.method, SmoothingMethod::Median);
+        let (config, _) = Config::parse("smoothing = off");
+        assert_eq!(config.settings.smoothing.method, SmoothingMethod::Off);
+    }
+
+    #[test]
+    fn overlong_latencies_keep_the_default() {
+        for line in &["latency = 51", "latency = 18446744073709551615"] {
+            let (config, errors) = Config::parse(line);
+            assert_eq!(errors.len(), 1, "{}", line);
+            assert_eq!(config.settings.smoothing.latency, Config::default().settings.smoothing.latency);
+        }
+        let (config, _) = Config::parse("latency = 50");
+        assert_eq!(config.settings.smoothing.latency, 50);
+    }
+
+    #[test]
+    fn no_charts_is_allowed() {
+        let (config, errors) = Config::parse("charts =");
+        assert!(errors.is_empty());
+        assert!(config.visible_charts.is_empty());
+    }
+}
@@ -1,5 +1,8 @@
-use signal::Signal;
-use pitch::Pitch;
+use signal::{Signal, MIN_DBFS};
+use detector::{FrequencyRange, PitchEstimate, Rejection};
+use fft::{Complex, fft, inverse_fft};
+
+use std::iter;
 
 #[derive(Debug, Default, Clone)]
 pub struct Correlation {
@@ -7,7 +10,37 @@ pub struct Correlation {
 }
 
 impl Correlation {
+    // Wiener-Khinchin: the autocorrelation is the inverse transform of
+    // the power spectrum. The signal is zero padded to at least double
+    // its length so that the result isn't circular.
     pub fn from_signal(signal: &Signal) -> Correlation {
+        let samples = &signal.samples;
+        if samples.is_empty() {
+            return Correlation::default();
+        }
+
+        let fft_len = (2 * samples.len()).next_power_of_two();
+        let mut buffer: Vec<Complex> = samples.iter()
+            .map(|&x| Complex::new(x, 0.0))
+            .chain(iter::repeat(Complex::default()))
+            .take(fft_len)
+            .collect();
+
+        fft(&mut buffer);
+        for c in buffer.iter_mut() {
+            *c = Complex::new(c.norm_sqr(), 0.0);
+        }
+        inverse_fft(&mut buffer);
+
+        Correlation {
+            value: buffer.iter().take(samples.len()).map(|c| c.re).collect()
+        }
+    }
+
+    // The same result as from_signal, calculated directly. This is
+    // O(n^2), and kept around as a reference for testing and
+    // benchmarking.
+    pub fn from_signal_direct(signal: &Signal) -> Correlation {
         let samples = &signal.samples;
         Correlation {
             value: (0..samples.len()).map(|offset| {
@@ -19,37 +52,70 @@ impl Correlation {
         }
     }
 
-    pub fn find_fundamental_frequency(&self, signal: &Signal) -> Option<Pitch> {
-        if signal.is_silence() {
-            // silence
-            return None;
+    pub fn find_fundamental_frequency(&self, signal: &Signal) -> PitchEstimate {
+        self.find_fundamental_frequency_in(signal, FrequencyRange::default())
+    }
+
+    pub fn find_fundamental_frequency_in(&self, signal: &Signal, range: FrequencyRange) -> PitchEstimate {
+        // Quiet signals are gated in Model before they get here, so
+        // this only has to stop silence being divided by itself.
+        if signal.is_below(MIN_DBFS) {
+            return PitchEstimate::rejected(Rejection::Silence);
         }
 
         let first_peak_end = match self.value.iter().position(|&c| c < 0.0) {
             Some(p) => p,
             None => {
                 // musical signals will drop below 0 at some point
-                return None
+                return PitchEstimate::rejected(Rejection::NoZeroCrossing)
             }
         };
-        
+
+        let (shortest, longest) = range.lags(signal.sample_rate, self.value.len() - 1);
+        let start = first_peak_end.max(shortest);
         let peak = self.value.iter()
             .enumerate()
-            .skip(first_peak_end)
-            .fold((first_peak_end, 0.0), |(xi, xmag), (yi, &ymag)| if ymag > xmag { (yi, ymag) } else { (xi, xmag) });
+            .take(longest + 1)
+            .skip(start)
+            .fold((start, 0.0), |(xi, xmag), (yi, &ymag)| if ymag > xmag { (yi, ymag) } else { (xi, xmag) });
 
-        let (peak_index, _) = peak;
+        let (peak_index, peak_value) = peak;
+        // Still climbing at the longest period allowed means the real
+        // peak is a lower note than the range allows.
+        if peak_index == longest && longest + 1 < self.value.len() {
+            return PitchEstimate::rejected(Rejection::OutOfRange);
+        }
+        if peak_value <= 0.0 {
+            return PitchEstimate::rejected(Rejection::Noise);
+        }
 
         let refined_peak_index = self.refine_fundamentals(peak_index as f32 - 0.5, peak_index as f32 + 0.5);
 
+        let hz = signal.sample_rate / refined_peak_index;
+        let clarity = self.clarity(refined_peak_index);
         if self.is_noise(refined_peak_index) {
-            None
+            PitchEstimate::noise(hz, clarity)
         }
         else {
-            Some(Pitch::new(signal.sample_rate / refined_peak_index))
+            PitchEstimate::new(hz, clarity)
         }
     }
 
+    // The correlation at the period, relative to what a perfectly
+    // periodic signal would have there. The correlation at lag i only
+    // overlaps len-i samples, so it is scaled up to compensate.
+    fn clarity(&self, period: f32) -> f32 {
+        let energy = match self.value.first() {
+            Some(&e) if e > 0.0 => e,
+            _ => return 0.0
+        };
+        let overlap = 1.0 - period / self.value.len() as f32;
+        if overlap <= 0.0 {
+            return 0.0;
+        }
+        (self.interpolate(period) / (energy * overlap)).clamp(0.0, 1.0)
+    }
+
     fn refine_fundamentals(&self, low_bound: f32, high_bound: f32) -> f32 {
         let data_points = 2 * self.value.len() / high_bound.ceil() as usize;
         let range = high_bound - low_bound;
@@ -144,10 +210,46 @@ mod tests {
         let frequency = 440.0f32; //concert A
         
         let signal = sample_sinusoid(1.0, frequency);
-        let fundamental = Correlation::from_signal(&signal).find_fundamental_frequency(&signal).expect("Find fundamental returned None");
+        let fundamental = Correlation::from_signal(&signal).find_fundamental_frequency(&signal).pitch().expect("Find fundamental returned None");
         assert!((fundamental.hz-frequency).abs() < frequency_resolution(), "expected={}, actual={}", frequency, fundamental);
     }
 
+    #[test]
+    fn sine_wave_is_clear() {
+        let signal = sample_sinusoid(1.0, 440.0);
+        let estimate = Correlation::from_signal(&signal).find_fundamental_frequency(&signal);
+        assert!(estimate.clarity > 0.9, "clarity={}", estimate.clarity);
+    }
+
+    #[test]
+    fn silent_signal_is_rejected_as_silence() {
+        let signal = sample_sinusoid(0.0, 440.0);
+        let estimate = Correlation::from_signal(&signal).find_fundamental_frequency(&signal);
+        assert_eq!(estimate.rejection, Some(Rejection::Silence));
+        assert!(estimate.pitch().is_none());
+    }
+
+    #[test]
+    fn fft_correlation_matches_direct_calculation() {
+        let signal = Signal::new(
+            &(0..FRAMES).map(|x| (f32::from(x) * 0.37).sin() + 0.3 * (f32::from(x) * 1.9).cos()).collect::<Vec<_>>(),
+            SAMPLE_RATE
+        );
+        let fft = Correlation::from_signal(&signal);
+        let direct = Correlation::from_signal_direct(&signal);
+
+        assert_eq!(fft.value.len(), direct.value.len());
+        let tolerance = direct.value[0] * 1e-4;
+        for (i, (a, b)) in fft.value.iter().zip(direct.value.iter()).enumerate() {
+            assert!((a - b).abs() < tolerance, "lag={}, fft={}, direct={}", i, a, b);
+        }
+    }
+
+    #[test]
+    fn correlation_of_empty_signal_is_empty() {
+        assert!(Correlation::from_signal(&Signal::empty()).value.is_empty());
+    }
+
     #[test]
     fn interpolate_half_way() {
         let corr = Correlation {
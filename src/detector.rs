@@ -0,0 +1,343 @@
+use std::fmt;
+use std::f32;
+use std::str::FromStr;
+
+use signal::Signal;
+use correlation::Correlation;
+use pitch::Pitch;
+use yin::Yin;
+use mcleod::Mcleod;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rejection {
+    Silence,
+    NoZeroCrossing,
+    Noise,
+    OutOfRange
+}
+
+impl fmt::Display for Rejection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let reason = match *self {
+            Rejection::Silence => "silence",
+            Rejection::NoZeroCrossing => "no zero crossing",
+            Rejection::Noise => "noise",
+            Rejection::OutOfRange => "out of range"
+        };
+        write!(f, "{}", reason)
+    }
+}
+
+// What a detector found in a signal. Clarity runs from 0 (no
+// periodicity at all) to 1 (perfectly periodic). Rejected estimates
+// still carry the frequency and clarity of the best guess where
+// there was one, so that a UI can show uncertain readings faded out
+// rather than not at all.
+#[derive(Debug, Clone, Copy)]
+pub struct PitchEstimate {
+    pub hz: f32,
+    pub clarity: f32,
+    pub rejection: Option<Rejection>
+}
+
+impl PitchEstimate {
+    pub fn new(hz: f32, clarity: f32) -> PitchEstimate {
+        PitchEstimate {
+            hz: hz,
+            clarity: clarity,
+            rejection: None
+        }
+    }
+
+    pub fn rejected(rejection: Rejection) -> PitchEstimate {
+        PitchEstimate {
+            hz: f32::NAN,
+            clarity: 0.0,
+            rejection: Some(rejection)
+        }
+    }
+
+    pub fn noise(hz: f32, clarity: f32) -> PitchEstimate {
+        PitchEstimate {
+            hz: hz,
+            clarity: clarity,
+            rejection: Some(Rejection::Noise)
+        }
+    }
+
+    // Rejects an otherwise accepted estimate that falls outside range.
+    pub fn within(self, range: FrequencyRange) -> PitchEstimate {
+        if self.is_accepted() && !range.contains(self.hz) {
+            PitchEstimate {
+                rejection: Some(Rejection::OutOfRange),
+                ..self
+            }
+        }
+        else {
+            self
+        }
+    }
+
+    pub fn is_accepted(&self) -> bool {
+        self.rejection.is_none()
+    }
+
+    pub fn pitch(&self) -> Option<Pitch> {
+        if self.is_accepted() {
+            Some(Pitch::new(self.hz))
+        }
+        else {
+            None
+        }
+    }
+}
+
+impl Default for PitchEstimate {
+    fn default() -> PitchEstimate {
+        PitchEstimate::rejected(Rejection::Silence)
+    }
+}
+
+// The fundamentals a detector should consider. Limiting the search
+// to what an instrument can actually play stops a strong harmonic, or
+// a period of two cycles, being taken as the fundamental.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FrequencyRange {
+    pub min_hz: f32,
+    pub max_hz: f32
+}
+
+impl FrequencyRange {
+    pub fn new(min_hz: f32, max_hz: f32) -> FrequencyRange {
+        FrequencyRange {
+            min_hz: min_hz,
+            max_hz: max_hz
+        }
+    }
+
+    pub fn contains(&self, hz: f32) -> bool {
+        hz >= self.min_hz && hz <= self.max_hz
+    }
+
+    // The shortest and longest periods in the range, in samples, with
+    // the longest no more than max_lag.
+    pub fn lags(&self, sample_rate: f32, max_lag: usize) -> (usize, usize) {
+        let shortest = (sample_rate / self.max_hz).floor().max(0.0);
+        let longest = (sample_rate / self.min_hz).ceil().min(max_lag as f32);
+        ((shortest as usize).min(max_lag), longest as usize)
+    }
+}
+
+impl Default for FrequencyRange {
+    fn default() -> FrequencyRange {
+        FrequencyRange::new(0.0, f32::INFINITY)
+    }
+}
+
+pub trait PitchDetector {
+    // Only periods within range are searched, so the result can still
+    // fall slightly outside it after interpolation.
+    fn find_fundamental_frequency_in(&self, signal: &Signal, range: FrequencyRange) -> PitchEstimate;
+
+    fn find_fundamental_frequency(&self, signal: &Signal) -> PitchEstimate {
+        self.find_fundamental_frequency_in(signal, FrequencyRange::default())
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Autocorrelation;
+
+impl PitchDetector for Autocorrelation {
+    fn find_fundamental_frequency_in(&self, signal: &Signal, range: FrequencyRange) -> PitchEstimate {
+        Correlation::from_signal(signal).find_fundamental_frequency_in(signal, range)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Detector {
+    #[default]
+    Autocorrelation,
+    Yin,
+    Mcleod
+}
+
+impl Detector {
+    pub fn all() -> Vec<Detector> {
+        vec!(Detector::Autocorrelation, Detector::Yin, Detector::Mcleod)
+    }
+
+    pub fn id(&self) -> &'static str {
+        match *self {
+            Detector::Autocorrelation => "autocorrelation",
+            Detector::Yin => "yin",
+            Detector::Mcleod => "mpm"
+        }
+    }
+
+    // The same as find_fundamental_frequency_in, but reusing a
+    // correlation that the caller has already calculated for the
+    // signal rather than calculating it again.
+    pub fn find_fundamental_frequency_with(&self, signal: &Signal, correlation: &Correlation, range: FrequencyRange) -> PitchEstimate {
+        match *self {
+            Detector::Autocorrelation => correlation.find_fundamental_frequency_in(signal, range).within(range),
+            _ => self.find_fundamental_frequency_in(signal, range)
+        }
+    }
+}
+
+impl PitchDetector for Detector {
+    fn find_fundamental_frequency_in(&self, signal: &Signal, range: FrequencyRange) -> PitchEstimate {
+        let estimate = match *self {
+            Detector::Autocorrelation => Autocorrelation.find_fundamental_frequency_in(signal, range),
+            Detector::Yin => Yin::default().find_fundamental_frequency_in(signal, range),
+            Detector::Mcleod => Mcleod::default().find_fundamental_frequency_in(signal, range)
+        };
+        estimate.within(range)
+    }
+}
+
+impl fmt::Display for Detector {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match *self {
+            Detector::Autocorrelation => "Autocorrelation",
+            Detector::Yin => "YIN",
+            Detector::Mcleod => "McLeod (MPM)"
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for Detector {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Detector, String> {
+        Detector::all().into_iter()
+            .find(|d| d.id() == s.to_lowercase())
+            .ok_or_else(|| format!("Unknown pitch detector: {}", s))
+    }
+}
+
+// Fits a parabola through the point at index and its two neighbours,
+// and returns the (fractional) index of the parabola's turning point.
+pub(crate) fn parabolic_interpolation(values: &[f32], index: usize) -> f32 {
+    if index == 0 || index + 1 >= values.len() {
+        return index as f32;
+    }
+
+    let (y0, y1, y2) = (values[index - 1], values[index], values[index + 1]);
+    let denominator = y0 - 2.0 * y1 + y2;
+    if denominator == 0.0 {
+        index as f32
+    }
+    else {
+        index as f32 + 0.5 * (y0 - y2) / denominator
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_signals::{self, SAMPLE_RATE};
+
+    const FRAMES: usize = 2048;
+
+    fn sample_harmonics(frequency: f32, harmonics: &[f32]) -> Signal {
+        test_signals::sample_harmonics(frequency, harmonics, FRAMES)
+    }
+
+    fn assert_detects(detector: Detector, signal: &Signal, expected: f32) {
+        let actual = detector.find_fundamental_frequency(signal)
+            .pitch()
+            .unwrap_or_else(|| panic!("{} found no pitch, expected {}", detector, expected));
+        let tolerance = expected * 0.01;
+        assert!((actual.hz - expected).abs() < tolerance, "detector={}, expected={}, actual={}", detector, expected, actual.hz);
+    }
+
+    #[test]
+    fn every_detector_finds_sine_waves() {
+        for &frequency in &[220.0, 440.0, 880.0] {
+            let signal = sample_harmonics(frequency, &[1.0]);
+            for detector in Detector::all() {
+                assert_detects(detector, &signal, frequency);
+            }
+        }
+    }
+
+    #[test]
+    fn every_detector_finds_harmonically_rich_tones() {
+        // Roughly brass-like: the second and third harmonics are
+        // stronger than the fundamental.
+        let frequency = 233.08;
+        let signal = sample_harmonics(frequency, &[0.4, 0.6, 0.5, 0.3, 0.2]);
+        for detector in Detector::all() {
+            assert_detects(detector, &signal, frequency);
+        }
+    }
+
+    #[test]
+    fn every_detector_rejects_silence() {
+        let signal = Signal::new(&[0.0; FRAMES], SAMPLE_RATE);
+        for detector in Detector::all() {
+            let estimate = detector.find_fundamental_frequency(&signal);
+            assert_eq!(estimate.rejection, Some(Rejection::Silence), "detector={}", detector);
+            assert!(estimate.pitch().is_none(), "detector={}", detector);
+        }
+    }
+
+    #[test]
+    fn every_detector_reports_clarity_in_range() {
+        let clean = sample_harmonics(440.0, &[1.0]);
+        // A cheap linear congruential generator, so the test is
+        // repeatable without pulling in a random number crate.
+        let mut seed = 12345u32;
+        let noise: Vec<f32> = (0..FRAMES).map(|_| {
+            seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+            (seed >> 16) as f32 / 32768.0 - 1.0
+        }).collect();
+        let noise = Signal::new(&noise, SAMPLE_RATE);
+
+        for detector in Detector::all() {
+            let clean_estimate = detector.find_fundamental_frequency(&clean);
+            let noise_estimate = detector.find_fundamental_frequency(&noise);
+            assert!(clean_estimate.clarity > 0.9, "detector={}, clarity={}", detector, clean_estimate.clarity);
+            assert!(noise_estimate.clarity >= 0.0 && noise_estimate.clarity < clean_estimate.clarity,
+                    "detector={}, clarity={}", detector, noise_estimate.clarity);
+        }
+    }
+
+    #[test]
+    fn mcleod_rejects_nothing_periodic_in_range_as_noise() {
+        let signal = sample_harmonics(440.0, &[1.0]);
+        let estimate = Detector::Mcleod.find_fundamental_frequency_in(&signal, FrequencyRange::new(2000.0, 4000.0));
+        assert_eq!(estimate.rejection, Some(Rejection::Noise));
+    }
+
+    #[test]
+    fn given_correlation_gives_the_same_estimate() {
+        let signal = sample_harmonics(233.08, &[0.4, 0.6, 0.5, 0.3, 0.2]);
+        let correlation = Correlation::from_signal(&signal);
+        let range = FrequencyRange::new(100.0, 1000.0);
+        for detector in Detector::all() {
+            let estimate = detector.find_fundamental_frequency_in(&signal, range);
+            let reused = detector.find_fundamental_frequency_with(&signal, &correlation, range);
+            assert_eq!(estimate.hz, reused.hz, "detector={}", detector);
+            assert_eq!(estimate.rejection, reused.rejection, "detector={}", detector);
+        }
+    }
+
+    #[test]
+    fn detectors_parse_from_their_ids() {
+        for detector in Detector::all() {
+            assert_eq!(detector.id().parse::<Detector>(), Ok(detector));
+        }
+        assert!("fft".parse::<Detector>().is_err());
+    }
+
+    #[test]
+    fn parabolic_interpolation_finds_vertex() {
+        // y = (x - 2.25)^2
+        let values: Vec<f32> = (0..5).map(|x| (x as f32 - 2.25).powi(2)).collect();
+        assert!((parabolic_interpolation(&values, 2) - 2.25).abs() < 0.0001);
+    }
+}
@@ -0,0 +1,429 @@
+use std::collections::VecDeque;
+use std::fmt;
+
+use model::Model;
+use notes::{NoteEvent, NoteTracker};
+use pitch::Pitch;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DynamicsPoint {
+    pub time: f32,
+    // RMS level in dBFS
+    pub level_db: f32,
+    pub cents: Option<f32>
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Crescendo,
+    Diminuendo
+}
+
+impl fmt::Display for Direction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Direction::Crescendo => write!(f, "Crescendo"),
+            Direction::Diminuendo => write!(f, "Diminuendo")
+        }
+    }
+}
+
+// One steady change in loudness during a held note.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Swell {
+    pub direction: Direction,
+    pub start: f32,
+    pub end: f32,
+    pub level_change_db: f32,
+    // cents error at the end of the swell minus at the start
+    pub drift_cents: f32,
+    // the furthest the pitch strayed from where it started
+    pub max_drift_cents: f32,
+    pub flagged: bool
+}
+
+impl fmt::Display for Swell {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {:+.0} dB over {:.1}s, pitch moved {:+.0} cents",
+               self.direction, self.level_change_db, self.end - self.start, self.drift_cents)?;
+        if self.flagged {
+            write!(f, " - went {}", if self.max_drift_cents >= 0.0 { "sharp" } else { "flat" })?;
+        }
+        Ok(())
+    }
+}
+
+// Follows loudness and cents error through held notes, and splits each
+// note into crescendos and diminuendos. A change of direction only
+// counts once the level has come back min_change_db from its peak or
+// trough, so the wobble of a steady note isn't a swell. Notes come from
+// a NoteTracker, so vibrato and brief dropouts don't end them.
+#[derive(Debug, Clone)]
+pub struct DynamicsTracker {
+    pub min_change_db: f32,
+    pub drift_threshold_cents: f32,
+    // swells shorter than this are accents, not swells
+    pub min_duration: f32,
+    // the start of a note is skipped, so the attack isn't counted as a
+    // crescendo and any scoop isn't counted as drift
+    pub settle_time: f32,
+    // seconds of points and swells to keep for drawing
+    pub duration: f32,
+    pub tracker: NoteTracker,
+    points: VecDeque<DynamicsPoint>,
+    swells: VecDeque<Swell>,
+    note: Option<NoteDynamics>
+}
+
+#[derive(Debug, Clone)]
+struct NoteDynamics {
+    note: i32,
+    start: f32,
+    points: Vec<DynamicsPoint>,
+    direction: Option<Direction>,
+    // index of the point the current swell started from
+    phase_start: usize,
+    // index of the loudest point since the phase started if getting
+    // louder, the quietest if getting quieter. Before a direction is
+    // known, both are tracked.
+    loudest: usize,
+    quietest: usize
+}
+
+impl DynamicsTracker {
+    pub fn new() -> DynamicsTracker {
+        DynamicsTracker::default()
+    }
+
+    pub fn update(&mut self, time: f32, model: &Model) -> Vec<Swell> {
+        self.push(time, model.signal.rms_dbfs(), model.pitch)
+    }
+
+    // Returns any swells that finished with this point.
+    pub fn push(&mut self, time: f32, level_db: f32, pitch: Option<Pitch>) -> Vec<Swell> {
+        let mut finished = Vec::new();
+        for event in self.tracker.update_pitch(time, pitch) {
+            match event {
+                NoteEvent::NoteOff(_) => finished.extend(self.finish_note()),
+                NoteEvent::NoteOn { start, note } => {
+                    finished.extend(self.finish_note());
+                    self.note = Some(NoteDynamics {
+                        note: note,
+                        start: start,
+                        points: Vec::new(),
+                        direction: None,
+                        phase_start: 0,
+                        loudest: 0,
+                        quietest: 0
+                    });
+                }
+            }
+        }
+
+        // Measured from the held note rather than the nearest one, so a
+        // note bent more than 50 cents doesn't wrap round to the other
+        // side.
+        let held_note = self.note.as_ref().map(|n| n.note);
+        let held_cents = match (held_note, pitch) {
+            (Some(note), Some(pitch)) => Some((pitch.midi_number() - note as f32) * 100.0)
+                .filter(|c| c.abs() < self.tracker.hold_semitones * 100.0),
+            _ => None
+        };
+        let cents = held_cents
+            .or_else(|| pitch.map(|p| p.cents_error()))
+            .filter(|c| c.is_finite());
+        let point = DynamicsPoint {
+            time: time,
+            level_db: level_db,
+            cents: cents
+        };
+        self.points.push_back(point);
+        let oldest_allowed = time - self.duration;
+        while self.points.front().is_some_and(|p| p.time < oldest_allowed) {
+            self.points.pop_front();
+        }
+        while self.swells.front().is_some_and(|s| s.end < oldest_allowed) {
+            self.swells.pop_front();
+        }
+
+        // Dropouts and the first frames of the next note aren't part of
+        // this note's dynamics.
+        let settled = self.note.as_ref().is_some_and(|n| time - n.start >= self.settle_time);
+        if settled && held_cents.is_some() {
+            finished.extend(self.follow(point));
+        }
+        self.swells.extend(finished.iter().cloned());
+        finished
+    }
+
+    fn follow(&mut self, point: DynamicsPoint) -> Option<Swell> {
+        let min_change_db = self.min_change_db;
+        let (phase_start, phase_end, direction) = {
+            let note = self.note.as_mut()?;
+            note.points.push(point);
+            let latest = note.points.len() - 1;
+            let level = point.level_db;
+            if level > note.points[note.loudest].level_db {
+                note.loudest = latest;
+            }
+            if level < note.points[note.quietest].level_db {
+                note.quietest = latest;
+            }
+            let above_quietest = level - note.points[note.quietest].level_db;
+            let below_loudest = note.points[note.loudest].level_db - level;
+
+            match note.direction {
+                None => {
+                    if above_quietest >= min_change_db {
+                        note.direction = Some(Direction::Crescendo);
+                        note.phase_start = note.quietest;
+                    }
+                    else if below_loudest >= min_change_db {
+                        note.direction = Some(Direction::Diminuendo);
+                        note.phase_start = note.loudest;
+                    }
+                    return None;
+                },
+                Some(Direction::Crescendo) if below_loudest >= min_change_db => {
+                    let ended = (note.phase_start, note.loudest, Direction::Crescendo);
+                    note.direction = Some(Direction::Diminuendo);
+                    note.phase_start = note.loudest;
+                    note.quietest = latest;
+                    ended
+                },
+                Some(Direction::Diminuendo) if above_quietest >= min_change_db => {
+                    let ended = (note.phase_start, note.quietest, Direction::Diminuendo);
+                    note.direction = Some(Direction::Crescendo);
+                    note.phase_start = note.quietest;
+                    note.loudest = latest;
+                    ended
+                },
+                Some(_) => return None
+            }
+        };
+        self.swell(phase_start, phase_end, direction)
+    }
+
+    fn swell(&self, start: usize, end: usize, direction: Direction) -> Option<Swell> {
+        let points = match self.note.as_ref() {
+            Some(note) => &note.points[start..end + 1],
+            None => return None
+        };
+        let (first, last) = (points[0], points[points.len() - 1]);
+        if last.time - first.time < self.min_duration {
+            return None;
+        }
+
+        // Average a little either end, so one noisy reading doesn't
+        // decide the drift.
+        let edge = (self.min_duration / 4.0).max(0.0);
+        let start_cents = mean_cents(points.iter().filter(|p| p.time <= first.time + edge));
+        let end_cents = mean_cents(points.iter().filter(|p| p.time >= last.time - edge));
+        let (start_cents, end_cents) = match (start_cents, end_cents) {
+            (Some(start), Some(end)) => (start, end),
+            _ => return None
+        };
+        let max_drift = points.iter()
+            .filter_map(|p| p.cents)
+            .map(|c| c - start_cents)
+            .fold(0.0f32, |max, drift| if drift.abs() > max.abs() { drift } else { max });
+
+        Some(Swell {
+            direction: direction,
+            start: first.time,
+            end: last.time,
+            level_change_db: last.level_db - first.level_db,
+            drift_cents: end_cents - start_cents,
+            max_drift_cents: max_drift,
+            flagged: max_drift.abs() > self.drift_threshold_cents
+        })
+    }
+
+    // How far the pitch has moved since the swell in progress started,
+    // if there is one and it's moved further than the threshold.
+    pub fn current_drift(&self) -> Option<f32> {
+        let note = self.note.as_ref()?;
+        note.direction?;
+        let start_cents = note.points.get(note.phase_start).and_then(|p| p.cents)?;
+        let latest_cents = note.points.last().and_then(|p| p.cents)?;
+        let drift = latest_cents - start_cents;
+        if drift.abs() > self.drift_threshold_cents {
+            Some(drift)
+        }
+        else {
+            None
+        }
+    }
+
+    // Ends the note in progress, returning its last swell if it had
+    // one. Call this at the end of a recording.
+    pub fn finish(&mut self) -> Option<Swell> {
+        self.tracker.finish();
+        self.finish_note()
+    }
+
+    fn finish_note(&mut self) -> Option<Swell> {
+        let ended = self.note.as_ref().and_then(|note| match note.direction {
+            Some(Direction::Crescendo) => Some((note.phase_start, note.loudest, Direction::Crescendo)),
+            Some(Direction::Diminuendo) => Some((note.phase_start, note.quietest, Direction::Diminuendo)),
+            None => None
+        });
+        let swell = ended.and_then(|(start, end, direction)| self.swell(start, end, direction));
+        self.note = None;
+        swell
+    }
+
+    pub fn points(&self) -> &VecDeque<DynamicsPoint> {
+        &self.points
+    }
+
+    // finished swells that are still within the kept duration
+    pub fn swells(&self) -> &VecDeque<Swell> {
+        &self.swells
+    }
+
+    pub fn latest_time(&self) -> Option<f32> {
+        self.points.back().map(|p| p.time)
+    }
+}
+
+impl Default for DynamicsTracker {
+    fn default() -> DynamicsTracker {
+        DynamicsTracker {
+            min_change_db: 6.0,
+            drift_threshold_cents: 10.0,
+            min_duration: 0.4,
+            settle_time: 0.15,
+            duration: 10.0,
+            tracker: NoteTracker::default(),
+            points: VecDeque::new(),
+            swells: VecDeque::new(),
+            note: None
+        }
+    }
+}
+
+fn mean_cents<'a, I: Iterator<Item = &'a DynamicsPoint>>(points: I) -> Option<f32> {
+    let (sum, count) = points.filter_map(|p| p.cents).fold((0.0, 0), |(sum, count), c| (sum + c, count + 1));
+    if count > 0 {
+        Some(sum / count as f32)
+    }
+    else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STEP: f32 = 0.02;
+
+    fn cents(hz: f32, cents: f32) -> f32 {
+        hz * 2.0f32.powf(cents / 1200.0)
+    }
+
+    // Runs a held B♭ through the tracker, with the level and cents
+    // error given as functions of time.
+    fn perform<L: Fn(f32) -> f32, C: Fn(f32) -> f32>(seconds: f32, level_at: L, cents_at: C) -> Vec<Swell> {
+        let mut tracker = DynamicsTracker::new();
+        let mut swells = Vec::new();
+        for i in 0..(seconds / STEP) as usize {
+            let t = i as f32 * STEP;
+            swells.extend(tracker.push(t, level_at(t), Some(Pitch::new(cents(466.16, cents_at(t))))));
+        }
+        swells.extend(tracker.finish());
+        swells
+    }
+
+    #[test]
+    fn steady_note_has_no_swells() {
+        let swells = perform(3.0, |t| -20.0 + (t * 13.0).sin(), |_| 0.0);
+        assert!(swells.is_empty(), "{:?}", swells);
+    }
+
+    #[test]
+    fn crescendo_then_diminuendo() {
+        // -40dB up to -10dB over two seconds and back down again
+        let level = |t: f32| if t < 2.0 { -40.0 + 15.0 * t } else { -10.0 - 15.0 * (t - 2.0) };
+        let swells = perform(4.0, level, |_| 0.0);
+        let directions: Vec<Direction> = swells.iter().map(|s| s.direction).collect();
+        assert_eq!(directions, vec!(Direction::Crescendo, Direction::Diminuendo));
+        assert!((swells[0].end - 2.0).abs() < 0.05);
+        assert!(swells[0].level_change_db > 25.0);
+        assert!(swells[1].level_change_db < -25.0);
+        assert!(swells.iter().all(|s| !s.flagged));
+    }
+
+    #[test]
+    fn drifting_sharp_in_a_crescendo_is_flagged() {
+        let level = |t: f32| -40.0 + 15.0 * t;
+        let swells = perform(2.0, level, |t| 12.0 * t);
+        assert_eq!(swells.len(), 1);
+        assert_eq!(swells[0].direction, Direction::Crescendo);
+        assert!(swells[0].flagged);
+        assert!(swells[0].drift_cents > 15.0, "{}", swells[0].drift_cents);
+        assert!(format!("{}", swells[0]).ends_with("went sharp"));
+    }
+
+    #[test]
+    fn small_drift_is_not_flagged() {
+        let swells = perform(2.0, |t| -10.0 - 15.0 * t, |t| -2.0 * t);
+        assert_eq!(swells.len(), 1);
+        assert_eq!(swells[0].direction, Direction::Diminuendo);
+        assert!(!swells[0].flagged);
+    }
+
+    #[test]
+    fn drift_is_reported_while_it_happens() {
+        let mut tracker = DynamicsTracker::new();
+        for i in 0..50 {
+            let t = i as f32 * STEP;
+            tracker.push(t, -40.0 + 15.0 * t, Some(Pitch::new(cents(466.16, -20.0 * t))));
+        }
+        let drift = tracker.current_drift().expect("Expected drift to be flagged");
+        assert!(drift < -10.0);
+    }
+
+    #[test]
+    fn swells_end_with_the_note() {
+        let mut tracker = DynamicsTracker::new();
+        let mut swells = Vec::new();
+        for i in 0..100 {
+            let t = i as f32 * STEP;
+            let pitch = if t < 1.5 { Some(Pitch::new(466.16)) } else { None };
+            swells.extend(tracker.push(t, -40.0 + 20.0 * t, pitch));
+        }
+        assert_eq!(swells.len(), 1);
+        assert!(swells[0].end < 1.5);
+    }
+
+    #[test]
+    fn dropouts_do_not_end_a_swell() {
+        let mut tracker = DynamicsTracker::new();
+        let mut swells = Vec::new();
+        for i in 0..100 {
+            let t = i as f32 * STEP;
+            let pitch = if i % 10 == 5 { None } else { Some(Pitch::new(466.16)) };
+            swells.extend(tracker.push(t, -40.0 + 15.0 * t, pitch));
+        }
+        swells.extend(tracker.finish());
+        assert_eq!(swells.len(), 1, "{:?}", swells);
+        assert!(swells[0].level_change_db > 25.0);
+    }
+
+    #[test]
+    fn wording_follows_the_furthest_drift() {
+        // went flat mid-swell, then ended up a little sharp
+        let swell = Swell {
+            direction: Direction::Crescendo,
+            start: 0.0,
+            end: 1.0,
+            level_change_db: 12.0,
+            drift_cents: 3.0,
+            max_drift_cents: -25.0,
+            flagged: true
+        };
+        assert!(format!("{}", swell).ends_with("went flat"));
+    }
+}
@@ -0,0 +1,154 @@
+use std::ops::{Add, Sub, Mul};
+use std::f64::consts::PI;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Complex {
+    pub re: f32,
+    pub im: f32
+}
+
+impl Complex {
+    pub fn new(re: f32, im: f32) -> Complex {
+        Complex {
+            re: re,
+            im: im
+        }
+    }
+
+    pub fn conj(&self) -> Complex {
+        Complex::new(self.re, -self.im)
+    }
+
+    pub fn norm_sqr(&self) -> f32 {
+        self.re * self.re + self.im * self.im
+    }
+
+    pub fn norm(&self) -> f32 {
+        self.norm_sqr().sqrt()
+    }
+}
+
+impl Add for Complex {
+    type Output = Complex;
+    fn add(self, other: Complex) -> Complex {
+        Complex::new(self.re + other.re, self.im + other.im)
+    }
+}
+
+impl Sub for Complex {
+    type Output = Complex;
+    fn sub(self, other: Complex) -> Complex {
+        Complex::new(self.re - other.re, self.im - other.im)
+    }
+}
+
+impl Mul for Complex {
+    type Output = Complex;
+    fn mul(self, other: Complex) -> Complex {
+        Complex::new(
+            self.re * other.re - self.im * other.im,
+            self.re * other.im + self.im * other.re
+        )
+    }
+}
+
+// Iterative radix-2 Cooley-Tukey FFT, in place. The buffer's length
+// must be a power of two.
+pub fn fft(buffer: &mut [Complex]) {
+    let n = buffer.len();
+    assert!(n.is_power_of_two(), "FFT length must be a power of two, was {}", n);
+
+    // bit reversal permutation
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            buffer.swap(i, j);
+        }
+    }
+
+    // The twiddle factors are calculated directly in f64 rather than
+    // by repeated multiplication, which loses precision quickly in
+    // f32 at large sizes. Smaller butterflies use every nth one.
+    let twiddles: Vec<Complex> = (0..n / 2).map(|k| {
+        let angle = -2.0 * PI * k as f64 / n as f64;
+        Complex::new(angle.cos() as f32, angle.sin() as f32)
+    }).collect();
+
+    let mut len = 2;
+    while len <= n {
+        let stride = n / len;
+        for chunk in buffer.chunks_mut(len) {
+            let (low, high) = chunk.split_at_mut(len / 2);
+            for ((a, b), &w) in low.iter_mut().zip(high.iter_mut()).zip(twiddles.iter().step_by(stride)) {
+                let t = w * *b;
+                *b = *a - t;
+                *a = *a + t;
+            }
+        }
+        len *= 2;
+    }
+}
+
+pub fn inverse_fft(buffer: &mut [Complex]) {
+    let scale = 1.0 / buffer.len() as f32;
+    for c in buffer.iter_mut() {
+        *c = c.conj();
+    }
+    fft(buffer);
+    for c in buffer.iter_mut() {
+        *c = Complex::new(c.re * scale, -c.im * scale);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: Complex, b: Complex) {
+        assert!((a.re - b.re).abs() < 1e-4 && (a.im - b.im).abs() < 1e-4, "expected={:?}, actual={:?}", b, a);
+    }
+
+    #[test]
+    fn impulse_has_flat_spectrum() {
+        let mut buffer = vec!(Complex::default(); 8);
+        buffer[0] = Complex::new(1.0, 0.0);
+        fft(&mut buffer);
+        for &c in &buffer {
+            assert_close(c, Complex::new(1.0, 0.0));
+        }
+    }
+
+    #[test]
+    fn matches_direct_dft() {
+        let input: Vec<Complex> = (0..16)
+            .map(|i| Complex::new((i as f32 * 0.7).sin(), (i as f32 * 0.3).cos()))
+            .collect();
+        let mut buffer = input.clone();
+        fft(&mut buffer);
+
+        for (k, &actual) in buffer.iter().enumerate() {
+            let expected = input.iter().enumerate().fold(Complex::default(), |sum, (i, &x)| {
+                let angle = -2.0 * PI * (i * k) as f64 / input.len() as f64;
+                sum + x * Complex::new(angle.cos() as f32, angle.sin() as f32)
+            });
+            assert_close(actual, expected);
+        }
+    }
+
+    #[test]
+    fn inverse_round_trips() {
+        let input: Vec<Complex> = (0..32).map(|i| Complex::new(i as f32, -(i as f32) / 2.0)).collect();
+        let mut buffer = input.clone();
+        fft(&mut buffer);
+        inverse_fft(&mut buffer);
+        for (&actual, &expected) in buffer.iter().zip(input.iter()) {
+            assert_close(actual, expected);
+        }
+    }
+}
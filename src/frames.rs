@@ -0,0 +1,182 @@
+use std::collections::VecDeque;
+
+use signal::Signal;
+
+pub const DEFAULT_FRAME_SIZE: usize = 2048;
+// a quarter of the frame, so frames overlap by 75%
+pub const DEFAULT_HOP_SIZE: usize = 512;
+
+#[derive(Debug, Clone)]
+pub struct Frame {
+    // index of the first sample in the frame, counting from the first
+    // sample ever pushed
+    pub start: usize,
+    pub signal: Signal
+}
+
+impl Frame {
+    // seconds from the first sample to the middle of the frame
+    pub fn centre_time(&self) -> f32 {
+        (self.start as f32 + self.signal.samples.len() as f32 / 2.0) / self.signal.sample_rate
+    }
+}
+
+// Collects samples as they arrive, in whatever size chunks the audio
+// source likes, and cuts them into frames of frame_size samples with
+// the start of each frame hop_size samples after the last.
+#[derive(Debug, Clone)]
+pub struct SlidingWindow {
+    pub frame_size: usize,
+    pub hop_size: usize,
+    buffer: VecDeque<f32>,
+    // sample index of the front of the buffer
+    buffer_start: usize,
+    // If the hop is bigger than the frame, samples between frames are
+    // dropped before they reach the buffer.
+    to_skip: usize
+}
+
+impl SlidingWindow {
+    pub fn new(frame_size: usize, hop_size: usize) -> SlidingWindow {
+        SlidingWindow {
+            frame_size: frame_size.max(1),
+            hop_size: hop_size.max(1),
+            buffer: VecDeque::with_capacity(frame_size),
+            buffer_start: 0,
+            to_skip: 0
+        }
+    }
+
+    pub fn push(&mut self, samples: &[f32]) {
+        let skipped = self.to_skip.min(samples.len());
+        self.to_skip -= skipped;
+        self.buffer_start += skipped;
+        self.buffer.extend(&samples[skipped..]);
+    }
+
+    pub fn next_frame(&mut self, sample_rate: f32) -> Option<Frame> {
+        if self.buffer.len() < self.frame_size {
+            return None;
+        }
+
+        let samples: Vec<f32> = self.buffer.iter().take(self.frame_size).cloned().collect();
+        let frame = Frame {
+            start: self.buffer_start,
+            signal: Signal::new(&samples, sample_rate)
+        };
+
+        let dropped = self.hop_size.min(self.buffer.len());
+        self.buffer.drain(..dropped);
+        self.buffer_start += dropped;
+        self.to_skip = self.hop_size - dropped;
+
+        Some(frame)
+    }
+
+    // Every frame that can be made from the samples so far.
+    pub fn frames(&mut self, sample_rate: f32) -> Vec<Frame> {
+        let mut frames = Vec::new();
+        while let Some(frame) = self.next_frame(sample_rate) {
+            frames.push(frame);
+        }
+        frames
+    }
+
+    // For live input, where only the most up to date frame matters.
+    pub fn latest_frame(&mut self, sample_rate: f32) -> Option<Frame> {
+        let mut latest = None;
+        while let Some(frame) = self.next_frame(sample_rate) {
+            latest = Some(frame);
+        }
+        latest
+    }
+
+    pub fn clear(&mut self) {
+        self.buffer_start += self.buffer.len();
+        self.buffer.clear();
+    }
+}
+
+impl Default for SlidingWindow {
+    fn default() -> SlidingWindow {
+        SlidingWindow::new(DEFAULT_FRAME_SIZE, DEFAULT_HOP_SIZE)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn counting(from: usize, to: usize) -> Vec<f32> {
+        (from..to).map(|x| x as f32).collect()
+    }
+
+    // Signal removes the mean, so check frames by where they start
+    // and how long they are, and by the spacing of their samples.
+    fn boundaries(frames: &[Frame]) -> Vec<(usize, usize)> {
+        frames.iter().map(|f| (f.start, f.signal.samples.len())).collect()
+    }
+
+    #[test]
+    fn overlapping_frames_start_a_hop_apart() {
+        let mut window = SlidingWindow::new(8, 2);
+        window.push(&counting(0, 14));
+        let frames = window.frames(1.0);
+        assert_eq!(boundaries(&frames), vec!((0, 8), (2, 8), (4, 8), (6, 8)));
+    }
+
+    #[test]
+    fn frames_span_chunk_boundaries() {
+        let mut window = SlidingWindow::new(8, 4);
+        let mut frames = Vec::new();
+        for chunk in counting(0, 20).chunks(3) {
+            window.push(chunk);
+            frames.extend(window.frames(1.0));
+        }
+        assert_eq!(boundaries(&frames), vec!((0, 8), (4, 8), (8, 8), (12, 8)));
+
+        // The samples are a ramp, so after removing the mean a frame
+        // is the same ramp whichever chunks it came from.
+        let expected: Vec<f32> = (0..8).map(|x| x as f32 - 3.5).collect();
+        for frame in &frames {
+            assert_eq!(frame.signal.samples, expected);
+        }
+    }
+
+    #[test]
+    fn hops_bigger_than_frames_skip_samples() {
+        let mut window = SlidingWindow::new(4, 10);
+        window.push(&counting(0, 12));
+        window.push(&counting(12, 30));
+        let frames = window.frames(1.0);
+        assert_eq!(boundaries(&frames), vec!((0, 4), (10, 4), (20, 4)));
+    }
+
+    #[test]
+    fn no_frame_until_one_is_full() {
+        let mut window = SlidingWindow::new(2048, 512);
+        window.push(&[0.0; 512]);
+        window.push(&[0.0; 1535]);
+        assert!(window.next_frame(44100.0).is_none());
+        window.push(&[0.0; 1]);
+        assert!(window.next_frame(44100.0).is_some());
+        assert!(window.next_frame(44100.0).is_none());
+    }
+
+    #[test]
+    fn latest_frame_catches_up() {
+        let mut window = SlidingWindow::new(4, 2);
+        window.push(&counting(0, 11));
+        let frame = window.latest_frame(1.0).unwrap();
+        assert_eq!(frame.start, 6);
+        assert!(window.next_frame(1.0).is_none());
+    }
+
+    #[test]
+    fn frame_times_are_centres() {
+        let mut window = SlidingWindow::new(1000, 500);
+        window.push(&vec!(0.0; 2000));
+        let times: Vec<f32> = window.frames(1000.0).iter().map(|f| f.centre_time()).collect();
+        assert_eq!(times, vec!(0.5, 1.0, 1.5));
+    }
+}
@@ -3,69 +3,195 @@ use gtk::prelude::*;
 use std::cell::RefCell;
 use portaudio as pa;
 use std::rc::Rc;
+use std::cell::Cell;
+use std::collections::VecDeque;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::RwLock;
 use std::io;
 use std::io::Write;
+use std::path::PathBuf;
 use std::thread;
+use std::time::Instant;
 use std::sync::mpsc::*;
 
-use model::Model;
-use audio::SAMPLE_RATE;
+use model::{Model, AnalysisSettings};
+use audio::{AudioError, SAMPLE_RATE};
 use signal::Signal;
+use pitch::{self, Pitch, Temperament, TuningReference, Transposition, NoteNaming, NamingSystem, Spelling, Accidentals, OctaveNotation};
+use detector::Detector;
+use instrument::Instrument;
+use smoothing::{PitchSmoother, Smoothing, SmoothingMethod};
+use level::PeakHold;
+use signal::DEFAULT_NOISE_GATE_DB;
+use history::PitchHistory;
+use vibrato::Vibrato;
+use dynamics::DynamicsTracker;
+use frames::{SlidingWindow, DEFAULT_HOP_SIZE};
+use spectrum::{self, Spectrum, Spectrogram};
+use tone::{ToneGenerator, Waveform};
+use midi::{self, MidiRecorder, TimedEvent};
+use config::{self, Chart, Config};
 
 const FPS: u32 = 60;
 
+// Range shown on the spectrum and spectrogram charts. Below 50Hz is
+// mostly mains hum and handling noise.
+const SPECTRUM_MIN_HZ: f32 = 50.0;
+const SPECTRUM_MAX_HZ: f32 = 10000.0;
+const SPECTRUM_MIN_DB: f32 = -100.0;
+
+// bottom of the level meter
+const METER_MIN_DB: f32 = -60.0;
+const NOISE_GATE_PRESETS: [f32; 6] = [-20.0, -26.0, -30.0, -40.0, -50.0, -60.0];
+// frames either side used for smoothing
+const LATENCY_PRESETS: [usize; 5] = [1, 2, 3, 5, 8];
+// how often to check the input stream is still delivering audio, and
+// to look for the device again when it isn't
+const AUDIO_WATCHDOG_MS: u32 = 2000;
+// Restarting PortAudio to look for a device is slow and can glitch
+// other programs' audio, so while it stays missing the checks between
+// tries double, up to this many.
+const AUDIO_WATCHDOG_MAX_BACKOFF: u32 = 16;
+
 struct RustyUi {
+    window: gtk::Window,
     dropdown: gtk::ComboBoxText,
+    refresh_devices_button: gtk::Button,
+    status_label: gtk::Label,
+    transposition_dropdown: gtk::ComboBoxText,
+    reference_dropdown: gtk::ComboBoxText,
+    temperament_dropdown: gtk::ComboBoxText,
+    tonic_dropdown: gtk::ComboBoxText,
+    detector_dropdown: gtk::ComboBoxText,
+    instrument_dropdown: gtk::ComboBoxText,
+    smoothing_dropdown: gtk::ComboBoxText,
+    latency_dropdown: gtk::ComboBoxText,
+    noise_gate_dropdown: gtk::ComboBoxText,
+    naming_system_dropdown: gtk::ComboBoxText,
+    spelling_dropdown: gtk::ComboBoxText,
+    key_dropdown: gtk::ComboBoxText,
+    octaves_dropdown: gtk::ComboBoxText,
+    ascii_check_button: gtk::CheckButton,
     pitch_label: gtk::Label,
+    vibrato_label: gtk::Label,
+    dynamics_label: gtk::Label,
     pitch_error_indicator: gtk::DrawingArea,
+    level_meter: gtk::DrawingArea,
     oscilloscope_chart: gtk::DrawingArea,
     correlation_chart: gtk::DrawingArea,
+    history_chart: gtk::DrawingArea,
+    dynamics_chart: gtk::DrawingArea,
+    spectrum_chart: gtk::DrawingArea,
+    spectrogram_chart: gtk::DrawingArea,
     oscilloscope_toggle_button: gtk::Button,
-    correlation_toggle_button: gtk::Button
+    correlation_toggle_button: gtk::Button,
+    history_toggle_button: gtk::Button,
+    dynamics_toggle_button: gtk::Button,
+    spectrum_toggle_button: gtk::Button,
+    spectrogram_toggle_button: gtk::Button,
+    tone_note_dropdown: gtk::ComboBoxText,
+    tone_octave_dropdown: gtk::ComboBoxText,
+    tone_waveform_dropdown: gtk::ComboBoxText,
+    tone_play_button: gtk::Button,
+    record_button: gtk::Button,
+    save_midi_button: gtk::Button
 }
 
 struct ApplicationState {
-    pa: pa::PortAudio,
+    // None only while PortAudio is being restarted to find new devices
+    pa: Option<pa::PortAudio>,
     pa_stream: Option<pa::Stream<pa::NonBlocking, pa::Input<f32>>>,
+    tone_stream: Option<pa::Stream<pa::NonBlocking, pa::Output<f32>>>,
+    // The device the user chose, by name, since indices change when
+    // devices come and go. It's kept while the device is unplugged so
+    // that it can be reopened when it comes back.
+    microphone: Option<String>,
+    mic_sender: Sender<Signal>,
+    // stops the device dropdown's changed handler firing while its
+    // items are replaced
+    refreshing_devices: bool,
+    recorded_midi: Vec<TimedEvent>,
+    transposition: Transposition,
+    naming: NoteNaming,
     ui: RustyUi
 }
 
 pub fn start_gui() -> Result<(), String> {
     let pa = try!(::audio::init().map_err(|e| e.to_string()));
-    let microphones = try!(::audio::get_device_list(&pa).map_err(|e| e.to_string()));
-    let default_microphone = try!(::audio::get_default_device(&pa).map_err(|e| e.to_string()));
     
     try!(gtk::init().map_err(|_| "Failed to initialize GTK."));
 
+    let config_path = config::default_path();
+    let (config, config_errors) = match config_path {
+        Some(ref path) => Config::load(path),
+        None => (Config::default(), Vec::new())
+    };
+
+    let (mic_sender, mic_receiver) = channel();
     let state = Rc::new(RefCell::new(ApplicationState {
-        pa: pa,
+        pa: Some(pa),
         pa_stream: None,
-        ui: create_window(microphones, default_microphone)
+        tone_stream: None,
+        microphone: config.device.clone(),
+        mic_sender: mic_sender,
+        refreshing_devices: false,
+        recorded_midi: Vec::new(),
+        transposition: config.transposition,
+        naming: config.naming,
+        ui: create_window(&config)
     }));
 
     let cross_thread_state = Arc::new(RwLock::new(Model::new()));
-    
-    let (mic_sender, mic_receiver) = channel();
+    let settings = Arc::new(RwLock::new(config.settings));
+    let history = Arc::new(RwLock::new(PitchHistory::default()));
+    let dynamics = Arc::new(RwLock::new(DynamicsTracker::default()));
+    let spectrogram = Arc::new(RwLock::new(Spectrogram::default()));
+    // only Some while recording
+    let recorder = Arc::new(RwLock::new(None));
+    let audio_buffers = Arc::new(AtomicUsize::new(0));
 
-    connect_dropdown_choose_microphone(mic_sender, Rc::clone(&state));
+    connect_dropdown_choose_microphone(Rc::clone(&state));
+    if !config_errors.is_empty() {
+        let errors: Vec<String> = config_errors.iter().map(|e| e.to_string()).collect();
+        show_status(&state.borrow().ui, Some(&format!("Ignoring part of the settings file: {}", errors.join("; "))));
+    }
+    connect_dropdown_choose_transposition(Rc::clone(&state));
+    connect_naming_controls(&state);
+    connect_dropdown_choose_reference(&state, Arc::clone(&settings));
+    connect_dropdown_choose_temperament(&state, Arc::clone(&settings));
+    connect_dropdown_choose_detector(&state, Arc::clone(&settings));
+    connect_dropdown_choose_instrument(&state, Arc::clone(&settings));
+    connect_dropdown_choose_smoothing(&state, Arc::clone(&settings));
+    connect_dropdown_choose_noise_gate(&state, Arc::clone(&settings));
+    connect_tone_controls(&state, Arc::clone(&settings));
+    connect_recording_controls(&state, Arc::clone(&recorder));
+    connect_save_settings_on_close(&state, Arc::clone(&settings), config_path);
     
-    start_processing_audio(mic_receiver, Arc::clone(&cross_thread_state));
-    setup_pitch_label_callbacks(Rc::clone(&state), Arc::clone(&cross_thread_state));
+    start_processing_audio(mic_receiver, Arc::clone(&audio_buffers), Arc::clone(&cross_thread_state), Arc::clone(&history), Arc::clone(&dynamics), Arc::clone(&spectrogram), recorder, settings);
+    setup_pitch_label_callbacks(Rc::clone(&state), Arc::clone(&cross_thread_state), Arc::clone(&history), Arc::clone(&dynamics));
     setup_pitch_error_indicator_callbacks(&state, Arc::clone(&cross_thread_state));
+    setup_level_meter_callbacks(&state, Arc::clone(&cross_thread_state), Arc::clone(&settings));
     setup_oscilloscope_drawing_area_callbacks(&state, Arc::clone(&cross_thread_state));
     setup_correlation_drawing_area_callbacks(&state, Arc::clone(&cross_thread_state));
+    setup_history_drawing_area_callbacks(&state, Arc::clone(&history));
+    setup_dynamics_drawing_area_callbacks(&state, Arc::clone(&dynamics));
+    setup_spectrum_drawing_area_callbacks(&state, Arc::clone(&cross_thread_state), Arc::clone(&spectrogram));
+    setup_spectrogram_drawing_area_callbacks(&state, Arc::clone(&spectrogram));
 
     setup_chart_visibility_callbacks(Rc::clone(&state));
+    setup_audio_watchdog(Rc::clone(&state), audio_buffers);
     
     gtk::main();
     Ok(())
 }
 
-fn create_window(microphones: Vec<(u32, String)>, default_microphone: u32) -> RustyUi {
+fn create_window(config: &Config) -> RustyUi {
     let window = gtk::Window::new(gtk::WindowType::Toplevel);
     window.set_title("Rusty Microphone");
+    if let Some((width, height)) = config.window_size {
+        window.set_default_size(width, height);
+    }
     window.connect_delete_event(|_, _| {
         gtk::main_quit();
         Inhibit(false)
@@ -76,23 +202,110 @@ fn create_window(microphones: Vec<(u32, String)>, default_microphone: u32) -> Ru
 
     let hbox = gtk::Box::new(gtk::Orientation::Horizontal, 2);
     vbox.add(&hbox);
+    // filled in by refresh_devices
     let dropdown = gtk::ComboBoxText::new();
     dropdown.set_hexpand(true);
-    set_dropdown_items(&dropdown, microphones, default_microphone);
     hbox.add(&dropdown);
+    let refresh_devices_button = gtk::Button::new_with_label("Refresh");
+    hbox.add(&refresh_devices_button);
+
+    let transposition_dropdown = gtk::ComboBoxText::new();
+    set_transposition_dropdown_items(&transposition_dropdown, config.transposition);
+    hbox.add(&transposition_dropdown);
+
+    let reference_dropdown = gtk::ComboBoxText::new();
+    set_reference_dropdown_items(&reference_dropdown, config.settings.reference);
+    hbox.add(&reference_dropdown);
+
+    let temperament_dropdown = gtk::ComboBoxText::new();
+    let tonic_dropdown = gtk::ComboBoxText::new();
+    set_temperament_dropdown_items(&temperament_dropdown, &tonic_dropdown, config.settings.reference);
+    hbox.add(&temperament_dropdown);
+    hbox.add(&tonic_dropdown);
+
+    let detector_dropdown = gtk::ComboBoxText::new();
+    set_detector_dropdown_items(&detector_dropdown, config.settings.detector);
+    hbox.add(&detector_dropdown);
+
+    let instrument_dropdown = gtk::ComboBoxText::new();
+    set_instrument_dropdown_items(&instrument_dropdown, config.settings.instrument);
+    hbox.add(&instrument_dropdown);
+
+    let smoothing_dropdown = gtk::ComboBoxText::new();
+    let latency_dropdown = gtk::ComboBoxText::new();
+    set_smoothing_dropdown_items(&smoothing_dropdown, &latency_dropdown, config.settings.smoothing);
+    hbox.add(&smoothing_dropdown);
+    hbox.add(&latency_dropdown);
+
+    let noise_gate_dropdown = gtk::ComboBoxText::new();
+    set_noise_gate_dropdown_items(&noise_gate_dropdown, config.settings.noise_gate_db);
+    hbox.add(&noise_gate_dropdown);
     
     let oscilloscope_toggle_button = gtk::Button::new_with_label("Osc");
     hbox.add(&oscilloscope_toggle_button);
     let correlation_toggle_button = gtk::Button::new_with_label("Corr");
     hbox.add(&correlation_toggle_button);
+    let history_toggle_button = gtk::Button::new_with_label("Hist");
+    hbox.add(&history_toggle_button);
+    let dynamics_toggle_button = gtk::Button::new_with_label("Dyn");
+    hbox.add(&dynamics_toggle_button);
+    let spectrum_toggle_button = gtk::Button::new_with_label("Spec");
+    hbox.add(&spectrum_toggle_button);
+    let spectrogram_toggle_button = gtk::Button::new_with_label("Sgram");
+    hbox.add(&spectrogram_toggle_button);
+
+    let status_label = gtk::Label::new(None);
+    vbox.add(&status_label);
+
+    let naming_hbox = gtk::Box::new(gtk::Orientation::Horizontal, 2);
+    vbox.add(&naming_hbox);
+    naming_hbox.add(&gtk::Label::new(Some("Note names")));
+    let naming_system_dropdown = gtk::ComboBoxText::new();
+    let spelling_dropdown = gtk::ComboBoxText::new();
+    let key_dropdown = gtk::ComboBoxText::new();
+    let octaves_dropdown = gtk::ComboBoxText::new();
+    set_naming_dropdown_items(&naming_system_dropdown, &spelling_dropdown, &key_dropdown, &octaves_dropdown, config.naming);
+    naming_hbox.add(&naming_system_dropdown);
+    naming_hbox.add(&spelling_dropdown);
+    naming_hbox.add(&key_dropdown);
+    naming_hbox.add(&octaves_dropdown);
+    let ascii_check_button = gtk::CheckButton::new_with_label("Write ♯ and ♭ as # and b");
+    ascii_check_button.set_active(config.naming.accidentals == Accidentals::Ascii);
+    naming_hbox.add(&ascii_check_button);
+
+    let practice_hbox = gtk::Box::new(gtk::Orientation::Horizontal, 2);
+    vbox.add(&practice_hbox);
+    practice_hbox.add(&gtk::Label::new(Some("Drone")));
+    let tone_note_dropdown = gtk::ComboBoxText::new();
+    let tone_octave_dropdown = gtk::ComboBoxText::new();
+    let tone_waveform_dropdown = gtk::ComboBoxText::new();
+    set_tone_dropdown_items(&tone_note_dropdown, &tone_octave_dropdown, &tone_waveform_dropdown);
+    practice_hbox.add(&tone_note_dropdown);
+    practice_hbox.add(&tone_octave_dropdown);
+    practice_hbox.add(&tone_waveform_dropdown);
+    let tone_play_button = gtk::Button::new_with_label("Play");
+    practice_hbox.add(&tone_play_button);
+    let record_button = gtk::Button::new_with_label("Record");
+    practice_hbox.pack_end(&record_button, false, false, 0);
+    let save_midi_button = gtk::Button::new_with_label("Save MIDI");
+    save_midi_button.set_sensitive(false);
+    practice_hbox.pack_end(&save_midi_button, false, false, 0);
 
     let pitch_label = gtk::Label::new(None);
     vbox.add(&pitch_label);
+    let vibrato_label = gtk::Label::new(None);
+    vbox.add(&vibrato_label);
+    let dynamics_label = gtk::Label::new(None);
+    vbox.add(&dynamics_label);
 
     let pitch_error_indicator = gtk::DrawingArea::new();
     pitch_error_indicator.set_size_request(600, 70);
     vbox.add(&pitch_error_indicator);
 
+    let level_meter = gtk::DrawingArea::new();
+    level_meter.set_size_request(600, 24);
+    vbox.add(&level_meter);
+
     let oscilloscope_chart = gtk::DrawingArea::new();
     oscilloscope_chart.set_size_request(600, 250);
     oscilloscope_chart.set_vexpand(true);
@@ -103,60 +316,726 @@ fn create_window(microphones: Vec<(u32, String)>, default_microphone: u32) -> Ru
     correlation_chart.set_vexpand(true);
     vbox.add(&correlation_chart);
 
+    let history_chart = gtk::DrawingArea::new();
+    history_chart.set_size_request(600, 250);
+    history_chart.set_vexpand(true);
+    vbox.add(&history_chart);
+
+    let dynamics_chart = gtk::DrawingArea::new();
+    dynamics_chart.set_size_request(600, 250);
+    dynamics_chart.set_vexpand(true);
+    vbox.add(&dynamics_chart);
+
+    let spectrum_chart = gtk::DrawingArea::new();
+    spectrum_chart.set_size_request(600, 250);
+    spectrum_chart.set_vexpand(true);
+    vbox.add(&spectrum_chart);
+
+    let spectrogram_chart = gtk::DrawingArea::new();
+    spectrogram_chart.set_size_request(600, 250);
+    spectrogram_chart.set_vexpand(true);
+    vbox.add(&spectrogram_chart);
+
     window.show_all();
     
-    // correlation chart is only really useful for debugging, so it
-    // makes sense to have it default to being hidden
-    correlation_chart.set_visible(false);
-    
-    RustyUi {
+    let ui = RustyUi {
+        window: window,
         dropdown: dropdown,
+        refresh_devices_button: refresh_devices_button,
+        status_label: status_label,
+        transposition_dropdown: transposition_dropdown,
+        reference_dropdown: reference_dropdown,
+        temperament_dropdown: temperament_dropdown,
+        tonic_dropdown: tonic_dropdown,
+        detector_dropdown: detector_dropdown,
+        instrument_dropdown: instrument_dropdown,
+        smoothing_dropdown: smoothing_dropdown,
+        latency_dropdown: latency_dropdown,
+        noise_gate_dropdown: noise_gate_dropdown,
+        naming_system_dropdown: naming_system_dropdown,
+        spelling_dropdown: spelling_dropdown,
+        key_dropdown: key_dropdown,
+        octaves_dropdown: octaves_dropdown,
+        ascii_check_button: ascii_check_button,
         pitch_label: pitch_label,
+        vibrato_label: vibrato_label,
+        dynamics_label: dynamics_label,
         pitch_error_indicator: pitch_error_indicator,
+        level_meter: level_meter,
         oscilloscope_chart: oscilloscope_chart,
         correlation_chart: correlation_chart,
+        history_chart: history_chart,
+        dynamics_chart: dynamics_chart,
+        spectrum_chart: spectrum_chart,
+        spectrogram_chart: spectrogram_chart,
         oscilloscope_toggle_button: oscilloscope_toggle_button,
-        correlation_toggle_button: correlation_toggle_button
+        correlation_toggle_button: correlation_toggle_button,
+        history_toggle_button: history_toggle_button,
+        dynamics_toggle_button: dynamics_toggle_button,
+        spectrum_toggle_button: spectrum_toggle_button,
+        spectrogram_toggle_button: spectrogram_toggle_button,
+        tone_note_dropdown: tone_note_dropdown,
+        tone_octave_dropdown: tone_octave_dropdown,
+        tone_waveform_dropdown: tone_waveform_dropdown,
+        tone_play_button: tone_play_button,
+        record_button: record_button,
+        save_midi_button: save_midi_button
+    };
+    for chart in Chart::all() {
+        ui.set_chart_visible(chart, config.is_visible(chart));
+    }
+    show_status(&ui, None);
+    ui
+}
+
+impl RustyUi {
+    fn chart(&self, chart: Chart) -> &gtk::DrawingArea {
+        match chart {
+            Chart::Oscilloscope => &self.oscilloscope_chart,
+            Chart::Correlation => &self.correlation_chart,
+            Chart::History => &self.history_chart,
+            Chart::Dynamics => &self.dynamics_chart,
+            Chart::Spectrum => &self.spectrum_chart,
+            Chart::Spectrogram => &self.spectrogram_chart
+        }
+    }
+
+    fn set_chart_visible(&self, chart: Chart, visible: bool) {
+        self.chart(chart).set_visible(visible);
+        // the swell summaries only make sense next to the chart
+        if chart == Chart::Dynamics {
+            self.dynamics_label.set_visible(visible);
+        }
     }
 }
 
-fn set_dropdown_items(dropdown: &gtk::ComboBoxText, microphones: Vec<(u32, String)>, default_mic: u32) {
+fn set_dropdown_items(dropdown: &gtk::ComboBoxText, microphones: Vec<(u32, String)>, default_mic: Option<u32>) {
     for (index, name) in microphones {
         dropdown.append(Some(format!("{}", index).as_ref()), name.as_ref());
     }
-    dropdown.set_active_id(Some(format!("{}", default_mic).as_ref()));
+    if let Some(default_mic) = default_mic {
+        dropdown.set_active_id(Some(format!("{}", default_mic).as_ref()));
+    }
+}
+
+// Problems the user should know about, such as an unplugged
+// microphone, shown under the device list until they're resolved.
+fn show_status(ui: &RustyUi, message: Option<&str>) {
+    ui.status_label.set_label(message.unwrap_or(""));
+    ui.status_label.set_visible(message.is_some());
+}
+
+fn set_transposition_dropdown_items(dropdown: &gtk::ComboBoxText, default_transposition: Transposition) {
+    for transposition in Transposition::all() {
+        dropdown.append(Some(format!("{}", transposition.semitones()).as_ref()), format!("{}", transposition).as_ref());
+    }
+    dropdown.set_active_id(Some(format!("{}", default_transposition.semitones()).as_ref()));
+}
+
+fn connect_dropdown_choose_transposition(state: Rc<RefCell<ApplicationState>>) {
+    let dropdown = state.borrow().ui.transposition_dropdown.clone();
+    dropdown.connect_changed(move |dropdown: &gtk::ComboBoxText| {
+        let selected = dropdown.get_active_id()
+            .and_then(|id| id.parse().ok())
+            .and_then(Transposition::from_semitones);
+        if let Some(transposition) = selected {
+            state.borrow_mut().transposition = transposition;
+        }
+    });
+}
+
+fn set_naming_dropdown_items(system_dropdown: &gtk::ComboBoxText, spelling_dropdown: &gtk::ComboBoxText, key_dropdown: &gtk::ComboBoxText, octaves_dropdown: &gtk::ComboBoxText, default_naming: NoteNaming) {
+    for system in NamingSystem::all() {
+        system_dropdown.append(Some(system.id()), format!("{}", system).as_ref());
+    }
+    system_dropdown.set_active_id(Some(default_naming.system.id()));
+
+    for spelling in Spelling::all() {
+        spelling_dropdown.append(Some(spelling.id()), format!("{}", spelling).as_ref());
+    }
+    spelling_dropdown.set_active_id(Some(default_naming.spelling.id()));
+
+    for (semitone, name) in pitch::NOTE_NAMES.iter().enumerate() {
+        key_dropdown.append(Some(format!("{}", semitone).as_ref()), format!("{} major", name).as_ref());
+    }
+    key_dropdown.set_active_id(Some(format!("{}", default_naming.key).as_ref()));
+    // the key only matters when spelling by key signature
+    key_dropdown.set_sensitive(default_naming.spelling == Spelling::Key);
+
+    for octaves in OctaveNotation::all() {
+        octaves_dropdown.append(Some(octaves.id()), format!("{}", octaves).as_ref());
+    }
+    octaves_dropdown.set_active_id(Some(default_naming.octaves.id()));
+}
+
+fn connect_naming_controls(state: &Rc<RefCell<ApplicationState>>) {
+    let ui = &state.borrow().ui;
+
+    let system_state = Rc::clone(state);
+    ui.naming_system_dropdown.connect_changed(move |dropdown: &gtk::ComboBoxText| {
+        if let Some(system) = dropdown.get_active_id().and_then(|id| id.parse().ok()) {
+            system_state.borrow_mut().naming.system = system;
+        }
+    });
+
+    let spelling_state = Rc::clone(state);
+    let key_dropdown = ui.key_dropdown.clone();
+    ui.spelling_dropdown.connect_changed(move |dropdown: &gtk::ComboBoxText| {
+        if let Some(spelling) = dropdown.get_active_id().and_then(|id| id.parse().ok()) {
+            spelling_state.borrow_mut().naming.spelling = spelling;
+            key_dropdown.set_sensitive(spelling == Spelling::Key);
+        }
+    });
+
+    let key_state = Rc::clone(state);
+    ui.key_dropdown.connect_changed(move |dropdown: &gtk::ComboBoxText| {
+        if let Some(key) = dropdown.get_active_id().and_then(|id| id.parse().ok()) {
+            key_state.borrow_mut().naming.key = key;
+        }
+    });
+
+    let octaves_state = Rc::clone(state);
+    ui.octaves_dropdown.connect_changed(move |dropdown: &gtk::ComboBoxText| {
+        if let Some(octaves) = dropdown.get_active_id().and_then(|id| id.parse().ok()) {
+            octaves_state.borrow_mut().naming.octaves = octaves;
+        }
+    });
+
+    let ascii_state = Rc::clone(state);
+    ui.ascii_check_button.connect_toggled(move |button: &gtk::CheckButton| {
+        ascii_state.borrow_mut().naming.accidentals = if button.get_active() { Accidentals::Ascii } else { Accidentals::Unicode };
+    });
+}
+
+fn set_reference_dropdown_items(dropdown: &gtk::ComboBoxText, default_reference: TuningReference) {
+    // a saved reference that isn't a preset still needs to be shown
+    let mut references = TuningReference::presets();
+    if !references.iter().any(|r| r.a4_hz == default_reference.a4_hz) {
+        references.push(TuningReference::new(default_reference.a4_hz));
+        references.sort_by(|a, b| a.a4_hz.partial_cmp(&b.a4_hz).unwrap());
+    }
+    for reference in references {
+        dropdown.append(Some(format!("{}", reference.a4_hz).as_ref()), format!("{}", reference).as_ref());
+    }
+    dropdown.set_active_id(Some(format!("{}", default_reference.a4_hz).as_ref()));
+}
+
+fn connect_dropdown_choose_reference(state: &Rc<RefCell<ApplicationState>>, settings: Arc<RwLock<AnalysisSettings>>) {
+    let dropdown = &state.borrow().ui.reference_dropdown;
+    dropdown.connect_changed(move |dropdown: &gtk::ComboBoxText| {
+        let selected = dropdown.get_active_id().and_then(|id| id.parse().ok());
+        if let (Some(a4_hz), Ok(mut settings)) = (selected, settings.write()) {
+            settings.reference.a4_hz = a4_hz;
+        }
+    });
+}
+
+fn set_temperament_dropdown_items(temperament_dropdown: &gtk::ComboBoxText, tonic_dropdown: &gtk::ComboBoxText, default_reference: TuningReference) {
+    for temperament in Temperament::all() {
+        temperament_dropdown.append(Some(temperament.id()), format!("{}", temperament).as_ref());
+    }
+    temperament_dropdown.set_active_id(Some(default_reference.temperament.id()));
+
+    for (semitone, name) in pitch::NOTE_NAMES.iter().enumerate() {
+        tonic_dropdown.append(Some(format!("{}", semitone).as_ref()), format!("in {}", name).as_ref());
+    }
+    tonic_dropdown.set_active_id(Some(format!("{}", default_reference.tonic).as_ref()));
+    // every key is the same in equal temperament
+    tonic_dropdown.set_sensitive(default_reference.temperament != Temperament::Equal);
+}
+
+fn connect_dropdown_choose_temperament(state: &Rc<RefCell<ApplicationState>>, settings: Arc<RwLock<AnalysisSettings>>) {
+    let ui = &state.borrow().ui;
+
+    let tonic_dropdown = ui.tonic_dropdown.clone();
+    let temperament_settings = Arc::clone(&settings);
+    ui.temperament_dropdown.connect_changed(move |dropdown: &gtk::ComboBoxText| {
+        let selected = dropdown.get_active_id().and_then(|id| id.parse().ok());
+        if let (Some(temperament), Ok(mut settings)) = (selected, temperament_settings.write()) {
+            settings.reference.temperament = temperament;
+            tonic_dropdown.set_sensitive(temperament != Temperament::Equal);
+        }
+    });
+
+    ui.tonic_dropdown.connect_changed(move |dropdown: &gtk::ComboBoxText| {
+        let selected = dropdown.get_active_id().and_then(|id| id.parse().ok());
+        if let (Some(tonic), Ok(mut settings)) = (selected, settings.write()) {
+            settings.reference.tonic = tonic;
+        }
+    });
+}
+
+fn set_detector_dropdown_items(dropdown: &gtk::ComboBoxText, default_detector: Detector) {
+    for detector in Detector::all() {
+        dropdown.append(Some(detector.id()), format!("{}", detector).as_ref());
+    }
+    dropdown.set_active_id(Some(default_detector.id()));
+}
+
+fn connect_dropdown_choose_detector(state: &Rc<RefCell<ApplicationState>>, settings: Arc<RwLock<AnalysisSettings>>) {
+    let dropdown = &state.borrow().ui.detector_dropdown;
+    dropdown.connect_changed(move |dropdown: &gtk::ComboBoxText| {
+        let selected = dropdown.get_active_id().and_then(|id| id.parse().ok());
+        if let (Some(detector), Ok(mut settings)) = (selected, settings.write()) {
+            settings.detector = detector;
+        }
+    });
+}
+
+fn set_instrument_dropdown_items(dropdown: &gtk::ComboBoxText, default_instrument: Instrument) {
+    for instrument in Instrument::all() {
+        dropdown.append(Some(instrument.id()), format!("{}", instrument).as_ref());
+    }
+    dropdown.set_active_id(Some(default_instrument.id()));
+}
+
+fn connect_dropdown_choose_instrument(state: &Rc<RefCell<ApplicationState>>, settings: Arc<RwLock<AnalysisSettings>>) {
+    let dropdown = &state.borrow().ui.instrument_dropdown;
+    dropdown.connect_changed(move |dropdown: &gtk::ComboBoxText| {
+        let selected = dropdown.get_active_id().and_then(|id| id.parse().ok());
+        if let (Some(instrument), Ok(mut settings)) = (selected, settings.write()) {
+            settings.instrument = instrument;
+        }
+    });
+}
+
+fn set_smoothing_dropdown_items(method_dropdown: &gtk::ComboBoxText, latency_dropdown: &gtk::ComboBoxText, default_smoothing: Smoothing) {
+    for method in SmoothingMethod::all() {
+        method_dropdown.append(Some(method.id()), format!("{}", method).as_ref());
+    }
+    method_dropdown.set_active_id(Some(default_smoothing.method.id()));
+
+    // shown in milliseconds at the default hop, which is what the
+    // processing thread uses
+    let mut latencies = LATENCY_PRESETS.to_vec();
+    if !latencies.contains(&default_smoothing.latency) {
+        latencies.push(default_smoothing.latency);
+        latencies.sort();
+    }
+    for latency in latencies {
+        let millis = (latency * DEFAULT_HOP_SIZE) as f32 * 1000.0 / SAMPLE_RATE;
+        latency_dropdown.append(Some(format!("{}", latency).as_ref()), format!("{:.0} ms delay", millis).as_ref());
+    }
+    latency_dropdown.set_active_id(Some(format!("{}", default_smoothing.latency).as_ref()));
+}
+
+fn connect_dropdown_choose_smoothing(state: &Rc<RefCell<ApplicationState>>, settings: Arc<RwLock<AnalysisSettings>>) {
+    let method_settings = Arc::clone(&settings);
+    state.borrow().ui.smoothing_dropdown.connect_changed(move |dropdown: &gtk::ComboBoxText| {
+        let selected = dropdown.get_active_id().and_then(|id| id.parse().ok());
+        if let (Some(method), Ok(mut settings)) = (selected, method_settings.write()) {
+            settings.smoothing.method = method;
+        }
+    });
+    state.borrow().ui.latency_dropdown.connect_changed(move |dropdown: &gtk::ComboBoxText| {
+        let selected = dropdown.get_active_id().and_then(|id| id.parse().ok());
+        if let (Some(latency), Ok(mut settings)) = (selected, settings.write()) {
+            settings.smoothing.latency = latency;
+        }
+    });
+}
+
+fn set_noise_gate_dropdown_items(dropdown: &gtk::ComboBoxText, default_gate_db: f32) {
+    let mut gates = NOISE_GATE_PRESETS.to_vec();
+    if !gates.contains(&default_gate_db) {
+        gates.push(default_gate_db);
+        gates.sort_by(|a, b| b.partial_cmp(a).unwrap());
+    }
+    for gate_db in gates {
+        dropdown.append(Some(format!("{}", gate_db).as_ref()), format!("Gate {} dB", gate_db).as_ref());
+    }
+    dropdown.set_active_id(Some(format!("{}", default_gate_db).as_ref()));
+}
+
+fn connect_dropdown_choose_noise_gate(state: &Rc<RefCell<ApplicationState>>, settings: Arc<RwLock<AnalysisSettings>>) {
+    let dropdown = &state.borrow().ui.noise_gate_dropdown;
+    dropdown.connect_changed(move |dropdown: &gtk::ComboBoxText| {
+        let selected = dropdown.get_active_id().and_then(|id| id.parse().ok());
+        if let (Some(gate_db), Ok(mut settings)) = (selected, settings.write()) {
+            settings.noise_gate_db = gate_db;
+        }
+    });
+}
+
+fn set_tone_dropdown_items(note_dropdown: &gtk::ComboBoxText, octave_dropdown: &gtk::ComboBoxText, waveform_dropdown: &gtk::ComboBoxText) {
+    for (semitone, name) in pitch::NOTE_NAMES.iter().enumerate() {
+        note_dropdown.append(Some(format!("{}", semitone).as_ref()), name);
+    }
+    // concert B♭, the usual brass tuning note
+    note_dropdown.set_active_id(Some("10"));
+
+    for octave in 1..7 {
+        octave_dropdown.append(Some(format!("{}", octave).as_ref()), format!("{}", octave).as_ref());
+    }
+    octave_dropdown.set_active_id(Some("3"));
+
+    for waveform in Waveform::all() {
+        waveform_dropdown.append(Some(waveform.id()), format!("{}", waveform).as_ref());
+    }
+    waveform_dropdown.set_active_id(Some(Waveform::default().id()));
+}
+
+fn connect_tone_controls(state: &Rc<RefCell<ApplicationState>>, settings: Arc<RwLock<AnalysisSettings>>) {
+    let ui = &state.borrow().ui;
+
+    let play_state = Rc::clone(state);
+    let play_settings = Arc::clone(&settings);
+    ui.tone_play_button.connect_clicked(move |_| {
+        let playing = play_state.borrow().tone_stream.is_some();
+        if playing {
+            stop_tone(&play_state);
+        }
+        else {
+            start_tone(&play_state, &play_settings);
+        }
+    });
+
+    // changing the note while the drone is playing should be heard
+    // straight away
+    for dropdown in &[&ui.tone_note_dropdown, &ui.tone_octave_dropdown, &ui.tone_waveform_dropdown, &ui.reference_dropdown, &ui.temperament_dropdown, &ui.tonic_dropdown] {
+        let dropdown_state = Rc::clone(state);
+        let dropdown_settings = Arc::clone(&settings);
+        dropdown.connect_changed(move |_| {
+            let playing = dropdown_state.borrow().tone_stream.is_some();
+            if playing {
+                stop_tone(&dropdown_state);
+                start_tone(&dropdown_state, &dropdown_settings);
+            }
+        });
+    }
+}
+
+fn selected_tone(ui: &RustyUi, reference: TuningReference) -> Option<ToneGenerator> {
+    let semitone = ui.tone_note_dropdown.get_active_id().and_then(|id| id.parse().ok());
+    let octave = ui.tone_octave_dropdown.get_active_id().and_then(|id| id.parse().ok());
+    let waveform = ui.tone_waveform_dropdown.get_active_id().and_then(|id| id.parse().ok());
+    match (semitone, octave, waveform) {
+        (Some(semitone), Some(octave), Some(waveform)) =>
+            Some(ToneGenerator::new(Pitch::from_note(semitone, octave, reference), waveform, SAMPLE_RATE)),
+        _ => None
+    }
+}
+
+fn start_tone(state: &Rc<RefCell<ApplicationState>>, settings: &Arc<RwLock<AnalysisSettings>>) {
+    let reference = settings.read().map(|s| s.reference).unwrap_or_default();
+    let generator = match selected_tone(&state.borrow().ui, reference) {
+        Some(generator) => generator,
+        None => {return;}
+    };
+    let mut state = state.borrow_mut();
+    let result = match state.pa {
+        Some(ref pa) => ::audio::start_playing_default(pa, generator),
+        None => {return;}
+    };
+    let stream = match result {
+        Ok(stream) => Some(stream),
+        Err(e) => {
+            show_status(&state.ui, Some(&format!("Failed to open audio output: {}", e)));
+            None
+        }
+    };
+    state.ui.tone_play_button.set_label(if stream.is_some() { "Stop" } else { "Play" });
+    state.tone_stream = stream;
+}
+
+fn stop_tone(state: &Rc<RefCell<ApplicationState>>) {
+    let mut state = state.borrow_mut();
+    if let Some(ref mut stream) = state.tone_stream {
+        stream.stop().ok();
+    }
+    state.tone_stream = None;
+    state.ui.tone_play_button.set_label("Play");
+}
+
+fn connect_recording_controls(state: &Rc<RefCell<ApplicationState>>, recorder: Arc<RwLock<Option<MidiRecorder>>>) {
+    let ui = &state.borrow().ui;
+
+    let record_state = Rc::clone(state);
+    ui.record_button.connect_clicked(move |button| {
+        let mut recorder = match recorder.write() {
+            Ok(recorder) => recorder,
+            Err(_) => {return;}
+        };
+        match recorder.take() {
+            Some(finished) => {
+                let mut state = record_state.borrow_mut();
+                state.recorded_midi = finished.finish();
+                state.ui.save_midi_button.set_sensitive(!state.recorded_midi.is_empty());
+                button.set_label("Record");
+            },
+            None => {
+                *recorder = Some(MidiRecorder::new());
+                button.set_label("Stop");
+            }
+        }
+    });
+
+    let save_state = Rc::clone(state);
+    ui.save_midi_button.connect_clicked(move |_| {
+        // The dialog runs its own main loop, so don't hold on to the
+        // state while it's open.
+        let events = save_state.borrow().recorded_midi.clone();
+        let window = save_state.borrow().ui.window.clone();
+        let dialog = gtk::FileChooserDialog::new(Some("Save MIDI"), Some(&window), gtk::FileChooserAction::Save);
+        dialog.set_modal(true);
+        dialog.add_buttons(&[("Cancel", gtk::ResponseType::Cancel.into()), ("Save", gtk::ResponseType::Accept.into())]);
+        dialog.set_do_overwrite_confirmation(true);
+        dialog.set_current_name("performance.mid");
+
+        if dialog.run() == gtk::ResponseType::Accept.into() {
+            if let Some(path) = dialog.get_filename() {
+                if let Err(e) = midi::save(&path, &events) {
+                    show_status(&save_state.borrow().ui, Some(&format!("Failed to save {}: {}", path.display(), e)));
+                }
+            }
+        }
+        dialog.destroy();
+    });
+}
+
+fn connect_save_settings_on_close(state: &Rc<RefCell<ApplicationState>>, settings: Arc<RwLock<AnalysisSettings>>, path: Option<PathBuf>) {
+    let path = match path {
+        Some(path) => path,
+        None => return
+    };
+    let window = &state.borrow().ui.window;
+    let closing_state = Rc::clone(state);
+    window.connect_delete_event(move |_, _| {
+        let state = closing_state.borrow();
+        let ui = &state.ui;
+        let config = Config {
+            device: state.microphone.clone(),
+            settings: settings.read().map(|s| *s).unwrap_or_default(),
+            transposition: state.transposition,
+            naming: state.naming,
+            visible_charts: Chart::all().into_iter().filter(|&chart| ui.chart(chart).get_visible()).collect(),
+            window_size: Some(ui.window.get_size())
+        };
+        if let Err(e) = config.save(&path) {
+            writeln!(io::stderr(), "Failed to save settings to {}: {}", path.display(), e).ok();
+        }
+        Inhibit(false)
+    });
 }
 
-fn connect_dropdown_choose_microphone(mic_sender: Sender<Signal>, state: Rc<RefCell<ApplicationState>>) {
+fn connect_dropdown_choose_microphone(state: Rc<RefCell<ApplicationState>>) {
+    refresh_devices(&state);
+
     let dropdown = state.borrow().ui.dropdown.clone();
-    start_listening_current_dropdown_value(&dropdown, mic_sender.clone(), &state);
+    let dropdown_state = Rc::clone(&state);
     dropdown.connect_changed(move |dropdown: &gtk::ComboBoxText| {
-        start_listening_current_dropdown_value(dropdown, mic_sender.clone(), &state)
+        if dropdown_state.borrow().refreshing_devices {
+            return;
+        }
+        if let Some(name) = dropdown.get_active_text() {
+            dropdown_state.borrow_mut().microphone = Some(name);
+        }
+        start_listening_selected_microphone(&dropdown_state);
     });
+
+    let button = state.borrow().ui.refresh_devices_button.clone();
+    button.connect_clicked(move |_| {
+        refresh_devices(&state);
+    });
+}
+
+// PortAudio only looks for devices when it starts, so this closes
+// every stream and restarts it, then reopens the chosen microphone if
+// it's still there.
+fn refresh_devices(state: &Rc<RefCell<ApplicationState>>) {
+    stop_tone(state);
+    {
+        let mut state = state.borrow_mut();
+        if let Some(ref mut stream) = state.pa_stream {
+            stream.stop().ok();
+        }
+        state.pa_stream = None;
+        state.pa = None;
+        match ::audio::init() {
+            Ok(pa) => state.pa = Some(pa),
+            Err(e) => {
+                show_status(&state.ui, Some(&format!("Failed to start audio: {}", e)));
+                return;
+            }
+        }
+    }
+
+    let (devices, default_device) = {
+        let state = state.borrow();
+        let pa = match state.pa {
+            Some(ref pa) => pa,
+            None => {return;}
+        };
+        (::audio::get_device_list(pa), ::audio::get_default_device(pa).ok())
+    };
+    let devices = match devices {
+        Ok(devices) => devices,
+        Err(e) => {
+            show_status(&state.borrow().ui, Some(&format!("Failed to list audio devices: {}", e)));
+            return;
+        }
+    };
+    // Nothing is selected if the chosen device has gone, rather than
+    // quietly switching to another microphone.
+    let selected = match state.borrow().microphone {
+        Some(ref microphone) => devices.iter()
+            .find(|&&(_, ref name)| name == microphone)
+            .map(|&(index, _)| index),
+        None => default_device
+    };
+
+    let dropdown = state.borrow().ui.dropdown.clone();
+    state.borrow_mut().refreshing_devices = true;
+    dropdown.remove_all();
+    set_dropdown_items(&dropdown, devices, selected);
+    state.borrow_mut().refreshing_devices = false;
+
+    start_listening_selected_microphone(state);
 }
 
-fn start_listening_current_dropdown_value(dropdown: &gtk::ComboBoxText, mic_sender: Sender<Signal>, state: &Rc<RefCell<ApplicationState>>) {
-    if let Some(ref mut stream) = state.borrow_mut().pa_stream {
+fn start_listening_selected_microphone(state: &Rc<RefCell<ApplicationState>>) {
+    let mut state = state.borrow_mut();
+    if let Some(ref mut stream) = state.pa_stream {
         stream.stop().ok();
     }
-    let selected_mic = match dropdown.get_active_id().and_then(|id| id.parse().ok()) {
+    state.pa_stream = None;
+
+    let selected_mic = match state.ui.dropdown.get_active_id().and_then(|id| id.parse().ok()) {
         Some(mic) => mic,
+        None => {
+            let message = match state.microphone {
+                Some(ref name) => AudioError::DeviceNotFound(name.clone()).to_string(),
+                None => "No input devices found".to_string()
+            };
+            show_status(&state.ui, Some(&message));
+            return;
+        }
+    };
+    let result = match state.pa {
+        Some(ref pa) => ::audio::start_listening(pa, selected_mic, state.mic_sender.clone()),
         None => {return;}
     };
-    let stream = ::audio::start_listening(&state.borrow().pa, selected_mic, mic_sender).ok();
-    if stream.is_none() {
-        writeln!(io::stderr(), "Failed to open audio channel").ok();
+    match result {
+        Ok(stream) => {
+            show_status(&state.ui, None);
+            state.pa_stream = Some(stream);
+        },
+        Err(e) => {
+            let name = state.ui.dropdown.get_active_text().unwrap_or_default();
+            show_status(&state.ui, Some(&format!("Failed to open {}: {}", name, e)));
+        }
     }
-    state.borrow_mut().pa_stream = stream;
 }
 
-fn start_processing_audio(mic_receiver: Receiver<Signal>, cross_thread_state: Arc<RwLock<Model>>) {
+// Most devices don't report an error when they're unplugged, their
+// stream just stops calling back, so a stream that hasn't delivered
+// anything since the last check is treated as dead. While there's no
+// stream, this keeps looking for the chosen device to come back.
+fn setup_audio_watchdog(state: Rc<RefCell<ApplicationState>>, audio_buffers: Arc<AtomicUsize>) {
+    let last_buffers = Cell::new(audio_buffers.load(Ordering::Relaxed));
+    // checks to wait between looking for the device, and until the next look
+    let backoff = Cell::new(1);
+    let next_look = Cell::new(1);
+    gtk::timeout_add(AUDIO_WATCHDOG_MS, move || {
+        let buffers = audio_buffers.load(Ordering::Relaxed);
+        let receiving = buffers != last_buffers.get();
+        last_buffers.set(buffers);
+
+        let (alive, playing_tone) = {
+            let state = state.borrow();
+            let alive = state.pa_stream.as_ref()
+                .map(|stream| receiving && stream.is_active().unwrap_or(false));
+            (alive, state.tone_stream.is_some())
+        };
+        match alive {
+            Some(true) => {
+                backoff.set(1);
+                next_look.set(1);
+            },
+            Some(false) => {
+                refresh_devices(&state);
+                // Only say so if reopening didn't work; a stream that
+                // comes straight back isn't worth interrupting for.
+                if state.borrow().pa_stream.is_none() {
+                    let name = state.borrow().microphone.clone().unwrap_or_default();
+                    show_status(&state.borrow().ui, Some(&format!("{} ({}), waiting for it to come back", AudioError::StreamDied, name)));
+                }
+            },
+            // restarting PortAudio would cut off the drone
+            None if playing_tone => {},
+            None => {
+                next_look.set(next_look.get() - 1);
+                if next_look.get() == 0 {
+                    refresh_devices(&state);
+                    backoff.set((backoff.get() * 2).min(AUDIO_WATCHDOG_MAX_BACKOFF));
+                    next_look.set(backoff.get());
+                }
+            }
+        }
+        gtk::Continue(true)
+    });
+}
+
+fn start_processing_audio(mic_receiver: Receiver<Signal>, audio_buffers: Arc<AtomicUsize>, cross_thread_state: Arc<RwLock<Model>>, history: Arc<RwLock<PitchHistory>>, dynamics: Arc<RwLock<DynamicsTracker>>, spectrogram: Arc<RwLock<Spectrogram>>, recorder: Arc<RwLock<Option<MidiRecorder>>>, settings: Arc<RwLock<AnalysisSettings>>) {
     thread::spawn(move || {
+        let mut window = SlidingWindow::default();
+        let mut smoother = PitchSmoother::default();
+        // models waiting on the smoother, oldest first
+        let mut pending: VecDeque<(f32, Model)> = VecDeque::new();
         while let Ok(signal) = mic_receiver.recv() {
+            audio_buffers.fetch_add(1, Ordering::Relaxed);
+            window.push(&signal.samples);
             //just in case we hit performance difficulties, clear out the channel
-            while mic_receiver.try_recv().is_ok() {}
-            
-            let new_model = Model::from_signal(signal);
+            while let Ok(newer_signal) = mic_receiver.try_recv() {
+                window.push(&newer_signal.samples);
+            }
+            let frame = match window.latest_frame(signal.sample_rate) {
+                Some(frame) => frame,
+                None => continue
+            };
+
+            // time is counted in samples received, rather than wall
+            // clock, so that the history isn't affected by processing
+            // delays
+            let time = frame.centre_time();
+            let settings = settings.read().map(|s| *s).unwrap_or_default();
+            let new_model = Model::from_signal(frame.signal, &settings);
+
+            // Smoothing holds each pitch back by its latency, so the
+            // model is held back with it, and everything shown and
+            // recorded comes from the frame the smoothed pitch did.
+            smoother.set_smoothing(settings.smoothing);
+            let smoothed = smoother.push(time, new_model.pitch);
+            pending.push_back((time, new_model));
+            let smoothed = match smoothed {
+                Some(smoothed) => smoothed,
+                None => continue
+            };
+            // Changing the smoothing drops the frames the smoother was
+            // holding, so their models go too.
+            while pending.front().is_some_and(|&(time, _)| time < smoothed.time) {
+                pending.pop_front();
+            }
+            let (time, mut new_model) = match pending.pop_front() {
+                Some(frame) => frame,
+                None => continue
+            };
+            new_model.pitch = smoothed.pitch;
+
+            if let Ok(mut history) = history.write() {
+                history.push(time, new_model.pitch);
+            }
+            if let Ok(mut dynamics) = dynamics.write() {
+                dynamics.update(time, &new_model);
+            }
+            if let Ok(mut spectrogram) = spectrogram.write() {
+                spectrogram.push(Spectrum::from_signal(&new_model.signal));
+            }
+            if let Ok(mut recorder) = recorder.write() {
+                if let Some(ref mut recorder) = *recorder {
+                    recorder.record_model(time, &new_model);
+                }
+            }
 
             match cross_thread_state.write() {
                 Ok(mut model) => {
@@ -170,14 +1049,37 @@ fn start_processing_audio(mic_receiver: Receiver<Signal>, cross_thread_state: Ar
     });
 }
 
-fn setup_pitch_label_callbacks(state: Rc<RefCell<ApplicationState>>, cross_thread_state: Arc<RwLock<Model>>) {
+fn setup_pitch_label_callbacks(state: Rc<RefCell<ApplicationState>>, cross_thread_state: Arc<RwLock<Model>>, history: Arc<RwLock<PitchHistory>>, dynamics: Arc<RwLock<DynamicsTracker>>) {
     gtk::timeout_add(1000/FPS, move || {
-        let ui = &state.borrow().ui;
+        let state = state.borrow();
+        let ui = &state.ui;
         if let Ok(cross_thread_state) = cross_thread_state.read() {
-            ui.pitch_label.set_label(&cross_thread_state.pitch_display());
+            ui.pitch_label.set_label(&cross_thread_state.pitch_display(state.transposition, &state.naming));
+            // fade uncertain readings rather than letting them flicker
+            ui.pitch_label.set_opacity(0.2 + 0.8 * f64::from(cross_thread_state.estimate.clarity));
             ui.pitch_error_indicator.queue_draw();
+            ui.level_meter.queue_draw();
             ui.oscilloscope_chart.queue_draw();
             ui.correlation_chart.queue_draw();
+            ui.history_chart.queue_draw();
+            ui.dynamics_chart.queue_draw();
+            ui.spectrum_chart.queue_draw();
+            ui.spectrogram_chart.queue_draw();
+        }
+        if let Ok(history) = history.read() {
+            // a couple of seconds is long enough to see several cycles
+            // without lagging too far behind changes
+            let vibrato = Vibrato::from_history(&history, 2.0);
+            ui.vibrato_label.set_label(&vibrato.map_or(String::new(), |v| format!("{}", v)));
+        }
+        if let Ok(dynamics) = dynamics.read() {
+            // a warning while it's happening, then the summary of the
+            // last swell until the next one finishes
+            let text = match dynamics.current_drift() {
+                Some(drift) => format!("Pitch has moved {:+.0} cents in this swell", drift),
+                None => dynamics.swells().back().map_or(String::new(), |s| format!("{}", s))
+            };
+            ui.dynamics_label.set_label(&text);
         }
 
         gtk::Continue(true)
@@ -223,6 +1125,62 @@ fn setup_pitch_error_indicator_callbacks(state: &Rc<RefCell<ApplicationState>>,
     });
 }
 
+fn setup_level_meter_callbacks(state: &Rc<RefCell<ApplicationState>>, cross_thread_state: Arc<RwLock<Model>>, settings: Arc<RwLock<AnalysisSettings>>) {
+    let canvas = &state.borrow().ui.level_meter;
+    let peak_hold = RefCell::new((PeakHold::default(), Instant::now()));
+    canvas.connect_draw(move |canvas, context| {
+        let width = f64::from(canvas.get_allocated_width());
+        let height = f64::from(canvas.get_allocated_height());
+        let x_for_db = |db: f32| f64::from((db - METER_MIN_DB) / -METER_MIN_DB).clamp(0.0, 1.0) * width;
+
+        let (rms_db, peak_db) = match cross_thread_state.read() {
+            Ok(model) => (model.signal.rms_dbfs(), model.signal.peak_dbfs()),
+            Err(_) => return gtk::Inhibit(false)
+        };
+        let held_db = {
+            let mut peak_hold = peak_hold.borrow_mut();
+            let elapsed = peak_hold.1.elapsed();
+            peak_hold.1 = Instant::now();
+            let elapsed = elapsed.as_secs() as f32 + elapsed.subsec_nanos() as f32 / 1_000_000_000.0;
+            peak_hold.0.update(peak_db, elapsed)
+        };
+        let gate_db = settings.read().map(|s| s.noise_gate_db).unwrap_or(DEFAULT_NOISE_GATE_DB);
+
+        //peak behind the rms, both greyed out when gated
+        let gated = peak_db <= gate_db;
+        let (r, g, b) = if gated { (0.6, 0.6, 0.6) } else { (0.2, 0.7, 0.2) };
+        context.set_source_rgb(r * 0.5 + 0.5, g * 0.5 + 0.5, b * 0.5 + 0.5);
+        context.rectangle(0.0, 0.0, x_for_db(peak_db), height);
+        context.fill();
+        context.set_source_rgb(r, g, b);
+        context.rectangle(0.0, 0.0, x_for_db(rms_db), height);
+        context.fill();
+
+        //peak hold, red if it's clipping
+        if held_db >= -0.1 {
+            context.set_source_rgb(0.9, 0.0, 0.0);
+        }
+        else {
+            context.set_source_rgb(0.0, 0.0, 0.0);
+        }
+        context.rectangle(x_for_db(held_db) - 1.0, 0.0, 2.0, height);
+        context.fill();
+
+        //noise gate
+        context.set_source_rgb(0.0, 0.0, 0.0);
+        context.new_path();
+        context.move_to(x_for_db(gate_db), 0.0);
+        context.line_to(x_for_db(gate_db), height);
+        context.stroke();
+
+        context.set_font_size(12.0);
+        context.move_to(4.0, height - 6.0);
+        context.show_text(&format!("{:.1} dBFS RMS, peak {:.1}", rms_db, held_db));
+
+        gtk::Inhibit(false)
+    });
+}
+
 fn setup_oscilloscope_drawing_area_callbacks(state: &Rc<RefCell<ApplicationState>>, cross_thread_state: Arc<RwLock<Model>>) {
     let canvas = &state.borrow().ui.oscilloscope_chart;
     canvas.connect_draw(move |canvas, context| {
@@ -298,10 +1256,240 @@ fn setup_correlation_drawing_area_callbacks(state: &Rc<RefCell<ApplicationState>
     });
 }
 
+fn setup_history_drawing_area_callbacks(state: &Rc<RefCell<ApplicationState>>, history: Arc<RwLock<PitchHistory>>) {
+    let canvas = &state.borrow().ui.history_chart;
+    let naming_state = Rc::clone(state);
+    canvas.connect_draw(move |canvas, context| {
+        let width = f64::from(canvas.get_allocated_width());
+        let height = f64::from(canvas.get_allocated_height());
+        let mid_height = height / 2.0;
+
+        //draw in tune
+        context.new_path();
+        context.move_to(0.0, mid_height);
+        context.line_to(width, mid_height);
+        context.stroke();
+
+        if let Ok(history) = history.read() {
+            let latest = match history.latest_time() {
+                Some(latest) => f64::from(latest),
+                None => return gtk::Inhibit(false)
+            };
+            let duration = f64::from(history.duration);
+            let x_for_time = |time: f32| (f64::from(time) - latest + duration) * width / duration;
+            let y_for_cents = |cents: f32| mid_height - f64::from(cents) * mid_height / 50.0;
+
+            //draw a labelled gridline where each note starts
+            let (transposition, naming) = {
+                let state = naming_state.borrow();
+                (state.transposition, state.naming)
+            };
+            context.set_source_rgb(0.6, 0.6, 0.6);
+            context.set_font_size(12.0);
+            for segment in history.note_segments() {
+                let x = x_for_time(segment.start);
+                context.new_path();
+                context.move_to(x, 0.0);
+                context.line_to(x, height);
+                context.stroke();
+                context.move_to(x + 2.0, 12.0);
+                context.show_text(&segment.pitch.written(transposition).name(&naming));
+            }
+
+            //draw cents error, breaking the line between notes
+            context.set_source_rgb(0.0, 0.0, 0.0);
+            context.new_path();
+            let mut previous_note = None;
+            for point in history.points() {
+                match point.pitch {
+                    Some(pitch) => {
+                        let note = pitch.midi_number().round() as i32;
+                        let x = x_for_time(point.time);
+                        let y = y_for_cents(pitch.cents_error());
+                        if previous_note == Some(note) {
+                            context.line_to(x, y);
+                        }
+                        else {
+                            context.move_to(x, y);
+                        }
+                        previous_note = Some(note);
+                    },
+                    None => {
+                        previous_note = None;
+                    }
+                }
+            }
+            context.stroke();
+        }
+
+        gtk::Inhibit(false)
+    });
+}
+
+fn setup_dynamics_drawing_area_callbacks(state: &Rc<RefCell<ApplicationState>>, dynamics: Arc<RwLock<DynamicsTracker>>) {
+    let canvas = &state.borrow().ui.dynamics_chart;
+    canvas.connect_draw(move |canvas, context| {
+        let width = f64::from(canvas.get_allocated_width());
+        let height = f64::from(canvas.get_allocated_height());
+        let mid_height = height / 2.0;
+
+        if let Ok(dynamics) = dynamics.read() {
+            let latest = match dynamics.latest_time() {
+                Some(latest) => f64::from(latest),
+                None => return gtk::Inhibit(false)
+            };
+            let duration = f64::from(dynamics.duration);
+            let x_for_time = |time: f32| (f64::from(time) - latest + duration) * width / duration;
+            let y_for_cents = |cents: f32| mid_height - f64::from(cents) * mid_height / 50.0;
+            let y_for_db = |db: f32| f64::from(db / METER_MIN_DB).clamp(0.0, 1.0) * height;
+
+            //shade each swell, red if the pitch moved too far
+            for swell in dynamics.swells() {
+                if swell.flagged {
+                    context.set_source_rgb(1.0, 0.8, 0.8);
+                }
+                else {
+                    context.set_source_rgb(0.9, 0.9, 0.9);
+                }
+                let x = x_for_time(swell.start);
+                context.rectangle(x, 0.0, x_for_time(swell.end) - x, height);
+                context.fill();
+            }
+
+            //draw in tune
+            context.set_source_rgb(0.0, 0.0, 0.0);
+            context.new_path();
+            context.move_to(0.0, mid_height);
+            context.line_to(width, mid_height);
+            context.stroke();
+
+            //draw loudness
+            context.set_source_rgb(0.2, 0.4, 0.8);
+            context.new_path();
+            let mut started = false;
+            for point in dynamics.points() {
+                if started {
+                    context.line_to(x_for_time(point.time), y_for_db(point.level_db));
+                }
+                else {
+                    context.move_to(x_for_time(point.time), y_for_db(point.level_db));
+                    started = true;
+                }
+            }
+            context.stroke();
+
+            //draw cents error, breaking the line where there's no pitch
+            context.set_source_rgb(0.0, 0.0, 0.0);
+            context.new_path();
+            let mut voiced = false;
+            for point in dynamics.points() {
+                match point.cents {
+                    Some(cents) if voiced => context.line_to(x_for_time(point.time), y_for_cents(cents)),
+                    Some(cents) => context.move_to(x_for_time(point.time), y_for_cents(cents)),
+                    None => {}
+                }
+                voiced = point.cents.is_some();
+            }
+            context.stroke();
+        }
+
+        gtk::Inhibit(false)
+    });
+}
+
+// Draws the spectrogram's latest column, which comes from the same
+// frame as the model.
+fn setup_spectrum_drawing_area_callbacks(state: &Rc<RefCell<ApplicationState>>, cross_thread_state: Arc<RwLock<Model>>, spectrogram: Arc<RwLock<Spectrogram>>) {
+    let canvas = &state.borrow().ui.spectrum_chart;
+    canvas.connect_draw(move |canvas, context| {
+        let width = f64::from(canvas.get_allocated_width());
+        let height = f64::from(canvas.get_allocated_height());
+        let x_for_hz = |hz: f32| f64::from(spectrum::log_frequency_position(hz, SPECTRUM_MIN_HZ, SPECTRUM_MAX_HZ)) * width;
+        let y_for_db = |db: f32| f64::from(db / SPECTRUM_MIN_DB) * height;
+
+        if let (Ok(cross_thread_state), Ok(spectrogram)) = (cross_thread_state.read(), spectrogram.read()) {
+            let spectrum = match spectrogram.columns().back() {
+                Some(spectrum) => spectrum,
+                None => return gtk::Inhibit(false)
+            };
+
+            //mark where the harmonics of the fundamental should be
+            if let Some(pitch) = cross_thread_state.pitch {
+                context.set_source_rgb(0.8, 0.2, 0.2);
+                for hz in spectrum.harmonics(pitch.hz) {
+                    if hz < SPECTRUM_MIN_HZ || hz > SPECTRUM_MAX_HZ {
+                        continue;
+                    }
+                    context.new_path();
+                    context.move_to(x_for_hz(hz), 0.0);
+                    context.line_to(x_for_hz(hz), height);
+                    context.stroke();
+                }
+            }
+
+            context.set_source_rgb(0.0, 0.0, 0.0);
+            context.new_path();
+            let mut started = false;
+            for (i, &db) in spectrum.decibels.iter().enumerate() {
+                let hz = spectrum.frequency(i);
+                if hz < SPECTRUM_MIN_HZ || hz > SPECTRUM_MAX_HZ {
+                    continue;
+                }
+                if started {
+                    context.line_to(x_for_hz(hz), y_for_db(db));
+                }
+                else {
+                    context.move_to(x_for_hz(hz), y_for_db(db));
+                    started = true;
+                }
+            }
+            context.stroke();
+        }
+
+        gtk::Inhibit(false)
+    });
+}
+
+fn setup_spectrogram_drawing_area_callbacks(state: &Rc<RefCell<ApplicationState>>, spectrogram: Arc<RwLock<Spectrogram>>) {
+    let canvas = &state.borrow().ui.spectrogram_chart;
+    canvas.connect_draw(move |canvas, context| {
+        let width = f64::from(canvas.get_allocated_width());
+        let height = f64::from(canvas.get_allocated_height());
+        // each cell is a couple of pixels high, which is plenty of
+        // detail and keeps the number of rectangles manageable
+        let row_height = 2.0;
+        let rows = (height / row_height).ceil() as usize;
+
+        if let Ok(spectrogram) = spectrogram.read() {
+            let column_width = width / spectrogram.capacity.max(1) as f64;
+            let first_x = width - column_width * spectrogram.columns().len() as f64;
+            for (column, spectrum) in spectrogram.columns().iter().enumerate() {
+                let x = first_x + column as f64 * column_width;
+                for row in 0..rows {
+                    // low frequencies at the bottom
+                    let position = 1.0 - (row as f32 + 0.5) / rows as f32;
+                    let hz = spectrum::log_frequency_at(position, SPECTRUM_MIN_HZ, SPECTRUM_MAX_HZ);
+                    let loudness = (1.0 - spectrum.decibels_at(hz) / SPECTRUM_MIN_DB).clamp(0.0, 1.0);
+                    let shade = 1.0 - f64::from(loudness);
+                    context.set_source_rgb(shade, shade, shade);
+                    context.rectangle(x, row as f64 * row_height, column_width.ceil(), row_height);
+                    context.fill();
+                }
+            }
+        }
+
+        gtk::Inhibit(false)
+    });
+}
+
 fn setup_chart_visibility_callbacks(state: Rc<RefCell<ApplicationState>>) {
     let outer_state = Rc::clone(&state);
     let oscilloscope_toggle_button = &outer_state.borrow().ui.oscilloscope_toggle_button;
     let correlation_toggle_button = &outer_state.borrow().ui.correlation_toggle_button;
+    let history_toggle_button = &outer_state.borrow().ui.history_toggle_button;
+    let dynamics_toggle_button = &outer_state.borrow().ui.dynamics_toggle_button;
+    let spectrum_toggle_button = &outer_state.borrow().ui.spectrum_toggle_button;
+    let spectrogram_toggle_button = &outer_state.borrow().ui.spectrogram_toggle_button;
 
     let oscilloscope_state = Rc::clone(&state);
     oscilloscope_toggle_button.connect_clicked(move |_| {
@@ -309,9 +1497,33 @@ fn setup_chart_visibility_callbacks(state: Rc<RefCell<ApplicationState>>) {
         chart.set_visible(!chart.get_visible());
     });
 
-    let correlation_state = state;
+    let correlation_state = Rc::clone(&state);
     correlation_toggle_button.connect_clicked(move |_| {
         let chart = &correlation_state.borrow().ui.correlation_chart;
         chart.set_visible(!chart.get_visible());
     });
+
+    let history_state = Rc::clone(&state);
+    history_toggle_button.connect_clicked(move |_| {
+        let chart = &history_state.borrow().ui.history_chart;
+        chart.set_visible(!chart.get_visible());
+    });
+
+    let dynamics_state = Rc::clone(&state);
+    dynamics_toggle_button.connect_clicked(move |_| {
+        let ui = &dynamics_state.borrow().ui;
+        ui.set_chart_visible(Chart::Dynamics, !ui.dynamics_chart.get_visible());
+    });
+
+    let spectrum_state = Rc::clone(&state);
+    spectrum_toggle_button.connect_clicked(move |_| {
+        let chart = &spectrum_state.borrow().ui.spectrum_chart;
+        chart.set_visible(!chart.get_visible());
+    });
+
+    let spectrogram_state = state;
+    spectrogram_toggle_button.connect_clicked(move |_| {
+        let chart = &spectrogram_state.borrow().ui.spectrogram_chart;
+        chart.set_visible(!chart.get_visible());
+    });
 }
@@ -0,0 +1,123 @@
+use std::collections::VecDeque;
+
+use pitch::Pitch;
+
+#[derive(Debug, Clone, Copy)]
+pub struct HistoryPoint {
+    pub time: f32,
+    pub pitch: Option<Pitch>
+}
+
+// A run of consecutive points that are all closest to the same note.
+#[derive(Debug, Clone, Copy)]
+pub struct NoteSegment {
+    pub start: f32,
+    pub end: f32,
+    pub note: i32,
+    pub pitch: Pitch
+}
+
+#[derive(Debug, Clone)]
+pub struct PitchHistory {
+    // seconds of history to keep, counted back from the latest point
+    pub duration: f32,
+    points: VecDeque<HistoryPoint>
+}
+
+impl PitchHistory {
+    pub fn new(duration: f32) -> PitchHistory {
+        PitchHistory {
+            duration: duration,
+            points: VecDeque::new()
+        }
+    }
+
+    pub fn push(&mut self, time: f32, pitch: Option<Pitch>) {
+        self.points.push_back(HistoryPoint {
+            time: time,
+            pitch: pitch
+        });
+
+        let oldest_allowed = time - self.duration;
+        while self.points.front().is_some_and(|p| p.time < oldest_allowed) {
+            self.points.pop_front();
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.points.clear();
+    }
+
+    pub fn points(&self) -> &VecDeque<HistoryPoint> {
+        &self.points
+    }
+
+    pub fn latest_time(&self) -> Option<f32> {
+        self.points.back().map(|p| p.time)
+    }
+
+    pub fn note_segments(&self) -> Vec<NoteSegment> {
+        let mut segments: Vec<NoteSegment> = Vec::new();
+        let mut previous_voiced = false;
+        for point in &self.points {
+            match point.pitch {
+                Some(pitch) => {
+                    let note = pitch.midi_number().round() as i32;
+                    match segments.last_mut() {
+                        Some(ref mut segment) if previous_voiced && segment.note == note => {
+                            segment.end = point.time;
+                        },
+                        _ => segments.push(NoteSegment {
+                            start: point.time,
+                            end: point.time,
+                            note: note,
+                            pitch: pitch
+                        })
+                    }
+                    previous_voiced = true;
+                },
+                None => {
+                    previous_voiced = false;
+                }
+            }
+        }
+        segments
+    }
+}
+
+impl Default for PitchHistory {
+    fn default() -> PitchHistory {
+        PitchHistory::new(10.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn old_points_are_dropped() {
+        let mut history = PitchHistory::new(1.0);
+        for i in 0..12 {
+            history.push(i as f32 * 0.25, Some(Pitch::new(440.0)));
+        }
+        assert_eq!(history.latest_time(), Some(2.75));
+        let times: Vec<f32> = history.points().iter().map(|p| p.time).collect();
+        assert_eq!(times, vec!(1.75, 2.0, 2.25, 2.5, 2.75));
+    }
+
+    #[test]
+    fn segments_split_on_note_changes_and_silence() {
+        let mut history = PitchHistory::new(10.0);
+        history.push(0.0, Some(Pitch::new(440.0)));
+        history.push(0.1, Some(Pitch::new(445.0)));
+        history.push(0.2, Some(Pitch::new(493.88)));
+        history.push(0.3, None);
+        history.push(0.4, Some(Pitch::new(493.88)));
+
+        let segments: Vec<(f32, f32, i32)> = history.note_segments().iter()
+            .map(|s| (s.start, s.end, s.note))
+            .collect();
+        assert_eq!(segments, vec!((0.0, 0.1, 69), (0.2, 0.2, 71), (0.4, 0.4, 71)));
+    }
+}
@@ -0,0 +1,174 @@
+use std::fmt;
+use std::str::FromStr;
+
+use detector::FrequencyRange;
+
+// What's being tuned, which bounds the fundamentals the detector will
+// consider. Ranges are the concert pitch of each instrument's lowest
+// and highest usual notes, with a couple of semitones spare either
+// side for pedal tones, extreme registers and out of tune playing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Instrument {
+    #[default]
+    Any,
+    Voice,
+    Flute,
+    Clarinet,
+    Saxophone,
+    Trumpet,
+    Horn,
+    Trombone,
+    Tuba,
+    Violin,
+    Cello
+}
+
+impl Instrument {
+    pub fn all() -> Vec<Instrument> {
+        vec!(
+            Instrument::Any,
+            Instrument::Voice,
+            Instrument::Flute,
+            Instrument::Clarinet,
+            Instrument::Saxophone,
+            Instrument::Trumpet,
+            Instrument::Horn,
+            Instrument::Trombone,
+            Instrument::Tuba,
+            Instrument::Violin,
+            Instrument::Cello
+        )
+    }
+
+    pub fn id(&self) -> &'static str {
+        match *self {
+            Instrument::Any => "any",
+            Instrument::Voice => "voice",
+            Instrument::Flute => "flute",
+            Instrument::Clarinet => "clarinet",
+            Instrument::Saxophone => "saxophone",
+            Instrument::Trumpet => "trumpet",
+            Instrument::Horn => "horn",
+            Instrument::Trombone => "trombone",
+            Instrument::Tuba => "tuba",
+            Instrument::Violin => "violin",
+            Instrument::Cello => "cello"
+        }
+    }
+
+    pub fn range(&self) -> FrequencyRange {
+        match *self {
+            Instrument::Any => FrequencyRange::default(),
+            // bass E2 to soprano C6
+            Instrument::Voice => FrequencyRange::new(70.0, 1200.0),
+            // C4 to D7
+            Instrument::Flute => FrequencyRange::new(230.0, 2650.0),
+            // B♭ clarinet, D3 to B♭6
+            Instrument::Clarinet => FrequencyRange::new(130.0, 2100.0),
+            // baritone A1 to soprano E♭6
+            Instrument::Saxophone => FrequencyRange::new(50.0, 1400.0),
+            // B♭ trumpet, E3 to D6
+            Instrument::Trumpet => FrequencyRange::new(145.0, 1300.0),
+            // F horn, B1 to F5
+            Instrument::Horn => FrequencyRange::new(55.0, 800.0),
+            // pedal B♭1 to F5
+            Instrument::Trombone => FrequencyRange::new(50.0, 800.0),
+            // pedal B♭0 to F4
+            Instrument::Tuba => FrequencyRange::new(25.0, 400.0),
+            // G3 to A7
+            Instrument::Violin => FrequencyRange::new(175.0, 3950.0),
+            // C2 to A5
+            Instrument::Cello => FrequencyRange::new(58.0, 1000.0)
+        }
+    }
+}
+
+impl fmt::Display for Instrument {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match *self {
+            Instrument::Any => "Any instrument",
+            Instrument::Voice => "Voice",
+            Instrument::Flute => "Flute",
+            Instrument::Clarinet => "Clarinet",
+            Instrument::Saxophone => "Saxophone",
+            Instrument::Trumpet => "Trumpet",
+            Instrument::Horn => "Horn",
+            Instrument::Trombone => "Trombone",
+            Instrument::Tuba => "Tuba",
+            Instrument::Violin => "Violin",
+            Instrument::Cello => "Cello"
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for Instrument {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Instrument, String> {
+        Instrument::all().into_iter()
+            .find(|i| i.id() == s.to_lowercase())
+            .ok_or_else(|| format!("Unknown instrument: {}", s))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use detector::{Detector, PitchDetector, Rejection};
+    use signal::Signal;
+    use test_signals;
+
+    fn sample_harmonics(frequency: f32, harmonics: &[f32]) -> Signal {
+        test_signals::sample_harmonics(frequency, harmonics, 4096)
+    }
+
+    #[test]
+    fn instruments_parse_from_their_ids() {
+        for instrument in Instrument::all() {
+            assert_eq!(instrument.id().parse::<Instrument>(), Ok(instrument));
+        }
+        assert!("kazoo".parse::<Instrument>().is_err());
+    }
+
+    #[test]
+    fn ranges_are_ordered() {
+        for instrument in Instrument::all() {
+            let range = instrument.range();
+            assert!(range.min_hz < range.max_hz, "{}", instrument);
+        }
+    }
+
+    #[test]
+    fn tuba_range_rejects_octave_errors() {
+        // B♭3 with a weak fundamental and strong even harmonics, which
+        // YIN and MPM both read as the octave above when unbounded
+        let signal = sample_harmonics(233.08, &[0.1, 1.0, 0.1, 0.5]);
+        let unbounded = Detector::Yin.find_fundamental_frequency(&signal);
+        assert!((unbounded.hz - 466.16).abs() < 3.0, "Expected an octave error, got {}", unbounded.hz);
+
+        for detector in Detector::all() {
+            let estimate = detector.find_fundamental_frequency_in(&signal, Instrument::Tuba.range());
+            let pitch = estimate.pitch()
+                .unwrap_or_else(|| panic!("{} found no pitch: {:?}", detector, estimate.rejection));
+            assert!((pitch.hz - 233.08).abs() < 2.0, "detector={}, hz={}", detector, pitch.hz);
+        }
+    }
+
+    #[test]
+    fn notes_outside_the_range_are_not_accepted() {
+        // an A2 is far below anything a flute can play
+        let signal = sample_harmonics(110.0, &[1.0, 0.5, 0.3]);
+        for detector in Detector::all() {
+            let estimate = detector.find_fundamental_frequency_in(&signal, Instrument::Flute.range());
+            assert!(estimate.pitch().is_none(), "detector={}, hz={}", detector, estimate.hz);
+        }
+    }
+
+    #[test]
+    fn out_of_range_estimates_are_rejected() {
+        let signal = sample_harmonics(440.0, &[1.0]);
+        let estimate = Detector::Mcleod.find_fundamental_frequency_in(&signal, FrequencyRange::new(440.5, 1000.0));
+        assert_eq!(estimate.rejection, Some(Rejection::OutOfRange));
+    }
+}
@@ -0,0 +1,82 @@
+use signal::MIN_DBFS;
+
+// The highest recent peak, as shown on a level meter. It holds still
+// for a moment so the eye can catch it, then falls back steadily.
+#[derive(Debug, Clone, Copy)]
+pub struct PeakHold {
+    // seconds before a held peak starts to fall
+    pub hold: f32,
+    // dB per second
+    pub fall_rate: f32,
+    level: f32,
+    held_for: f32
+}
+
+impl PeakHold {
+    pub fn new(hold: f32, fall_rate: f32) -> PeakHold {
+        PeakHold {
+            hold: hold,
+            fall_rate: fall_rate,
+            level: MIN_DBFS,
+            held_for: 0.0
+        }
+    }
+
+    // Takes the latest peak in dBFS and the seconds since the last
+    // update, and returns the level to show.
+    pub fn update(&mut self, peak_db: f32, elapsed: f32) -> f32 {
+        if peak_db >= self.level {
+            self.level = peak_db;
+            self.held_for = 0.0;
+        }
+        else {
+            self.held_for += elapsed;
+            if self.held_for > self.hold {
+                let falling_for = (self.held_for - self.hold).min(elapsed);
+                self.level = (self.level - falling_for * self.fall_rate).max(peak_db);
+            }
+        }
+        self.level
+    }
+
+    pub fn level(&self) -> f32 {
+        self.level
+    }
+}
+
+impl Default for PeakHold {
+    fn default() -> PeakHold {
+        PeakHold::new(1.5, 20.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn louder_peaks_are_taken_straight_away() {
+        let mut hold = PeakHold::default();
+        assert_eq!(hold.update(-30.0, 0.1), -30.0);
+        assert_eq!(hold.update(-10.0, 0.1), -10.0);
+    }
+
+    #[test]
+    fn peaks_hold_then_fall() {
+        let mut hold = PeakHold::new(1.0, 10.0);
+        hold.update(-10.0, 0.0);
+        assert_eq!(hold.update(-50.0, 0.5), -10.0);
+        assert_eq!(hold.update(-50.0, 0.5), -10.0);
+        // half a second into the fall
+        assert_eq!(hold.update(-50.0, 0.5), -15.0);
+        assert_eq!(hold.update(-50.0, 0.5), -20.0);
+    }
+
+    #[test]
+    fn falling_stops_at_the_current_peak() {
+        let mut hold = PeakHold::new(0.0, 100.0);
+        hold.update(-10.0, 0.0);
+        assert_eq!(hold.update(-20.0, 1.0), -20.0);
+        assert_eq!(hold.level(), -20.0);
+    }
+}
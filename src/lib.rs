@@ -1,7 +1,29 @@
 pub mod model;
 pub mod signal;
+pub mod level;
+pub mod frames;
 pub mod correlation;
+pub mod fft;
+pub mod spectrum;
 pub mod pitch;
+pub mod scala;
+pub mod detector;
+pub mod instrument;
+pub mod yin;
+pub mod mcleod;
+pub mod smoothing;
+pub mod analyzer;
+pub mod wav;
+pub mod track;
+pub mod history;
+pub mod vibrato;
+pub mod dynamics;
+pub mod notes;
+pub mod midi;
+pub mod tone;
+
+#[cfg(test)]
+mod test_signals;
 
 #[cfg(not(target_arch = "wasm32"))]
 extern crate gtk;
@@ -9,11 +31,17 @@ extern crate gtk;
 extern crate cairo;
 #[cfg(not(target_arch = "wasm32"))]
 pub mod gui;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod config;
 
 #[cfg(not(target_arch = "wasm32"))]
 extern crate portaudio;
 #[cfg(not(target_arch = "wasm32"))]
 pub mod audio;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod cli;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod c_api;
 
 #[cfg(target_arch = "wasm32")]
 pub mod wasm_api;
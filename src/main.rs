@@ -3,11 +3,13 @@ extern crate rusty_microphone;
 #[cfg(not(target_arch = "wasm32"))]
 fn main() {
     use rusty_microphone::*;
+    use std::env;
+    use std::process;
 
-    let gui_result = gui::start_gui();
-    if gui_result.is_err() {
-        println!("Failed to initialize");
-        return;
+    let args: Vec<String> = env::args().skip(1).collect();
+    if let Err(err) = cli::run(&args) {
+        eprintln!("{}", err);
+        process::exit(err.exit_code());
     }
 }
 
@@ -0,0 +1,97 @@
+use signal::{Signal, MIN_DBFS};
+use detector::{FrequencyRange, PitchDetector, PitchEstimate, Rejection, parabolic_interpolation};
+
+// McLeod and Wyvill's "A Smarter Way to Find Pitch". The normalized
+// square difference function is like an autocorrelation scaled to
+// -1..1, so the first key maximum that comes close enough to the
+// highest one can be taken as the period without octave errors.
+#[derive(Debug, Clone, Copy)]
+pub struct Mcleod {
+    pub cutoff: f32,
+    pub min_clarity: f32
+}
+
+impl Default for Mcleod {
+    fn default() -> Mcleod {
+        Mcleod {
+            cutoff: 0.9,
+            min_clarity: 0.5
+        }
+    }
+}
+
+impl Mcleod {
+    pub fn normalized_square_difference(samples: &[f32]) -> Vec<f32> {
+        let max_lag = samples.len() / 2;
+        (0..max_lag).map(|lag| {
+            let (correlation, energy) = samples.iter()
+                .zip(samples.iter().skip(lag))
+                .fold((0.0, 0.0), |(correlation, energy), (x, y)| {
+                    (correlation + x * y, energy + x * x + y * y)
+                });
+            if energy > 0.0 {
+                2.0 * correlation / energy
+            }
+            else {
+                0.0
+            }
+        }).collect()
+    }
+
+    // The highest point in each positive region of the NSDF, ignoring
+    // the region at lag 0.
+    fn key_maxima(nsdf: &[f32]) -> Vec<usize> {
+        let mut maxima = Vec::new();
+        let mut current: Option<usize> = None;
+        let first_negative = match nsdf.iter().position(|&n| n < 0.0) {
+            Some(p) => p,
+            None => return maxima
+        };
+
+        for (i, &n) in nsdf.iter().enumerate().skip(first_negative) {
+            if n > 0.0 {
+                current = match current {
+                    Some(c) if nsdf[c] >= n => Some(c),
+                    _ => Some(i)
+                };
+            }
+            else if let Some(c) = current.take() {
+                maxima.push(c);
+            }
+        }
+        // a region still rising at the end of the buffer has not
+        // necessarily reached its peak yet, so it isn't used.
+        maxima
+    }
+}
+
+impl PitchDetector for Mcleod {
+    fn find_fundamental_frequency_in(&self, signal: &Signal, range: FrequencyRange) -> PitchEstimate {
+        if signal.is_below(MIN_DBFS) {
+            return PitchEstimate::rejected(Rejection::Silence);
+        }
+
+        let nsdf = Mcleod::normalized_square_difference(&signal.samples);
+        let (shortest, longest) = range.lags(signal.sample_rate, nsdf.len());
+        let maxima: Vec<usize> = Mcleod::key_maxima(&nsdf).into_iter()
+            .filter(|&i| i >= shortest && i <= longest)
+            .collect();
+        let highest = maxima.iter().map(|&i| nsdf[i]).fold(0.0, f32::max);
+
+        let peak = match maxima.into_iter().find(|&i| nsdf[i] >= self.cutoff * highest) {
+            Some(peak) => peak,
+            // nothing periodic in range, so it's unvoiced or noise
+            None => return PitchEstimate::rejected(Rejection::Noise)
+        };
+
+        let period = parabolic_interpolation(&nsdf, peak);
+        let hz = signal.sample_rate / period;
+        let clarity = nsdf[peak].clamp(0.0, 1.0);
+        if clarity < self.min_clarity {
+            PitchEstimate::noise(hz, clarity)
+        }
+        else {
+            PitchEstimate::new(hz, clarity)
+        }
+    }
+}
@@ -0,0 +1,527 @@
+use std::error;
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::io::{Read, Write};
+use std::path::Path;
+
+use model::Model;
+use notes::{NoteTracker, NoteEvent};
+use pitch::Pitch;
+use track::TrackPoint;
+
+pub const TICKS_PER_QUARTER: u16 = 480;
+// 120 bpm, which makes a tick a little over a millisecond
+const MICROSECONDS_PER_QUARTER: u32 = 500_000;
+// The pitch bend range synthesizers use unless told otherwise.
+pub const BEND_RANGE_CENTS: f32 = 200.0;
+const BEND_CENTRE: u16 = 8192;
+const BEND_MAX: u16 = 16383;
+
+#[derive(Debug)]
+pub enum MidiError {
+    Io(io::Error),
+    NotMidi,
+    Truncated,
+    UnsupportedDivision(u16)
+}
+
+impl fmt::Display for MidiError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            MidiError::Io(ref e) => write!(f, "{}", e),
+            MidiError::NotMidi => write!(f, "Not a Standard MIDI File"),
+            MidiError::Truncated => write!(f, "MIDI file is truncated"),
+            MidiError::UnsupportedDivision(division) => write!(f, "Unsupported MIDI time division {:#06x}", division)
+        }
+    }
+}
+
+impl error::Error for MidiError {}
+
+impl From<io::Error> for MidiError {
+    fn from(e: io::Error) -> MidiError {
+        MidiError::Io(e)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MidiEvent {
+    NoteOn { note: u8, velocity: u8 },
+    NoteOff { note: u8 },
+    // 14 bit, with 8192 as no bend
+    PitchBend(u16)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TimedEvent {
+    // seconds from the start of the file
+    pub time: f32,
+    pub event: MidiEvent
+}
+
+pub fn bend_from_cents(cents: f32) -> u16 {
+    let bend = f32::from(BEND_CENTRE) + cents / BEND_RANGE_CENTS * f32::from(BEND_CENTRE);
+    bend.round().clamp(0.0, f32::from(BEND_MAX)) as u16
+}
+
+pub fn cents_from_bend(bend: u16) -> f32 {
+    (f32::from(bend) - f32::from(BEND_CENTRE)) / f32::from(BEND_CENTRE) * BEND_RANGE_CENTS
+}
+
+// Maps RMS level to velocity, with -60dB and below as the quietest
+// possible note and full scale as the loudest.
+pub fn velocity_from_level(rms: f32) -> u8 {
+    let db = 20.0 * rms.max(1e-6).log10();
+    (1.0 + (db + 60.0) / 60.0 * 126.0).round().clamp(1.0, 127.0) as u8
+}
+
+fn ticks_per_second(microseconds_per_quarter: u32) -> f64 {
+    f64::from(TICKS_PER_QUARTER) * 1_000_000.0 / f64::from(microseconds_per_quarter)
+}
+
+// Writes a format 0 Standard MIDI File with everything on channel 1.
+// Events don't need to be in order.
+pub fn write<W: Write>(writer: &mut W, events: &[TimedEvent]) -> io::Result<()> {
+    let mut sorted = events.to_vec();
+    sorted.sort_by(|a, b| a.time.partial_cmp(&b.time).unwrap_or(::std::cmp::Ordering::Equal));
+
+    let mut track = Vec::new();
+    // tempo, so that readers agree on how long a tick is
+    track.extend_from_slice(&[0x00, 0xFF, 0x51, 0x03]);
+    track.extend_from_slice(&MICROSECONDS_PER_QUARTER.to_be_bytes()[1..]);
+
+    let ticks_per_second = ticks_per_second(MICROSECONDS_PER_QUARTER);
+    let mut last_tick = 0;
+    for timed in &sorted {
+        let tick = (f64::from(timed.time.max(0.0)) * ticks_per_second).round() as u32;
+        write_variable_length(&mut track, tick - last_tick);
+        last_tick = tick;
+        match timed.event {
+            MidiEvent::NoteOn { note, velocity } => track.extend_from_slice(&[0x90, note & 0x7F, velocity & 0x7F]),
+            MidiEvent::NoteOff { note } => track.extend_from_slice(&[0x80, note & 0x7F, 0x40]),
+            MidiEvent::PitchBend(bend) => track.extend_from_slice(&[0xE0, (bend & 0x7F) as u8, (bend >> 7 & 0x7F) as u8])
+        }
+    }
+    track.extend_from_slice(&[0x00, 0xFF, 0x2F, 0x00]);
+
+    writer.write_all(b"MThd")?;
+    writer.write_all(&6u32.to_be_bytes())?;
+    // format 0, one track
+    writer.write_all(&[0x00, 0x00, 0x00, 0x01])?;
+    writer.write_all(&TICKS_PER_QUARTER.to_be_bytes())?;
+    writer.write_all(b"MTrk")?;
+    writer.write_all(&(track.len() as u32).to_be_bytes())?;
+    writer.write_all(&track)
+}
+
+pub fn save<P: AsRef<Path>>(path: P, events: &[TimedEvent]) -> io::Result<()> {
+    let mut file = io::BufWriter::new(File::create(path)?);
+    write(&mut file, events)?;
+    file.flush()
+}
+
+fn write_variable_length(bytes: &mut Vec<u8>, value: u32) {
+    let mut groups = vec!((value & 0x7F) as u8);
+    let mut rest = value >> 7;
+    while rest > 0 {
+        groups.push((rest & 0x7F) as u8 | 0x80);
+        rest >>= 7;
+    }
+    bytes.extend(groups.iter().rev());
+}
+
+// Reads the note and pitch bend events from every track and channel of
+// a Standard MIDI File, in time order. Everything else is skipped.
+pub fn read<R: Read>(mut reader: R) -> Result<Vec<TimedEvent>, MidiError> {
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes)?;
+
+    if bytes.len() < 14 || &bytes[0..4] != b"MThd" {
+        return Err(MidiError::NotMidi);
+    }
+    let header_length = read_u32(&bytes, 4) as usize;
+    let division = read_u16(&bytes, 12);
+    if division & 0x8000 != 0 || division == 0 {
+        return Err(MidiError::UnsupportedDivision(division));
+    }
+
+    let mut events: Vec<(u32, MidiEvent)> = Vec::new();
+    let mut tempo_changes: Vec<(u32, u32)> = Vec::new();
+    let mut position = 8 + header_length;
+    while position + 8 <= bytes.len() {
+        let length = read_u32(&bytes, position + 4) as usize;
+        let start = position + 8;
+        let end = start + length;
+        if end > bytes.len() {
+            return Err(MidiError::Truncated);
+        }
+        if &bytes[position..position + 4] == b"MTrk" {
+            read_track(&bytes[start..end], &mut events, &mut tempo_changes)?;
+        }
+        position = end;
+    }
+
+    // Ticks are converted to seconds using whichever tempo was in
+    // force, starting from the default of 120 bpm.
+    tempo_changes.sort_by_key(|&(tick, _)| tick);
+    events.sort_by_key(|&(tick, _)| tick);
+    let ticks_per_quarter = f64::from(division);
+    let seconds_at = |tick: u32| {
+        let mut seconds = 0.0;
+        let mut last_tick = 0;
+        let mut tempo = MICROSECONDS_PER_QUARTER;
+        for &(change_tick, new_tempo) in tempo_changes.iter().take_while(|&&(t, _)| t <= tick) {
+            seconds += f64::from(change_tick - last_tick) * f64::from(tempo) / 1_000_000.0 / ticks_per_quarter;
+            last_tick = change_tick;
+            tempo = new_tempo;
+        }
+        seconds + f64::from(tick - last_tick) * f64::from(tempo) / 1_000_000.0 / ticks_per_quarter
+    };
+
+    Ok(events.into_iter()
+       .map(|(tick, event)| TimedEvent {
+           time: seconds_at(tick) as f32,
+           event: event
+       })
+       .collect())
+}
+
+fn read_track(bytes: &[u8], events: &mut Vec<(u32, MidiEvent)>, tempo_changes: &mut Vec<(u32, u32)>) -> Result<(), MidiError> {
+    let mut position = 0;
+    let mut tick = 0;
+    let mut running_status = None;
+    while position < bytes.len() {
+        tick += read_variable_length(bytes, &mut position)?;
+        let mut status = *bytes.get(position).ok_or(MidiError::Truncated)?;
+        if status < 0x80 {
+            // running status, the data byte is the first parameter
+            status = running_status.ok_or(MidiError::NotMidi)?;
+        }
+        else {
+            position += 1;
+        }
+
+        match status {
+            0xFF => {
+                let kind = *bytes.get(position).ok_or(MidiError::Truncated)?;
+                position += 1;
+                let length = read_variable_length(bytes, &mut position)? as usize;
+                let data = bytes.get(position..position + length).ok_or(MidiError::Truncated)?;
+                position += length;
+                match kind {
+                    0x51 if length == 3 => tempo_changes.push((tick, u32::from(data[0]) << 16 | u32::from(data[1]) << 8 | u32::from(data[2]))),
+                    0x2F => return Ok(()),
+                    _ => {}
+                }
+            },
+            0xF0 | 0xF7 => {
+                let length = read_variable_length(bytes, &mut position)? as usize;
+                position += length;
+            },
+            _ => {
+                running_status = Some(status);
+                let data_length = match status & 0xF0 {
+                    0xC0 | 0xD0 => 1,
+                    _ => 2
+                };
+                let data = bytes.get(position..position + data_length).ok_or(MidiError::Truncated)?;
+                position += data_length;
+                let event = match status & 0xF0 {
+                    0x90 if data[1] > 0 => Some(MidiEvent::NoteOn { note: data[0], velocity: data[1] }),
+                    // a note on with no velocity is a note off
+                    0x80 | 0x90 => Some(MidiEvent::NoteOff { note: data[0] }),
+                    0xE0 => Some(MidiEvent::PitchBend(u16::from(data[0]) | u16::from(data[1]) << 7)),
+                    _ => None
+                };
+                if let Some(event) = event {
+                    events.push((tick, event));
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn read_variable_length(bytes: &[u8], position: &mut usize) -> Result<u32, MidiError> {
+    let mut value = 0u32;
+    // at most four bytes, according to the spec
+    for _ in 0..4 {
+        let byte = *bytes.get(*position).ok_or(MidiError::Truncated)?;
+        *position += 1;
+        value = value << 7 | u32::from(byte & 0x7F);
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(MidiError::NotMidi)
+}
+
+fn read_u16(bytes: &[u8], offset: usize) -> u16 {
+    u16::from(bytes[offset]) << 8 | u16::from(bytes[offset + 1])
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from(read_u16(bytes, offset)) << 16 | u32::from(read_u16(bytes, offset + 2))
+}
+
+// Builds up MIDI events from live or recorded analysis. Notes are
+// found with a NoteTracker, velocity comes from the level when the
+// note starts, and pitch bends follow the pitch within each note.
+#[derive(Debug, Clone)]
+pub struct MidiRecorder {
+    tracker: NoteTracker,
+    // subtracted from every time, so the file starts at 0
+    start_time: Option<f32>,
+    last_bend: Option<u16>,
+    events: Vec<TimedEvent>
+}
+
+impl MidiRecorder {
+    // Times are counted from the first thing recorded.
+    pub fn new() -> MidiRecorder {
+        MidiRecorder {
+            tracker: NoteTracker::default(),
+            start_time: None,
+            last_bend: None,
+            events: Vec::new()
+        }
+    }
+
+    pub fn starting_at(start_time: f32) -> MidiRecorder {
+        MidiRecorder {
+            start_time: Some(start_time),
+            ..MidiRecorder::new()
+        }
+    }
+
+    pub fn record_model(&mut self, time: f32, model: &Model) {
+        self.record(time, model.pitch, model.signal.rms());
+    }
+
+    pub fn record(&mut self, time: f32, pitch: Option<Pitch>, level: f32) {
+        let start_time = *self.start_time.get_or_insert(time);
+        let time = time - start_time;
+
+        for event in self.tracker.update_pitch(time, pitch) {
+            match event {
+                NoteEvent::NoteOn { start, note } => {
+                    let bend = pitch.map_or(BEND_CENTRE, |p| bend_from_cents((p.midi_number() - note as f32) * 100.0));
+                    self.push(start, MidiEvent::PitchBend(bend));
+                    self.last_bend = Some(bend);
+                    self.push(start, MidiEvent::NoteOn { note: midi_note(note), velocity: velocity_from_level(level) });
+                },
+                NoteEvent::NoteOff(note) => {
+                    self.push(note.start + note.duration, MidiEvent::NoteOff { note: midi_note(note.note) });
+                }
+            }
+        }
+
+        if let (Some(note), Some(pitch)) = (self.tracker.current_note(), pitch) {
+            let semitones = pitch.midi_number() - note as f32;
+            // Further away than this is a new note the tracker hasn't
+            // confirmed yet, and bending the old note up to it would
+            // leave the new one out of tune once it starts.
+            if semitones.abs() < self.tracker.hold_semitones {
+                let bend = bend_from_cents(semitones * 100.0);
+                // about a cent, any finer is just noise
+                let changed = self.last_bend.is_none_or(|last| (i32::from(bend) - i32::from(last)).abs() >= 41);
+                if changed {
+                    self.push(time, MidiEvent::PitchBend(bend));
+                    self.last_bend = Some(bend);
+                }
+            }
+        }
+    }
+
+    pub fn finish(mut self) -> Vec<TimedEvent> {
+        if let Some(NoteEvent::NoteOff(note)) = self.tracker.finish() {
+            self.push(note.start + note.duration, MidiEvent::NoteOff { note: midi_note(note.note) });
+        }
+        self.events
+    }
+
+    fn push(&mut self, time: f32, event: MidiEvent) {
+        self.events.push(TimedEvent {
+            time: time,
+            event: event
+        });
+    }
+}
+
+impl Default for MidiRecorder {
+    fn default() -> MidiRecorder {
+        MidiRecorder::new()
+    }
+}
+
+fn midi_note(note: i32) -> u8 {
+    note.clamp(0, 127) as u8
+}
+
+pub fn events_from_track(track: &[TrackPoint]) -> Vec<TimedEvent> {
+    let mut recorder = MidiRecorder::starting_at(0.0);
+    for point in track {
+        recorder.record(point.time, point.pitch, point.level);
+    }
+    recorder.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(events: &[TimedEvent]) -> Vec<TimedEvent> {
+        let mut bytes = Vec::new();
+        write(&mut bytes, events).unwrap();
+        read(&bytes[..]).unwrap()
+    }
+
+    fn at(time: f32, event: MidiEvent) -> TimedEvent {
+        TimedEvent {
+            time: time,
+            event: event
+        }
+    }
+
+    #[test]
+    fn events_survive_a_round_trip() {
+        let events = vec!(
+            at(0.0, MidiEvent::PitchBend(bend_from_cents(-12.0))),
+            at(0.0, MidiEvent::NoteOn { note: 58, velocity: 90 }),
+            at(0.25, MidiEvent::PitchBend(bend_from_cents(5.0))),
+            at(1.5, MidiEvent::NoteOff { note: 58 }),
+            at(200.0, MidiEvent::NoteOn { note: 60, velocity: 1 })
+        );
+        let read = round_trip(&events);
+        assert_eq!(read.len(), events.len());
+        for (a, b) in read.iter().zip(events.iter()) {
+            assert_eq!(a.event, b.event);
+            assert!((a.time - b.time).abs() < 0.001, "{} != {}", a.time, b.time);
+        }
+    }
+
+    #[test]
+    fn events_are_written_in_time_order() {
+        let read = round_trip(&[at(1.0, MidiEvent::NoteOff { note: 60 }), at(0.5, MidiEvent::NoteOn { note: 60, velocity: 64 })]);
+        let events: Vec<MidiEvent> = read.iter().map(|e| e.event).collect();
+        assert_eq!(events, vec!(MidiEvent::NoteOn { note: 60, velocity: 64 }, MidiEvent::NoteOff { note: 60 }));
+    }
+
+    #[test]
+    fn header_is_format_zero() {
+        let mut bytes = Vec::new();
+        write(&mut bytes, &[]).unwrap();
+        assert_eq!(&bytes[0..14], &[b'M', b'T', b'h', b'd', 0, 0, 0, 6, 0, 0, 0, 1, 0x01, 0xE0]);
+        assert_eq!(&bytes[14..18], b"MTrk");
+    }
+
+    #[test]
+    fn variable_length_quantities_match_the_spec() {
+        for &(value, ref expected) in &[(0u32, vec!(0x00u8)), (0x7F, vec!(0x7F)), (0x80, vec!(0x81, 0x00)),
+                                        (0x2000, vec!(0xC0, 0x00)), (0x0FFF_FFFF, vec!(0xFF, 0xFF, 0xFF, 0x7F))] {
+            let mut bytes = Vec::new();
+            write_variable_length(&mut bytes, value);
+            assert_eq!(&bytes, expected);
+            let mut position = 0;
+            assert_eq!(read_variable_length(&bytes, &mut position).unwrap(), value);
+        }
+    }
+
+    #[test]
+    fn reads_running_status_and_tempo_changes() {
+        let track = [
+            0x00, 0x90, 60, 100,
+            // running status note on, then a note on with no velocity as the off
+            0x83, 0x60, 62, 100,
+            0x00, 60, 0,
+            // 60 bpm from here
+            0x00, 0xFF, 0x51, 0x03, 0x0F, 0x42, 0x40,
+            0x83, 0x60, 0x80, 62, 0,
+            0x00, 0xFF, 0x2F, 0x00
+        ];
+        let mut bytes = vec!(b'M', b'T', b'h', b'd', 0, 0, 0, 6, 0, 0, 0, 1, 0x01, 0xE0, b'M', b'T', b'r', b'k', 0, 0, 0, track.len() as u8);
+        bytes.extend_from_slice(&track);
+
+        let events = read(&bytes[..]).unwrap();
+        let times: Vec<f32> = events.iter().map(|e| e.time).collect();
+        assert_eq!(times, vec!(0.0, 0.5, 0.5, 1.5));
+        assert_eq!(events[2].event, MidiEvent::NoteOff { note: 60 });
+    }
+
+    #[test]
+    fn rejects_files_that_are_not_midi() {
+        match read(&b"RIFF....WAVEfmt "[..]) {
+            Err(MidiError::NotMidi) => {},
+            other => panic!("Expected NotMidi, got {:?}", other)
+        }
+    }
+
+    #[test]
+    fn bends_and_velocities_are_in_range() {
+        assert_eq!(bend_from_cents(0.0), 8192);
+        assert_eq!(bend_from_cents(-200.0), 0);
+        assert_eq!(bend_from_cents(500.0), 16383);
+        assert!((cents_from_bend(bend_from_cents(-17.0)) - -17.0).abs() < 0.05);
+        assert_eq!(velocity_from_level(1.0), 127);
+        assert_eq!(velocity_from_level(0.0), 1);
+        assert!(velocity_from_level(0.1) > velocity_from_level(0.01));
+    }
+
+    #[test]
+    fn recorded_notes_become_midi_notes() {
+        let track: Vec<TrackPoint> = (0..100)
+            .map(|i| {
+                let time = i as f32 * 0.01;
+                let hz = if time < 0.5 { 440.0 } else { 233.08 * 2.0f32.powf(20.0 / 1200.0) };
+                TrackPoint { time: time, pitch: Some(Pitch::new(hz)), clarity: 1.0, level: 0.1 }
+            })
+            .collect();
+        let read = round_trip(&events_from_track(&track));
+
+        let notes: Vec<MidiEvent> = read.iter()
+            .map(|e| e.event)
+            .filter(|e| !matches!(*e, MidiEvent::PitchBend(_)))
+            .collect();
+        let velocity = velocity_from_level(0.1);
+        assert_eq!(notes, vec!(
+            MidiEvent::NoteOn { note: 69, velocity: velocity },
+            MidiEvent::NoteOff { note: 69 },
+            MidiEvent::NoteOn { note: 58, velocity: velocity },
+            MidiEvent::NoteOff { note: 58 }
+        ));
+
+        let last_bend = read.iter().filter_map(|e| match e.event {
+            MidiEvent::PitchBend(bend) => Some(bend),
+            _ => None
+        }).next_back().unwrap();
+        assert!((cents_from_bend(last_bend) - 20.0).abs() < 0.5);
+    }
+
+    #[test]
+    fn semitone_steps_are_not_bent_from_the_old_note() {
+        // A4 then B♭4, with a little jitter like a real detector gives
+        let track: Vec<TrackPoint> = (0..200)
+            .map(|i| {
+                let time = i as f32 * 0.01;
+                let jitter = if i % 2 == 0 { 1.6 } else { -1.6 };
+                let hz = if time < 1.0 { 440.0 } else { 466.16 * 2.0f32.powf(jitter / 1200.0) };
+                TrackPoint { time: time, pitch: Some(Pitch::new(hz)), clarity: 1.0, level: 0.1 }
+            })
+            .collect();
+        let read = round_trip(&events_from_track(&track));
+
+        let note_on = read.iter()
+            .position(|e| e.event == MidiEvent::NoteOn { note: 70, velocity: velocity_from_level(0.1) })
+            .expect("no B♭ note");
+        let bends: Vec<f32> = read[note_on..].iter()
+            .filter_map(|e| match e.event {
+                MidiEvent::PitchBend(bend) => Some(cents_from_bend(bend)),
+                _ => None
+            })
+            .collect();
+        assert!(!bends.is_empty());
+        // only the jitter, not the semitone up from A4
+        assert!(bends.iter().all(|cents| cents.abs() < 5.0), "{:?}", bends);
+    }
+}
@@ -1,9 +1,38 @@
-use signal::Signal;
+use signal::{Signal, DEFAULT_NOISE_GATE_DB};
 use correlation::Correlation;
-use pitch::Pitch;
+use pitch::{Pitch, TuningReference, Transposition, NoteNaming};
+use detector::{Detector, PitchEstimate, Rejection};
+use instrument::Instrument;
+use smoothing::Smoothing;
+
+#[derive(Debug, Clone, Copy)]
+pub struct AnalysisSettings {
+    pub reference: TuningReference,
+    pub detector: Detector,
+    // bounds the fundamentals the detector will consider
+    pub instrument: Instrument,
+    // signals with a peak below this many dBFS are treated as silence
+    pub noise_gate_db: f32,
+    // Applied to the pitches from a run of frames, by whatever is
+    // feeding them in, since from_signal only sees one frame.
+    pub smoothing: Smoothing
+}
+
+impl Default for AnalysisSettings {
+    fn default() -> AnalysisSettings {
+        AnalysisSettings {
+            reference: TuningReference::default(),
+            detector: Detector::default(),
+            instrument: Instrument::default(),
+            noise_gate_db: DEFAULT_NOISE_GATE_DB,
+            smoothing: Smoothing::default()
+        }
+    }
+}
 
 #[derive(Default)]
 pub struct Model {
+    pub estimate: PitchEstimate,
     pub pitch: Option<Pitch>,
     pub signal: Signal,
     pub correlation: Correlation
@@ -14,18 +43,56 @@ impl Model {
         Model::default()
     }
 
-    pub fn from_signal(signal: Signal) -> Model {
+    pub fn from_signal(signal: Signal, settings: &AnalysisSettings) -> Model {
         let correlation = Correlation::from_signal(&signal);
-        let pitch = correlation.find_fundamental_frequency(&signal);
+        let estimate = if signal.is_below(settings.noise_gate_db) {
+            PitchEstimate::rejected(Rejection::Silence)
+        }
+        else {
+            settings.detector.find_fundamental_frequency_with(&signal, &correlation, settings.instrument.range())
+        };
+        let pitch = estimate.pitch()
+            .map(|p| Pitch::with_reference(p.hz, settings.reference));
         
         Model {
+            estimate: estimate,
             pitch: pitch,
             signal: signal,
             correlation: correlation
         }
     }
 
-    pub fn pitch_display(&self) -> String {
-        self.pitch.map_or(String::new(), |p| format!("{}", p))
+    pub fn pitch_display(&self, transposition: Transposition, naming: &NoteNaming) -> String {
+        self.pitch.map_or(String::new(), |p| p.written(transposition).name(naming))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::PI;
+
+    fn sine(amplitude: f32) -> Signal {
+        let samples: Vec<f32> = (0..2048)
+            .map(|i| amplitude * (2.0 * PI * 440.0 * i as f32 / 44100.0).sin())
+            .collect();
+        Signal::new(&samples, 44100.0)
+    }
+
+    #[test]
+    fn quiet_signals_are_gated_as_silence() {
+        let model = Model::from_signal(sine(0.01), &AnalysisSettings::default());
+        assert_eq!(model.estimate.rejection, Some(Rejection::Silence));
+        assert!(model.pitch.is_none());
+    }
+
+    #[test]
+    fn lowering_the_gate_lets_quiet_signals_through() {
+        let settings = AnalysisSettings {
+            noise_gate_db: -60.0,
+            ..AnalysisSettings::default()
+        };
+        let model = Model::from_signal(sine(0.01), &settings);
+        assert_eq!(model.pitch.map(|p| format!("{}", p)), Some("A 4".to_string()));
     }
 }
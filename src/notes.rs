@@ -0,0 +1,272 @@
+use model::Model;
+use pitch::Pitch;
+use track::TrackPoint;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Note {
+    pub start: f32,
+    pub duration: f32,
+    // nearest midi note number
+    pub note: i32,
+    pub pitch: Pitch,
+    // Cents are measured from the equal tempered note, and not wrapped
+    // at ±50 like Pitch::cents_error, so wide vibrato doesn't jump
+    // from sharp to flat.
+    pub mean_cents: f32,
+    pub cents_std_dev: f32
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum NoteEvent {
+    NoteOn { start: f32, note: i32 },
+    NoteOff(Note)
+}
+
+#[derive(Debug, Clone)]
+struct PitchRun {
+    start: f32,
+    last_voiced: f32,
+    note: i32,
+    pitches: Vec<Pitch>
+}
+
+impl PitchRun {
+    fn new(time: f32, note: i32, pitch: Pitch) -> PitchRun {
+        PitchRun {
+            start: time,
+            last_voiced: time,
+            note: note,
+            pitches: vec!(pitch)
+        }
+    }
+
+    fn push(&mut self, time: f32, pitch: Pitch) {
+        self.last_voiced = time;
+        self.pitches.push(pitch);
+    }
+
+    fn to_note(&self) -> Note {
+        let mut sorted: Vec<f32> = self.pitches.iter().map(|p| p.hz).collect();
+        sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        let median_hz = sorted[sorted.len() / 2];
+
+        let cents: Vec<f32> = self.pitches.iter()
+            .map(|p| (p.midi_number() - self.note as f32) * 100.0)
+            .collect();
+        let mean = cents.iter().sum::<f32>() / cents.len() as f32;
+        let variance = cents.iter().map(|c| (c - mean).powi(2)).sum::<f32>() / cents.len() as f32;
+
+        Note {
+            start: self.start,
+            duration: self.last_voiced - self.start,
+            note: self.note,
+            pitch: Pitch::with_reference(median_hz, self.pitches[0].reference),
+            mean_cents: mean,
+            cents_std_dev: variance.sqrt()
+        }
+    }
+}
+
+// Turns a stream of pitch estimates into notes. A note only starts
+// once a pitch has held for min_duration, only changes when the pitch
+// moves more than hold_semitones from it, and survives dropouts of up
+// to max_gap seconds.
+#[derive(Debug, Clone)]
+pub struct NoteTracker {
+    pub min_duration: f32,
+    pub hold_semitones: f32,
+    pub max_gap: f32,
+    active: Option<PitchRun>,
+    candidate: Option<PitchRun>
+}
+
+impl NoteTracker {
+    pub fn new() -> NoteTracker {
+        NoteTracker::default()
+    }
+
+    // midi number of the note being played, if there is one
+    pub fn current_note(&self) -> Option<i32> {
+        self.active.as_ref().map(|active| active.note)
+    }
+
+    pub fn update(&mut self, time: f32, model: &Model) -> Vec<NoteEvent> {
+        self.update_pitch(time, model.pitch)
+    }
+
+    pub fn update_pitch(&mut self, time: f32, pitch: Option<Pitch>) -> Vec<NoteEvent> {
+        let mut events = Vec::new();
+
+        let gap_too_long = self.active.as_ref().is_some_and(|active| time - active.last_voiced > self.max_gap);
+        if gap_too_long {
+            events.extend(self.finish());
+        }
+
+        let pitch = match pitch {
+            Some(pitch) if pitch.hz.is_finite() && pitch.hz > 0.0 => pitch,
+            _ => {
+                self.candidate = None;
+                return events;
+            }
+        };
+        let midi_number = pitch.midi_number();
+
+        if let Some(ref mut active) = self.active {
+            if (midi_number - active.note as f32).abs() < self.hold_semitones {
+                active.push(time, pitch);
+                self.candidate = None;
+                return events;
+            }
+        }
+
+        let note = midi_number.round() as i32;
+        let same_candidate = self.candidate.as_ref().is_some_and(|c| c.note == note);
+        if same_candidate {
+            if let Some(ref mut candidate) = self.candidate {
+                candidate.push(time, pitch);
+            }
+        }
+        else {
+            self.candidate = Some(PitchRun::new(time, note, pitch));
+        }
+
+        let confirmed = self.candidate.as_ref().is_some_and(|c| c.last_voiced - c.start >= self.min_duration);
+        if confirmed {
+            events.extend(self.finish());
+            let candidate = self.candidate.take().unwrap();
+            events.push(NoteEvent::NoteOn { start: candidate.start, note: candidate.note });
+            self.active = Some(candidate);
+        }
+        events
+    }
+
+    // Ends the current note, if there is one. Call this at the end of
+    // a recording so the last note isn't lost.
+    pub fn finish(&mut self) -> Option<NoteEvent> {
+        self.active.take().map(|active| NoteEvent::NoteOff(active.to_note()))
+    }
+}
+
+impl Default for NoteTracker {
+    fn default() -> NoteTracker {
+        NoteTracker {
+            min_duration: 0.05,
+            hold_semitones: 0.7,
+            max_gap: 0.1,
+            active: None,
+            candidate: None
+        }
+    }
+}
+
+pub fn notes_from_track(track: &[TrackPoint], tracker: &mut NoteTracker) -> Vec<Note> {
+    let mut events: Vec<NoteEvent> = track.iter()
+        .flat_map(|point| tracker.update_pitch(point.time, point.pitch))
+        .collect();
+    events.extend(tracker.finish());
+    events.into_iter()
+        .filter_map(|event| match event {
+            NoteEvent::NoteOff(note) => Some(note),
+            NoteEvent::NoteOn { .. } => None
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::PI;
+
+    // one point every 10ms
+    const STEP: f32 = 0.01;
+
+    fn track<F: Fn(f32) -> Option<f32>>(seconds: f32, hz_at: F) -> Vec<TrackPoint> {
+        (0..(seconds / STEP).round() as usize)
+            .map(|i| {
+                let time = i as f32 * STEP;
+                TrackPoint {
+                    time: time,
+                    pitch: hz_at(time).map(Pitch::new),
+                    clarity: 1.0,
+                    level: 0.5
+                }
+            })
+            .collect()
+    }
+
+    fn cents(hz: f32, cents: f32) -> f32 {
+        hz * 2.0f32.powf(cents / 1200.0)
+    }
+
+    #[test]
+    fn steady_tone_is_one_note() {
+        let notes = notes_from_track(&track(1.0, |_| Some(cents(440.0, 10.0))), &mut NoteTracker::new());
+        assert_eq!(notes.len(), 1);
+        assert_eq!(notes[0].note, 69);
+        assert_eq!(notes[0].start, 0.0);
+        assert!((notes[0].duration - 0.99).abs() < 0.001);
+        assert!((notes[0].mean_cents - 10.0).abs() < 0.1);
+        assert!(notes[0].cents_std_dev < 0.1);
+    }
+
+    #[test]
+    fn vibrato_does_not_split_a_note() {
+        let vibrato = |t: f32| Some(cents(440.0, 60.0 * (2.0 * PI * 5.5 * t).sin()));
+        let notes = notes_from_track(&track(2.0, vibrato), &mut NoteTracker::new());
+        assert_eq!(notes.len(), 1);
+        assert!(notes[0].mean_cents.abs() < 5.0);
+        // the standard deviation of a sine is its amplitude over √2
+        assert!((notes[0].cents_std_dev - 60.0 / 2.0f32.sqrt()).abs() < 3.0, "{}", notes[0].cents_std_dev);
+    }
+
+    #[test]
+    fn brief_dropouts_are_bridged() {
+        let dropout = |t: f32| if t > 0.5 && t < 0.55 { None } else { Some(440.0) };
+        let notes = notes_from_track(&track(1.0, dropout), &mut NoteTracker::new());
+        assert_eq!(notes.len(), 1);
+    }
+
+    #[test]
+    fn rests_split_notes() {
+        let rest = |t: f32| if t > 0.405 && t < 0.595 { None } else { Some(440.0) };
+        let notes = notes_from_track(&track(1.0, rest), &mut NoteTracker::new());
+        let bounds: Vec<(i32, f32)> = notes.iter().map(|n| (n.note, n.start)).collect();
+        assert_eq!(bounds.len(), 2);
+        assert!((notes[0].duration - 0.4).abs() < 0.001);
+        assert!((bounds[1].1 - 0.6).abs() < 0.001);
+    }
+
+    #[test]
+    fn pitch_changes_start_new_notes() {
+        let scale = |t: f32| Some(if t < 0.5 { 440.0 } else { 493.88 });
+        let notes = notes_from_track(&track(1.0, scale), &mut NoteTracker::new());
+        let names: Vec<String> = notes.iter().map(|n| format!("{}", n.pitch)).collect();
+        assert_eq!(names, vec!("A 4", "B 4"));
+        assert!((notes[1].start - 0.5).abs() < 0.001);
+    }
+
+    #[test]
+    fn short_glitches_are_ignored() {
+        let glitch = |t: f32| Some(if t > 0.3 && t < 0.33 { 880.0 } else { 440.0 });
+        let notes = notes_from_track(&track(1.0, glitch), &mut NoteTracker::new());
+        assert_eq!(notes.len(), 1);
+        assert_eq!(notes[0].note, 69);
+    }
+
+    #[test]
+    fn events_come_in_on_off_pairs() {
+        let mut tracker = NoteTracker::new();
+        let mut events = Vec::new();
+        for point in track(1.0, |t| if t < 0.5 { Some(440.0) } else { None }) {
+            events.extend(tracker.update_pitch(point.time, point.pitch));
+        }
+        events.extend(tracker.finish());
+        match events.as_slice() {
+            &[NoteEvent::NoteOn { start, note: 69 }, NoteEvent::NoteOff(note)] => {
+                assert_eq!(start, 0.0);
+                assert_eq!(note.note, 69);
+            },
+            other => panic!("Expected a note on and off, got {:?}", other)
+        }
+    }
+}
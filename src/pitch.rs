@@ -1,72 +1,635 @@
 use std::fmt;
 use std::f32;
+use std::str::FromStr;
+
+use scala::{ScalaTuning, TunedNote};
+
+// How the twelve notes of the octave are tuned against each other.
+// Anything but equal temperament makes some keys purer than others, so
+// it's built up from a tonic.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Temperament {
+    #[default]
+    Equal,
+    JustMajor,
+    JustMinor,
+    Pythagorean,
+    Meantone,
+    Werckmeister,
+    Kirnberger,
+    Vallotti
+}
+
+impl Temperament {
+    pub fn all() -> Vec<Temperament> {
+        vec!(
+            Temperament::Equal,
+            Temperament::JustMajor,
+            Temperament::JustMinor,
+            Temperament::Pythagorean,
+            Temperament::Meantone,
+            Temperament::Werckmeister,
+            Temperament::Kirnberger,
+            Temperament::Vallotti
+        )
+    }
+
+    pub fn id(&self) -> &'static str {
+        match *self {
+            Temperament::Equal => "equal",
+            Temperament::JustMajor => "just-major",
+            Temperament::JustMinor => "just-minor",
+            Temperament::Pythagorean => "pythagorean",
+            Temperament::Meantone => "meantone",
+            Temperament::Werckmeister => "werckmeister",
+            Temperament::Kirnberger => "kirnberger",
+            Temperament::Vallotti => "vallotti"
+        }
+    }
+
+    // Cents above the tonic of each note in the octave, starting with
+    // the tonic itself.
+    pub fn cents_above_tonic(&self) -> [f32; 12] {
+        match *self {
+            Temperament::Equal => {
+                let mut cents = [0.0; 12];
+                for (i, c) in cents.iter_mut().enumerate() {
+                    *c = i as f32 * 100.0;
+                }
+                cents
+            },
+            // 5-limit, from the major triads on the tonic, subdominant
+            // and dominant, with the minor seventh a fourth above the
+            // subdominant
+            Temperament::JustMajor => from_ratios(&[(1, 1), (16, 15), (9, 8), (6, 5), (5, 4), (4, 3), (45, 32), (3, 2), (8, 5), (5, 3), (16, 9), (15, 8)]),
+            // as above, but the minor seventh is the third of the
+            // minor dominant
+            Temperament::JustMinor => from_ratios(&[(1, 1), (16, 15), (9, 8), (6, 5), (5, 4), (4, 3), (45, 32), (3, 2), (8, 5), (5, 3), (9, 5), (15, 8)]),
+            Temperament::Pythagorean => from_fifths(ratio_cents(3, 2)),
+            // quarter comma, so that major thirds are pure
+            Temperament::Meantone => from_fifths(ratio_cents(3, 2) - ratio_cents(81, 80) / 4.0),
+            // Werckmeister III
+            Temperament::Werckmeister => [0.0, 90.225, 192.18, 294.135, 390.225, 498.045, 588.27, 696.09, 792.18, 888.27, 996.09, 1092.18],
+            // Kirnberger III
+            Temperament::Kirnberger => [0.0, 90.225, 193.157, 294.135, 386.314, 498.045, 590.224, 696.578, 792.18, 889.735, 996.09, 1088.269],
+            Temperament::Vallotti => [0.0, 94.135, 196.09, 298.045, 392.18, 501.955, 592.18, 698.045, 796.09, 894.135, 1000.0, 1090.225]
+        }
+    }
+}
+
+fn ratio_cents(numerator: u32, denominator: u32) -> f32 {
+    1200.0 * (numerator as f32 / denominator as f32).log2()
+}
+
+fn from_ratios(ratios: &[(u32, u32); 12]) -> [f32; 12] {
+    let mut cents = [0.0; 12];
+    for (c, &(numerator, denominator)) in cents.iter_mut().zip(ratios.iter()) {
+        *c = ratio_cents(numerator, denominator);
+    }
+    cents
+}
+
+// A chain of equal fifths, from a minor third below the tonic up to
+// an augmented fifth above it, which leaves the wolf fifth between
+// those two.
+fn from_fifths(fifth_cents: f32) -> [f32; 12] {
+    let mut cents = [0.0; 12];
+    for fifths in -3i32..9 {
+        let total = fifths as f32 * fifth_cents;
+        let octaves = (total / 1200.0).floor();
+        let note = (fifths * 7).rem_euclid(12);
+        cents[note as usize] = total - octaves * 1200.0;
+    }
+    cents
+}
+
+impl fmt::Display for Temperament {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match *self {
+            Temperament::Equal => "Equal temperament",
+            Temperament::JustMajor => "Just (major)",
+            Temperament::JustMinor => "Just (minor)",
+            Temperament::Pythagorean => "Pythagorean",
+            Temperament::Meantone => "Quarter comma meantone",
+            Temperament::Werckmeister => "Werckmeister III",
+            Temperament::Kirnberger => "Kirnberger III",
+            Temperament::Vallotti => "Vallotti"
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for Temperament {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Temperament, String> {
+        Temperament::all().into_iter()
+            .find(|t| t.id() == s.to_lowercase())
+            .ok_or_else(|| format!("Unknown temperament: {}", s))
+    }
+}
+
+// A4 is always tuned to a4_hz, and the other notes are tuned around
+// it in the temperament.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TuningReference {
+    pub a4_hz: f32,
+    pub temperament: Temperament,
+    // semitones above C
+    pub tonic: i32,
+    // How far note numbers have been moved by transposing, so that the
+    // temperament still lines up with the notes that actually sound.
+    written_offset: i32
+}
+
+pub const DEFAULT_A4_HZ: f32 = 440.0;
+
+impl TuningReference {
+    // Anything that isn't a positive frequency would make every cents
+    // reading infinite or NaN, so it's replaced with the default.
+    // Callers that want to report the mistake can check is_valid_a4_hz
+    // first.
+    pub fn new(a4_hz: f32) -> TuningReference {
+        TuningReference {
+            a4_hz: if TuningReference::is_valid_a4_hz(a4_hz) { a4_hz } else { DEFAULT_A4_HZ },
+            temperament: Temperament::Equal,
+            tonic: 0,
+            written_offset: 0
+        }
+    }
+
+    pub fn is_valid_a4_hz(a4_hz: f32) -> bool {
+        a4_hz.is_finite() && a4_hz > 0.0
+    }
+
+    pub fn with_temperament(self, temperament: Temperament, tonic: i32) -> TuningReference {
+        TuningReference {
+            temperament: temperament,
+            tonic: tonic.rem_euclid(12),
+            ..self
+        }
+    }
+
+    fn transposed(self, semitones: i32) -> TuningReference {
+        TuningReference {
+            written_offset: self.written_offset + semitones,
+            ..self
+        }
+    }
+
+    // Where a note is tuned, as a fractional MIDI number, given the
+    // MIDI number it has in equal temperament.
+    pub fn tempered_midi_number(&self, note: i32) -> f32 {
+        let cents = self.temperament.cents_above_tonic();
+        let deviation = |semitones_above_tonic: i32| {
+            let index = semitones_above_tonic.rem_euclid(12);
+            cents[index as usize] - index as f32 * 100.0
+        };
+        let sounding_note = note - self.written_offset;
+        note as f32 + (deviation(sounding_note - self.tonic) - deviation(9 - self.tonic)) / 100.0
+    }
+
+    // Common references, for populating selectors. Baroque pitch is
+    // 415, and most modern orchestras tune somewhere between 440 and
+    // 443.
+    pub fn presets() -> Vec<TuningReference> {
+        [415.0, 430.0, 432.0, 435.0, 440.0, 441.0, 442.0, 443.0, 444.0, 466.0]
+            .iter()
+            .map(|&hz| TuningReference::new(hz))
+            .collect()
+    }
+}
+
+impl Default for TuningReference {
+    fn default() -> TuningReference {
+        TuningReference::new(DEFAULT_A4_HZ)
+    }
+}
+
+impl fmt::Display for TuningReference {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "A4 = {} Hz", self.a4_hz)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Transposition {
+    #[default]
+    C,
+    BFlat,
+    A,
+    F,
+    EFlat,
+    // an octave below the B♭ and E♭ instruments
+    BFlatTenor,
+    EFlatBaritone
+}
+
+impl Transposition {
+    pub fn all() -> Vec<Transposition> {
+        vec!(
+            Transposition::C,
+            Transposition::BFlat,
+            Transposition::A,
+            Transposition::F,
+            Transposition::EFlat,
+            Transposition::BFlatTenor,
+            Transposition::EFlatBaritone
+        )
+    }
+
+    // How far the written note sits above the note that actually
+    // sounds. For example, a B♭ trumpet playing a written C sounds a
+    // concert B♭, two semitones lower.
+    pub fn semitones(&self) -> i32 {
+        match *self {
+            Transposition::C => 0,
+            Transposition::BFlat => 2,
+            Transposition::A => 3,
+            Transposition::F => 7,
+            Transposition::EFlat => 9,
+            Transposition::BFlatTenor => 14,
+            Transposition::EFlatBaritone => 21
+        }
+    }
+
+    pub fn from_semitones(semitones: i32) -> Option<Transposition> {
+        Transposition::all().into_iter().find(|t| t.semitones() == semitones)
+    }
+
+    pub fn id(&self) -> &'static str {
+        match *self {
+            Transposition::C => "c",
+            Transposition::BFlat => "bb",
+            Transposition::A => "a",
+            Transposition::F => "f",
+            Transposition::EFlat => "eb",
+            Transposition::BFlatTenor => "bb-tenor",
+            Transposition::EFlatBaritone => "eb-baritone"
+        }
+    }
+
+    pub fn instruments(&self) -> &'static str {
+        match *self {
+            Transposition::C => "concert pitch",
+            Transposition::BFlat => "trumpet, clarinet, soprano sax",
+            Transposition::A => "clarinet in A",
+            Transposition::F => "horn",
+            Transposition::EFlat => "alto sax",
+            Transposition::BFlatTenor => "tenor sax",
+            Transposition::EFlatBaritone => "baritone sax"
+        }
+    }
+}
+
+impl fmt::Display for Transposition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let key = match *self {
+            Transposition::C => "C",
+            Transposition::BFlat => "B♭",
+            Transposition::A => "A",
+            Transposition::F => "F",
+            Transposition::EFlat => "E♭",
+            Transposition::BFlatTenor => "B♭ tenor",
+            Transposition::EFlatBaritone => "E♭ baritone"
+        };
+        write!(f, "{} ({})", key, self.instruments())
+    }
+}
+
+impl FromStr for Transposition {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Transposition, String> {
+        let id = s.to_lowercase().replace('♭', "b");
+        Transposition::all().into_iter()
+            .find(|t| t.id() == id)
+            .ok_or_else(|| format!("Unknown transposition: {}", s))
+    }
+}
+
+// The semitone above C named by a note like "F♯", "Eb" or "c#".
+pub fn parse_note_name(name: &str) -> Option<i32> {
+    let mut chars = name.trim().chars();
+    let natural = match chars.next().map(|c| c.to_ascii_uppercase()) {
+        Some('C') => 0,
+        Some('D') => 2,
+        Some('E') => 4,
+        Some('F') => 5,
+        Some('G') => 7,
+        Some('A') => 9,
+        Some('B') => 11,
+        _ => return None
+    };
+    let mut semitone: i32 = natural;
+    for c in chars {
+        semitone += match c {
+            '#' | '♯' => 1,
+            'b' | '♭' => -1,
+            _ => return None
+        };
+    }
+    Some(semitone.rem_euclid(12))
+}
+
+// Starting from C, one for each semitone in an octave.
+pub const NOTE_NAMES: [&str; 12] = [
+    "C",
+    "C♯",
+    "D",
+    "E♭",
+    "E",
+    "F",
+    "F♯",
+    "G",
+    "G♯",
+    "A",
+    "B♭",
+    "B"
+];
+
+// The naming options are enums of the same shape: all() in the order
+// they're offered, an id for the config file and command line, a
+// longer name for menus, and parsing back from the id. The first
+// option is the default.
+macro_rules! naming_option {
+    (
+        pub enum $name:ident ($kind:expr) {
+            $first:ident => ($first_id:expr, $first_name:expr)
+            $(, $option:ident => ($id:expr, $display:expr))*
+        }
+    ) => {
+        #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+        pub enum $name {
+            $first
+            $(, $option)*
+        }
+
+        impl $name {
+            pub fn all() -> Vec<$name> {
+                vec!($name::$first $(, $name::$option)*)
+            }
+
+            pub fn id(&self) -> &'static str {
+                match *self {
+                    $name::$first => $first_id
+                    $(, $name::$option => $id)*
+                }
+            }
+        }
+
+        impl Default for $name {
+            fn default() -> $name {
+                $name::$first
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                let name = match *self {
+                    $name::$first => $first_name
+                    $(, $name::$option => $display)*
+                };
+                write!(f, "{}", name)
+            }
+        }
+
+        impl FromStr for $name {
+            type Err = String;
+
+            fn from_str(s: &str) -> Result<$name, String> {
+                // so that Solfège is found as well as solfege
+                let id = s.to_lowercase().replace('è', "e");
+                $name::all().into_iter()
+                    .find(|option| option.id() == id)
+                    .ok_or_else(|| format!("Unknown {}: {}", $kind, s))
+            }
+        }
+    };
+}
+
+// The names given to the seven natural notes.
+naming_option! {
+    pub enum NamingSystem ("naming system") {
+        Letters => ("letters", "Letters (C D E)"),
+        // German names, where B is B♭ and B natural is H, and
+        // accidentals are the suffixes -is and -es
+        German => ("german", "German (H and B)"),
+        // fixed do, so Do is always C
+        Solfege => ("solfege", "Solfège (Do Re Mi)")
+    }
+}
+
+impl NamingSystem {
+    fn natural_name(&self, semitone: i32) -> &'static str {
+        let names = match *self {
+            NamingSystem::Letters => ["C", "D", "E", "F", "G", "A", "B"],
+            NamingSystem::German => ["C", "D", "E", "F", "G", "A", "H"],
+            NamingSystem::Solfege => ["Do", "Re", "Mi", "Fa", "Sol", "La", "Si"]
+        };
+        names[[0, 0, 1, 1, 2, 3, 3, 4, 4, 5, 5, 6][semitone as usize]]
+    }
+}
+
+// Whether the black keys are named as sharps or flats.
+naming_option! {
+    pub enum Spelling ("spelling") {
+        // C♯, E♭, F♯, G♯ and B♭, the most common names for each
+        Mixed => ("mixed", "Common spellings"),
+        Sharps => ("sharps", "Sharps"),
+        Flats => ("flats", "Flats"),
+        // sharps in sharp keys and flats in flat keys
+        Key => ("key", "Key signature")
+    }
+}
+
+naming_option! {
+    pub enum Accidentals ("accidentals") {
+        // ♯ and ♭
+        Unicode => ("unicode", "♯ and ♭"),
+        // # and b, for terminals and fonts without the symbols
+        Ascii => ("ascii", "# and b")
+    }
+}
+
+impl Accidentals {
+    fn symbol(&self, flat: bool) -> &'static str {
+        match (*self, flat) {
+            (Accidentals::Unicode, false) => "♯",
+            (Accidentals::Unicode, true) => "♭",
+            (Accidentals::Ascii, false) => "#",
+            (Accidentals::Ascii, true) => "b"
+        }
+    }
+}
+
+naming_option! {
+    pub enum OctaveNotation ("octave notation") {
+        // A4, with middle C as C4
+        Scientific => ("scientific", "Scientific (A4)"),
+        // a′, with middle C as c′, lower case from the octave below
+        // middle C up and with commas below that
+        Helmholtz => ("helmholtz", "Helmholtz (a′)")
+    }
+}
+
+// How notes are written out. The default gives the same names as
+// NOTE_NAMES, like "C♯4", with single letter names padded to line up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct NoteNaming {
+    pub system: NamingSystem,
+    pub spelling: Spelling,
+    // The major key whose signature Spelling::Key follows, in
+    // semitones above C.
+    pub key: i32,
+    pub accidentals: Accidentals,
+    pub octaves: OctaveNotation
+}
+
+impl NoteNaming {
+    fn is_flat(&self, semitone: i32) -> bool {
+        match self.spelling {
+            Spelling::Mixed => semitone == 3 || semitone == 10,
+            Spelling::Sharps => false,
+            Spelling::Flats => true,
+            // F, B♭, E♭, A♭ and D♭ major. F♯ is written with sharps
+            // rather than as G♭.
+            Spelling::Key => [5, 10, 3, 8, 1].contains(&self.key.rem_euclid(12))
+        }
+    }
+
+    // The name of a note within the octave, counting semitones from C.
+    pub fn note_name(&self, semitone: i32) -> String {
+        let semitone = semitone.rem_euclid(12);
+        let black_key = [1, 3, 6, 8, 10].contains(&semitone);
+        if !black_key {
+            return self.system.natural_name(semitone).to_string();
+        }
+        let flat = self.is_flat(semitone);
+        let natural = if flat { semitone + 1 } else { semitone - 1 };
+        let natural_name = self.system.natural_name(natural);
+        match self.system {
+            NamingSystem::German => match (natural_name, flat) {
+                ("H", true) => "B".to_string(),
+                ("E", true) | ("A", true) => format!("{}s", natural_name),
+                (_, true) => format!("{}es", natural_name),
+                (_, false) => format!("{}is", natural_name)
+            },
+            _ => format!("{}{}", natural_name, self.accidentals.symbol(flat))
+        }
+    }
+
+    // The full name of a MIDI note number, including its octave.
+    pub fn name(&self, midi_number: i32) -> String {
+        let name = self.note_name(midi_number);
+        //midi_number of 0 is C-1.
+        let octave = (midi_number as f32 / 12.0).floor() as i32 - 1;
+        match self.octaves {
+            OctaveNotation::Scientific => format!("{: <2}{}", name, octave),
+            OctaveNotation::Helmholtz if octave >= 3 => {
+                let primes = (octave - 3) as usize;
+                let marks = match (self.accidentals, primes) {
+                    (Accidentals::Ascii, _) => "'".repeat(primes),
+                    (Accidentals::Unicode, 2) => "″".to_string(),
+                    (Accidentals::Unicode, 3) => "‴".to_string(),
+                    (Accidentals::Unicode, _) => "′".repeat(primes)
+                };
+                format!("{}{}", name.to_lowercase(), marks)
+            },
+            OctaveNotation::Helmholtz => format!("{}{}", name, ",".repeat((2 - octave) as usize))
+        }
+    }
+}
 
 #[derive(Debug, Clone, Copy)]
 pub struct Pitch {
-    pub hz: f32
+    pub hz: f32,
+    pub reference: TuningReference
 }
 
 impl Pitch {
     pub fn new(hz: f32) -> Pitch {
+        Pitch::with_reference(hz, TuningReference::default())
+    }
+
+    pub fn with_reference(hz: f32, reference: TuningReference) -> Pitch {
         Pitch {
-            hz: hz
+            hz: hz,
+            reference: reference
         }
     }
-    
-    fn midi_number(&self) -> f32 {
-        69.0 + 12.0 * (self.hz / 440.0).log2()
+
+    // The pitch of a note in the reference's temperament, counting
+    // semitones up from C in the given octave. Middle C is note 0 in
+    // octave 4.
+    pub fn from_note(semitone: i32, octave: i32, reference: TuningReference) -> Pitch {
+        let midi_number = reference.tempered_midi_number((octave + 1) * 12 + semitone);
+        Pitch::with_reference(reference.a4_hz * 2.0f32.powf((midi_number - 69.0) / 12.0), reference)
+    }
+
+    pub fn transposed(&self, semitones: i32) -> Pitch {
+        Pitch::with_reference(self.hz * 2.0f32.powf(semitones as f32 / 12.0), self.reference.transposed(semitones))
+    }
+
+    pub fn written(&self, transposition: Transposition) -> Pitch {
+        self.transposed(transposition.semitones())
+    }
+
+    pub fn midi_number(&self) -> f32 {
+        69.0 + 12.0 * (self.hz / self.reference.a4_hz).log2()
+    }
+
+    // The MIDI number of the closest note in the reference's
+    // temperament. This can differ from the closest equal tempered note
+    // when the pitch is near halfway between two.
+    pub fn nearest_note(&self) -> i32 {
+        let midi_number = self.midi_number();
+        let distance = |note: i32| (midi_number - self.reference.tempered_midi_number(note)).abs();
+        let rounded = midi_number.round() as i32;
+        let mut nearest = rounded;
+        for &note in &[rounded - 1, rounded + 1] {
+            if distance(note) < distance(nearest) {
+                nearest = note;
+            }
+        }
+        nearest
     }
 
+    // Cents away from the nearest note in the reference's temperament.
     pub fn cents_error(&self) -> f32 {
         if !self.hz.is_finite() {
             return f32::NAN;
         }
-        
-        let midi_number = self.midi_number();
-        let cents = (midi_number - midi_number.floor()) * 100.0;
-        if cents >= 50.0 {
-            cents - 100.0
-        }
-        else {
-            cents
+        (self.midi_number() - self.reference.tempered_midi_number(self.nearest_note())) * 100.0
+    }
+
+    // The closest key in a tuning loaded from Scala files, which
+    // replaces the reference and its temperament.
+    pub fn nearest_in(&self, tuning: &ScalaTuning) -> Option<TunedNote> {
+        tuning.nearest(self.hz)
+    }
+
+    // The nearest note's name, or an empty string if this isn't a real
+    // pitch.
+    pub fn name(&self, naming: &NoteNaming) -> String {
+        if self.hz <= 0.0 || !self.hz.is_finite() {
+            String::new()
+        } else {
+            naming.name(self.nearest_note())
         }
     }
 }
 
 impl fmt::Display for Pitch {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        if self.hz <= 0.0 || !self.hz.is_finite() {
-            write!(f, "")
-        } else {
-            let pitch_names = [
-                "C",
-                "C♯",
-                "D",
-                "E♭",
-                "E",
-                "F",
-                "F♯",
-                "G",
-                "G♯",
-                "A",
-                "B♭",
-                "B"
-            ];
-
-            //midi_number of 0 is C-1.
-            let rounded_pitch = self.midi_number().round() as i32;
-            let name = pitch_names[rounded_pitch as usize % pitch_names.len()];
-            let octave = rounded_pitch / pitch_names.len() as i32 - 1;
-
-            write!(f, "{: <2}{}", name, octave)
-        }
+        write!(f, "{}", self.name(&NoteNaming::default()))
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    
+
     #[test]
     fn a4_is_correct() {
         assert_eq!(format!("{}", Pitch::new(440.0)), "A 4");
@@ -86,4 +649,255 @@ mod tests {
     fn f5_is_correct() {
         assert_eq!(format!("{}", Pitch::new(698.46)), "F 5");
     }
+
+    #[test]
+    fn notes_are_built_from_name_and_octave() {
+        assert_eq!(Pitch::from_note(9, 4, TuningReference::default()).hz, 440.0);
+        assert_eq!(format!("{}", Pitch::from_note(0, 4, TuningReference::default())), "C 4");
+        assert_eq!(format!("{}", Pitch::from_note(10, 3, TuningReference::default())), "B♭3");
+        assert_eq!(Pitch::from_note(9, 4, TuningReference::new(442.0)).hz, 442.0);
+    }
+
+    #[test]
+    fn reference_pitch_is_in_tune_at_several_references() {
+        for &a4 in &[415.0, 432.0, 440.0, 442.0, 466.0] {
+            let pitch = Pitch::with_reference(a4, TuningReference::new(a4));
+            assert_eq!(format!("{}", pitch), "A 4");
+            assert!(pitch.cents_error().abs() < 0.01, "a4={}, error={}", a4, pitch.cents_error());
+        }
+    }
+
+    #[test]
+    fn invalid_references_fall_back_to_the_default() {
+        for &a4 in &[0.0, -440.0, f32::NAN, f32::INFINITY] {
+            assert!(!TuningReference::is_valid_a4_hz(a4));
+            assert_eq!(TuningReference::new(a4), TuningReference::default(), "a4={}", a4);
+        }
+        let pitch = Pitch::with_reference(440.0, TuningReference::new(0.0));
+        assert!(pitch.cents_error().abs() < 0.01);
+    }
+
+    #[test]
+    fn a440_is_flat_against_a442() {
+        let pitch = Pitch::with_reference(440.0, TuningReference::new(442.0));
+        assert_eq!(format!("{}", pitch), "A 4");
+        assert!((pitch.cents_error() - -7.85).abs() < 0.01, "error={}", pitch.cents_error());
+    }
+
+    #[test]
+    fn baroque_a_is_named_a_not_g_sharp() {
+        // 415Hz is G♯4 at modern pitch, but A4 at baroque pitch
+        assert_eq!(format!("{}", Pitch::new(415.0)), "G♯4");
+        assert_eq!(format!("{}", Pitch::with_reference(415.0, TuningReference::new(415.0))), "A 4");
+    }
+
+    #[test]
+    fn concert_b_flat_is_written_c_for_trumpet() {
+        let pitch = Pitch::new(466.16);
+        assert_eq!(format!("{}", pitch), "B♭4");
+        assert_eq!(format!("{}", pitch.written(Transposition::BFlat)), "C 5");
+    }
+
+    #[test]
+    fn written_c5_on_each_transposing_instrument() {
+        let sounding = [
+            (Transposition::C, 523.25),
+            (Transposition::BFlat, 466.16),
+            (Transposition::A, 440.0),
+            (Transposition::F, 349.23),
+            (Transposition::EFlat, 311.13),
+            (Transposition::BFlatTenor, 233.08),
+            (Transposition::EFlatBaritone, 155.56)
+        ];
+        for &(transposition, hz) in &sounding {
+            let written = Pitch::new(hz).written(transposition);
+            assert_eq!(format!("{}", written), "C 5", "transposition={}", transposition);
+        }
+    }
+
+    #[test]
+    fn transposition_does_not_change_cents_error() {
+        let pitch = Pitch::with_reference(445.0, TuningReference::new(442.0));
+        let written = pitch.written(Transposition::F);
+        assert!((pitch.cents_error() - written.cents_error()).abs() < 0.01);
+    }
+
+    #[test]
+    fn transpositions_parse_from_their_ids() {
+        for transposition in Transposition::all() {
+            assert_eq!(transposition.id().parse::<Transposition>(), Ok(transposition));
+        }
+        assert_eq!("B♭".parse::<Transposition>(), Ok(Transposition::BFlat));
+        assert!("G".parse::<Transposition>().is_err());
+    }
+
+    // cents from one note to another above it, by MIDI number
+    fn interval(reference: TuningReference, from: i32, to: i32) -> f32 {
+        (reference.tempered_midi_number(to) - reference.tempered_midi_number(from)) * 100.0
+    }
+
+    fn tuned(temperament: Temperament, tonic: i32) -> TuningReference {
+        TuningReference::default().with_temperament(temperament, tonic)
+    }
+
+    #[test]
+    fn just_intervals_are_pure() {
+        for &tonic in &[0, 2, 7] {
+            let just = tuned(Temperament::JustMajor, tonic);
+            let root = 60 + tonic;
+            assert!((interval(just, root, root + 4) - 386.31).abs() < 0.01, "tonic={}", tonic);
+            assert!((interval(just, root, root + 7) - 701.955).abs() < 0.01, "tonic={}", tonic);
+            assert!((interval(just, root, root + 12) - 1200.0).abs() < 0.01, "tonic={}", tonic);
+        }
+        let minor = tuned(Temperament::JustMinor, 9);
+        assert!((interval(minor, 69, 72) - 315.64).abs() < 0.01);
+    }
+
+    #[test]
+    fn pythagorean_third_is_wide() {
+        let pythagorean = tuned(Temperament::Pythagorean, 0);
+        assert!((interval(pythagorean, 60, 64) - 407.82).abs() < 0.01);
+        assert!((interval(pythagorean, 60, 67) - 701.955).abs() < 0.01);
+    }
+
+    #[test]
+    fn meantone_fifth_is_narrow() {
+        let meantone = tuned(Temperament::Meantone, 0);
+        assert!((interval(meantone, 60, 67) - 696.58).abs() < 0.01);
+        assert!((interval(meantone, 60, 64) - 386.31).abs() < 0.01);
+    }
+
+    #[test]
+    fn a4_stays_at_the_reference_in_every_temperament() {
+        for temperament in Temperament::all() {
+            for tonic in 0..12 {
+                let reference = TuningReference::new(442.0).with_temperament(temperament, tonic);
+                let a4 = Pitch::from_note(9, 4, reference);
+                assert!((a4.hz - 442.0).abs() < 0.001, "temperament={}, tonic={}", temperament, tonic);
+                assert!(a4.cents_error().abs() < 0.01);
+            }
+        }
+    }
+
+    #[test]
+    fn cents_error_is_against_the_tempered_note() {
+        let just = tuned(Temperament::JustMajor, 0);
+        let e4 = Pitch::from_note(4, 4, just);
+        assert_eq!(format!("{}", e4), "E 4");
+        assert!(e4.cents_error().abs() < 0.01, "error={}", e4.cents_error());
+
+        // With A held at the reference, C is a pure sixth below it
+        // and E a pure third above C, which is sharp of equal
+        // temperament.
+        let equal = Pitch::new(e4.hz);
+        assert!((equal.cents_error() - 1.96).abs() < 0.01, "error={}", equal.cents_error());
+    }
+
+    #[test]
+    fn transposition_keeps_the_temperament_on_sounding_notes() {
+        let just = tuned(Temperament::JustMajor, 0);
+        let e4 = Pitch::from_note(4, 4, just);
+        let written = e4.written(Transposition::BFlat);
+        assert_eq!(format!("{}", written), "F♯4");
+        assert!(written.cents_error().abs() < 0.01, "error={}", written.cents_error());
+    }
+
+    #[test]
+    fn temperaments_parse_from_their_ids() {
+        for temperament in Temperament::all() {
+            assert_eq!(temperament.id().parse::<Temperament>(), Ok(temperament));
+        }
+        assert!("mystery".parse::<Temperament>().is_err());
+    }
+
+    #[test]
+    fn note_names_parse_to_semitones() {
+        assert_eq!(parse_note_name("C"), Some(0));
+        assert_eq!(parse_note_name("f#"), Some(6));
+        assert_eq!(parse_note_name("G♯"), Some(8));
+        assert_eq!(parse_note_name("Eb"), Some(3));
+        assert_eq!(parse_note_name("B♭"), Some(10));
+        assert_eq!(parse_note_name("Cb"), Some(11));
+        assert_eq!(parse_note_name("H"), None);
+        assert_eq!(parse_note_name(""), None);
+    }
+
+    #[test]
+    fn c4_follows_reference() {
+        let c4_at_442 = 442.0 * 2.0f32.powf(-9.0/12.0);
+        let pitch = Pitch::with_reference(c4_at_442, TuningReference::new(442.0));
+        assert_eq!(format!("{}", pitch), "C 4");
+        assert!(pitch.cents_error().abs() < 0.01);
+    }
+
+    fn names(naming: &NoteNaming, notes: &[i32]) -> Vec<String> {
+        notes.iter().map(|&note| naming.name(note)).collect()
+    }
+
+    #[test]
+    fn default_naming_matches_note_names() {
+        let naming = NoteNaming::default();
+        for (semitone, &name) in NOTE_NAMES.iter().enumerate() {
+            assert_eq!(naming.note_name(semitone as i32), name);
+        }
+        assert_eq!(names(&naming, &[61, 69, 70]), vec!("C♯4", "A 4", "B♭4"));
+    }
+
+    #[test]
+    fn black_keys_follow_the_spelling() {
+        let black_keys = [61, 63, 66, 68, 70];
+        let sharps = NoteNaming { spelling: Spelling::Sharps, accidentals: Accidentals::Ascii, ..NoteNaming::default() };
+        assert_eq!(names(&sharps, &black_keys), vec!("C#4", "D#4", "F#4", "G#4", "A#4"));
+        let flats = NoteNaming { spelling: Spelling::Flats, accidentals: Accidentals::Ascii, ..NoteNaming::default() };
+        assert_eq!(names(&flats, &black_keys), vec!("Db4", "Eb4", "Gb4", "Ab4", "Bb4"));
+
+        let in_f = NoteNaming { spelling: Spelling::Key, key: 5, ..NoteNaming::default() };
+        assert_eq!(in_f.note_name(6), "G♭");
+        let in_a = NoteNaming { spelling: Spelling::Key, key: 9, ..NoteNaming::default() };
+        assert_eq!(in_a.note_name(10), "A♯");
+    }
+
+    #[test]
+    fn keys_outside_an_octave_are_spelled_like_their_note() {
+        let e_flat_up = NoteNaming { spelling: Spelling::Key, key: 15, ..NoteNaming::default() };
+        assert_eq!(e_flat_up.note_name(8), "A♭");
+        let f_down = NoteNaming { spelling: Spelling::Key, key: -7, ..NoteNaming::default() };
+        assert_eq!(f_down.note_name(6), "G♭");
+    }
+
+    #[test]
+    fn german_names_use_h_and_suffixes() {
+        let german = NoteNaming { system: NamingSystem::German, spelling: Spelling::Flats, ..NoteNaming::default() };
+        assert_eq!(names(&german, &[70, 71, 63, 68, 61]), vec!("B 4", "H 4", "Es4", "As4", "Des4"));
+        let german = NoteNaming { system: NamingSystem::German, spelling: Spelling::Sharps, ..NoteNaming::default() };
+        assert_eq!(german.note_name(6), "Fis");
+    }
+
+    #[test]
+    fn solfege_is_fixed_do() {
+        let solfege = NoteNaming { system: NamingSystem::Solfege, ..NoteNaming::default() };
+        assert_eq!(names(&solfege, &[60, 67, 70, 71]), vec!("Do4", "Sol4", "Si♭4", "Si4"));
+    }
+
+    #[test]
+    fn helmholtz_octaves_are_marked_around_middle_c() {
+        let helmholtz = NoteNaming { octaves: OctaveNotation::Helmholtz, ..NoteNaming::default() };
+        assert_eq!(names(&helmholtz, &[24, 36, 48, 57, 60, 69, 70, 72, 84, 96]),
+                   vec!("C,", "C", "c", "a", "c′", "a′", "b♭′", "c″", "c‴", "c′′′′"));
+        let ascii = NoteNaming { accidentals: Accidentals::Ascii, ..helmholtz };
+        assert_eq!(ascii.name(73), "c#''");
+    }
+
+    #[test]
+    fn naming_options_parse_from_ids() {
+        assert_eq!("Solfège".parse::<NamingSystem>(), Ok(NamingSystem::Solfege));
+        assert_eq!("helmholtz".parse::<OctaveNotation>(), Ok(OctaveNotation::Helmholtz));
+        assert_eq!("FLATS".parse::<Spelling>(), Ok(Spelling::Flats));
+        assert!("dutch".parse::<NamingSystem>().is_err());
+    }
+
+    #[test]
+    fn no_pitch_has_no_name() {
+        assert_eq!(Pitch::new(f32::NAN).name(&NoteNaming::default()), "");
+    }
 }
@@ -0,0 +1,524 @@
+use std::cmp::Ordering;
+use std::error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use pitch::NoteNaming;
+
+// Reading tunings in the Scala formats, which is how most microtonal
+// and historical tunings are shared. A .scl file gives the intervals
+// of a scale, and an optional .kbm file says which MIDI keys play
+// which degrees of it and what frequency it's anchored to. See
+// http://www.huygens-fokker.org/scala/scl_format.html and
+// http://www.huygens-fokker.org/scala/help.htm#mappings
+
+const MIDI_KEYS: i32 = 128;
+
+#[derive(Debug)]
+pub enum ScalaError {
+    Io(io::Error),
+    // line numbers count from 1
+    Parse { line: usize, message: String },
+    // the files were read, but don't make a playable tuning together
+    Tuning(String)
+}
+
+impl fmt::Display for ScalaError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ScalaError::Io(ref e) => write!(f, "{}", e),
+            ScalaError::Parse { line, ref message } => write!(f, "line {}: {}", line, message),
+            ScalaError::Tuning(ref message) => write!(f, "{}", message)
+        }
+    }
+}
+
+impl error::Error for ScalaError {}
+
+impl From<io::Error> for ScalaError {
+    fn from(e: io::Error) -> ScalaError {
+        ScalaError::Io(e)
+    }
+}
+
+fn parse_error<T>(line: usize, message: String) -> Result<T, ScalaError> {
+    Err(ScalaError::Parse { line: line, message: message })
+}
+
+// Lines that aren't comments, with their line numbers. Both formats
+// use ! for comments.
+fn content_lines(text: &str) -> Vec<(usize, &str)> {
+    text.lines()
+        .enumerate()
+        .map(|(index, line)| (index + 1, line.trim_end_matches('\r')))
+        .filter(|&(_, line)| !line.starts_with('!'))
+        .collect()
+}
+
+// Anything after the first word is a free form label or comment.
+fn first_word(line: &str) -> &str {
+    line.split_whitespace().next().unwrap_or("")
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Scale {
+    pub description: String,
+    // Cents above the first degree, which isn't listed since it's
+    // always 0. The last is the period the scale repeats at, usually
+    // an octave.
+    pub cents: Vec<f32>
+}
+
+impl Scale {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Scale, ScalaError> {
+        Scale::parse(&fs::read_to_string(path)?)
+    }
+
+    pub fn parse(text: &str) -> Result<Scale, ScalaError> {
+        let lines = content_lines(text);
+        let mut lines = lines.into_iter();
+        // the description may be blank, but it must be there
+        let description = match lines.next() {
+            Some((_, line)) => line.trim().to_string(),
+            None => return parse_error(1, "Missing the description line".to_string())
+        };
+
+        let mut lines = lines.filter(|&(_, line)| !line.trim().is_empty());
+        let (count_line, count) = match lines.next() {
+            Some((number, line)) => match first_word(line).parse::<usize>() {
+                Ok(count) if count > 0 => (number, count),
+                _ => return parse_error(number, format!("Expected the number of notes, found \"{}\"", line.trim()))
+            },
+            None => return parse_error(text.lines().count() + 1, "Missing the number of notes".to_string())
+        };
+
+        // not allocated up front, since the count may be nonsense
+        let mut cents = Vec::new();
+        for (number, line) in lines {
+            if cents.len() == count {
+                return parse_error(number, format!("More notes than the {} given on line {}", count, count_line));
+            }
+            cents.push(parse_pitch(number, first_word(line))?);
+        }
+        if cents.len() < count {
+            return parse_error(count_line, format!("Expected {} notes, found {}", count, cents.len()));
+        }
+        if cents[count - 1] <= 0.0 {
+            return parse_error(count_line, "The last note, which the scale repeats at, must be above the first".to_string());
+        }
+
+        Ok(Scale {
+            description: description,
+            cents: cents
+        })
+    }
+
+    // notes per period
+    pub fn len(&self) -> usize {
+        self.cents.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cents.is_empty()
+    }
+
+    pub fn period_cents(&self) -> f32 {
+        self.cents[self.cents.len() - 1]
+    }
+
+    // Cents above degree 0 of any degree, continuing into the periods
+    // above and below.
+    pub fn degree_cents(&self, degree: i32) -> f32 {
+        let len = self.len() as i32;
+        let periods = floor_div(degree, len);
+        let degree = degree - periods * len;
+        let within_period = if degree == 0 { 0.0 } else { self.cents[degree as usize - 1] };
+        periods as f32 * self.period_cents() + within_period
+    }
+}
+
+// A pitch is in cents if it has a decimal point, and otherwise a ratio
+// like 5/4, or a whole number like 2 for 2/1.
+fn parse_pitch(line: usize, word: &str) -> Result<f32, ScalaError> {
+    if word.contains('.') {
+        return match word.parse::<f32>() {
+            Ok(cents) if cents.is_finite() => Ok(cents),
+            _ => parse_error(line, format!("Invalid cents value \"{}\"", word))
+        };
+    }
+
+    let mut parts = word.splitn(2, '/');
+    let numerator = parts.next().and_then(|n| n.parse::<u64>().ok());
+    let denominator = match parts.next() {
+        Some(d) => d.parse::<u64>().ok(),
+        None => Some(1)
+    };
+    match (numerator, denominator) {
+        (Some(n), Some(d)) if n > 0 && d > 0 => Ok(1200.0 * (n as f64 / d as f64).log2() as f32),
+        _ => parse_error(line, format!("Invalid ratio \"{}\"", word))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyboardMapping {
+    pub first_key: i32,
+    pub last_key: i32,
+    // the key that plays degree 0
+    pub middle_key: i32,
+    pub reference_key: i32,
+    pub reference_hz: f32,
+    // The degree that the pattern repeats at. Only used with a map, and
+    // 0 means the scale's period.
+    pub formal_octave: i32,
+    // The degree played by each key in a repeating pattern, starting
+    // from the middle key, or None for keys that aren't played. An
+    // empty map means consecutive keys play consecutive degrees.
+    pub map: Vec<Option<i32>>
+}
+
+impl KeyboardMapping {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<KeyboardMapping, ScalaError> {
+        KeyboardMapping::parse(&fs::read_to_string(path)?)
+    }
+
+    pub fn parse(text: &str) -> Result<KeyboardMapping, ScalaError> {
+        let lines = content_lines(text);
+        let mut lines = lines.into_iter().filter(|&(_, line)| !line.trim().is_empty());
+        let end_line = text.lines().count() + 1;
+
+        let mut header = Vec::new();
+        for (index, &name) in ["map size", "first key", "last key", "middle key", "reference key"].iter().enumerate() {
+            let (number, line) = match lines.next() {
+                Some(line) => line,
+                None => return parse_error(end_line, format!("Missing the {}", name))
+            };
+            // Everything but the map size is a MIDI key, and a map
+            // can't be longer than the keyboard.
+            let max = if index == 0 { MIDI_KEYS } else { MIDI_KEYS - 1 };
+            match first_word(line).parse::<i32>() {
+                Ok(value) if value >= 0 && value <= max => header.push(value),
+                _ if index == 0 => return parse_error(number, format!("Expected the {} from 0 to {}, found \"{}\"", name, MIDI_KEYS, line.trim())),
+                _ => return parse_error(number, format!("Expected the {} as a MIDI key from 0 to 127, found \"{}\"", name, line.trim()))
+            }
+        }
+        let (map_size, first_key, last_key, middle_key, reference_key) = (header[0], header[1], header[2], header[3], header[4]);
+
+        let reference_hz = match lines.next() {
+            Some((number, line)) => match first_word(line).parse::<f32>() {
+                Ok(hz) if hz.is_finite() && hz > 0.0 => hz,
+                _ => return parse_error(number, format!("Expected the reference frequency, found \"{}\"", line.trim()))
+            },
+            None => return parse_error(end_line, "Missing the reference frequency".to_string())
+        };
+        let formal_octave = match lines.next() {
+            Some((number, line)) => match first_word(line).parse::<i32>() {
+                Ok(degree) if degree >= 0 => degree,
+                _ => return parse_error(number, format!("Expected the formal octave degree, found \"{}\"", line.trim()))
+            },
+            None => return parse_error(end_line, "Missing the formal octave degree".to_string())
+        };
+
+        // Files may leave off the end of the map, which leaves those
+        // keys unplayed.
+        let mut map = vec!(None; map_size as usize);
+        for (entry, (number, line)) in map.iter_mut().zip(lines) {
+            *entry = match first_word(line) {
+                "x" | "X" => None,
+                word => match word.parse::<i32>() {
+                    Ok(degree) if degree >= 0 => Some(degree),
+                    _ => return parse_error(number, format!("Expected a scale degree or x, found \"{}\"", word))
+                }
+            };
+        }
+
+        Ok(KeyboardMapping {
+            first_key: first_key,
+            last_key: last_key,
+            middle_key: middle_key,
+            reference_key: reference_key,
+            reference_hz: reference_hz,
+            formal_octave: formal_octave,
+            map: map
+        })
+    }
+
+    // The degree a key plays, counting on through the periods, if it
+    // plays one.
+    fn degree(&self, scale: &Scale, key: i32) -> Option<i32> {
+        if key < self.first_key || key > self.last_key {
+            return None;
+        }
+        let steps = key - self.middle_key;
+        if self.map.is_empty() {
+            return Some(steps);
+        }
+        let size = self.map.len() as i32;
+        let patterns = floor_div(steps, size);
+        let octave = if self.formal_octave == 0 { scale.len() as i32 } else { self.formal_octave };
+        self.map[(steps - patterns * size) as usize]
+            .map(|degree| degree + patterns * octave)
+    }
+}
+
+impl Default for KeyboardMapping {
+    // What Scala uses without a .kbm file: degree 0 on middle C, and
+    // A4 tuned to 440Hz.
+    fn default() -> KeyboardMapping {
+        KeyboardMapping {
+            first_key: 0,
+            last_key: MIDI_KEYS - 1,
+            middle_key: 60,
+            reference_key: 69,
+            reference_hz: 440.0,
+            formal_octave: 0,
+            map: Vec::new()
+        }
+    }
+}
+
+fn floor_div(a: i32, b: i32) -> i32 {
+    let quotient = a / b;
+    if (a % b != 0) && ((a < 0) != (b < 0)) { quotient - 1 } else { quotient }
+}
+
+// The frequency of every MIDI key, from a scale and a mapping.
+#[derive(Debug, Clone)]
+pub struct ScalaTuning {
+    pub scale: Scale,
+    pub mapping: KeyboardMapping,
+    keys: Vec<Option<f32>>
+}
+
+impl ScalaTuning {
+    // Loads a .scl file, and optionally a .kbm file to map it to keys.
+    pub fn load<P: AsRef<Path>>(scale: P, mapping: Option<P>) -> Result<ScalaTuning, ScalaError> {
+        let mapping = match mapping {
+            Some(path) => KeyboardMapping::load(path)?,
+            None => KeyboardMapping::default()
+        };
+        ScalaTuning::new(Scale::load(scale)?, mapping)
+    }
+
+    pub fn new(scale: Scale, mapping: KeyboardMapping) -> Result<ScalaTuning, ScalaError> {
+        let reference_cents = match mapping.degree(&scale, mapping.reference_key) {
+            Some(degree) => scale.degree_cents(degree),
+            None => return Err(ScalaError::Tuning(format!("The reference key {} doesn't play a note in the scale", mapping.reference_key)))
+        };
+        let keys: Vec<Option<f32>> = (0..MIDI_KEYS)
+            .map(|key| mapping.degree(&scale, key).map(|degree| {
+                let cents = scale.degree_cents(degree) - reference_cents;
+                mapping.reference_hz * 2.0f32.powf(cents / 1200.0)
+            }))
+            .collect();
+        // A wide enough period takes the outer keys past what f32 can
+        // hold, even though every interval in the file is finite.
+        let unplayable = keys.iter()
+            .enumerate()
+            .filter_map(|(key, hz)| hz.map(|hz| (key, hz)))
+            .find(|&(_, hz)| !hz.is_finite() || hz <= 0.0);
+        if let Some((key, hz)) = unplayable {
+            return Err(ScalaError::Tuning(format!("Key {} comes out at {}Hz, which can't be played", key, hz)));
+        }
+        Ok(ScalaTuning {
+            scale: scale,
+            mapping: mapping,
+            keys: keys
+        })
+    }
+
+    pub fn key_hz(&self, key: i32) -> Option<f32> {
+        if !(0..MIDI_KEYS).contains(&key) {
+            return None;
+        }
+        self.keys[key as usize]
+    }
+
+    pub fn nearest(&self, hz: f32) -> Option<TunedNote> {
+        if hz <= 0.0 || !hz.is_finite() {
+            return None;
+        }
+        let cents_from = |key_hz: f32| 1200.0 * (hz / key_hz).log2();
+        let (key, key_hz) = self.keys.iter()
+            .enumerate()
+            .filter_map(|(key, key_hz)| key_hz.map(|key_hz| (key as i32, key_hz)))
+            .min_by(|a, b| cents_from(a.1).abs().partial_cmp(&cents_from(b.1).abs()).unwrap_or(Ordering::Equal))?;
+        let degree = self.mapping.degree(&self.scale, key).unwrap_or(0);
+        let len = self.scale.len() as i32;
+        let periods = floor_div(degree, len);
+        Some(TunedNote {
+            key: key,
+            degree: degree - periods * len,
+            octave: 4 + periods,
+            hz: key_hz,
+            cents_error: cents_from(key_hz),
+            twelve_tone: self.scale.len() == 12 && (self.mapping.map.is_empty() || self.mapping.map.len() == 12)
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct TunedNote {
+    pub key: i32,
+    pub degree: i32,
+    // Periods above or below the middle key's, which is counted as 4
+    // to match middle C.
+    pub octave: i32,
+    // the frequency the key is tuned to
+    pub hz: f32,
+    pub cents_error: f32,
+    // twelve note scales are named by their keys, like equal
+    // temperament
+    twelve_tone: bool
+}
+
+impl TunedNote {
+    pub fn name(&self, naming: &NoteNaming) -> String {
+        if self.twelve_tone {
+            naming.name(self.key)
+        } else {
+            format!("{} ({})", self.degree, self.octave)
+        }
+    }
+}
+
+impl fmt::Display for TunedNote {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name(&NoteNaming::default()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pitch::Pitch;
+    use std::path::PathBuf;
+
+    fn corpus(name: &str) -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests").join("scala").join(name)
+    }
+
+    fn tuning(scale: &str, mapping: Option<&str>) -> ScalaTuning {
+        let scale = Scale::load(corpus(scale)).unwrap();
+        let mapping = mapping.map_or_else(KeyboardMapping::default, |m| KeyboardMapping::load(corpus(m)).unwrap());
+        ScalaTuning::new(scale, mapping).unwrap()
+    }
+
+    fn parse_error_line(result: Result<Scale, ScalaError>) -> usize {
+        match result {
+            Err(ScalaError::Parse { line, .. }) => line,
+            other => panic!("Expected a parse error, got {:?}", other)
+        }
+    }
+
+    #[test]
+    fn twelve_tone_equal_matches_pitch() {
+        let tuning = tuning("12-edo.scl", None);
+        for &hz in &[110.0, 261.63, 440.0, 452.0, 698.46, 1000.0] {
+            let pitch = Pitch::new(hz);
+            let note = pitch.nearest_in(&tuning).unwrap();
+            assert_eq!(format!("{}", note), format!("{}", pitch));
+            assert!((note.cents_error - pitch.cents_error()).abs() < 0.01, "hz={}", hz);
+        }
+    }
+
+    #[test]
+    fn ratios_and_cents_are_both_read() {
+        let scale = Scale::load(corpus("just-major.scl")).unwrap();
+        assert_eq!(scale.description, "5-limit just intonation, with note names after each ratio");
+        assert_eq!(scale.len(), 12);
+        assert!((scale.degree_cents(4) - 386.31).abs() < 0.01);
+        assert!((scale.degree_cents(7) - 701.955).abs() < 0.01);
+        assert!((scale.period_cents() - 1200.0).abs() < 0.001);
+        assert!((scale.degree_cents(-5) - (701.955 - 1200.0)).abs() < 0.01);
+
+        let edo = Scale::load(corpus("31-edo.scl")).unwrap();
+        assert!((edo.degree_cents(10) - 387.097).abs() < 0.001);
+    }
+
+    #[test]
+    fn keys_follow_the_mapping() {
+        // degree 0 on D4, A4 at 415, with the black keys unplayed
+        let tuning = tuning("just-major.scl", Some("d-white-keys-415.kbm"));
+        assert!((tuning.key_hz(69).unwrap() - 415.0).abs() < 0.001);
+        assert!(tuning.key_hz(63).is_none());
+        assert!(tuning.key_hz(130).is_none());
+        // A is the just fifth above D
+        let d4 = tuning.key_hz(62).unwrap();
+        assert!((1200.0 * (415.0 / d4).log2() - 701.955).abs() < 0.01);
+        // and an octave up the pattern repeats
+        assert!((tuning.key_hz(74).unwrap() / d4 - 2.0).abs() < 0.0001);
+    }
+
+    #[test]
+    fn notes_are_found_in_non_octave_scales() {
+        // Bohlen-Pierce repeats at a twelfth, 3/1, over 13 steps
+        let tuning = tuning("bohlen-pierce.scl", Some("bohlen-pierce.kbm"));
+        let note = Pitch::new(tuning.key_hz(64).unwrap() * 1.002).nearest_in(&tuning).unwrap();
+        assert_eq!(note.key, 64);
+        assert_eq!(note.degree, 4);
+        assert_eq!(format!("{}", note), "4 (4)");
+        assert!((note.cents_error - 3.46).abs() < 0.01, "error={}", note.cents_error);
+
+        let below = Pitch::new(tuning.key_hz(55).unwrap()).nearest_in(&tuning).unwrap();
+        assert_eq!((below.degree, below.octave), (8, 3));
+    }
+
+    #[test]
+    fn no_pitch_has_no_note() {
+        let tuning = tuning("12-edo.scl", None);
+        assert!(Pitch::new(f32::NAN).nearest_in(&tuning).is_none());
+        assert!(Pitch::new(0.0).nearest_in(&tuning).is_none());
+    }
+
+    #[test]
+    fn scale_errors_carry_line_numbers() {
+        assert_eq!(parse_error_line(Scale::load(corpus("bad-ratio.scl"))), 7);
+        assert_eq!(parse_error_line(Scale::load(corpus("too-few-notes.scl"))), 4);
+        assert_eq!(parse_error_line(Scale::load(corpus("huge-note-count.scl"))), 4);
+        assert_eq!(parse_error_line(Scale::parse("d\n99999999999999\n100.0\n")), 2);
+        assert_eq!(parse_error_line(Scale::parse("")), 1);
+        assert_eq!(parse_error_line(Scale::parse("! only a comment\nA description\n3\n100.0\n200.0\n1200.0\n1300.0\n")), 7);
+    }
+
+    #[test]
+    fn mapping_errors_carry_line_numbers() {
+        match KeyboardMapping::load(corpus("bad-degree.kbm")) {
+            Err(ScalaError::Parse { line, .. }) => assert_eq!(line, 13),
+            other => panic!("Expected a parse error, got {:?}", other)
+        }
+        match KeyboardMapping::parse("0\n0\n127\n60\n200\n440.0\n0\n") {
+            Err(ScalaError::Parse { line, .. }) => assert_eq!(line, 5),
+            other => panic!("Expected a parse error, got {:?}", other)
+        }
+        match KeyboardMapping::load(corpus("huge-map-size.kbm")) {
+            Err(ScalaError::Parse { line, .. }) => assert_eq!(line, 4),
+            other => panic!("Expected a parse error, got {:?}", other)
+        }
+    }
+
+    #[test]
+    fn unplayed_reference_key_is_refused() {
+        let scale = Scale::load(corpus("just-major.scl")).unwrap();
+        let mut mapping = KeyboardMapping::load(corpus("d-white-keys-415.kbm")).unwrap();
+        mapping.reference_key = 70;
+        assert!(ScalaTuning::new(scale, mapping).is_err());
+    }
+
+    #[test]
+    fn periods_too_wide_to_tune_are_refused() {
+        match ScalaTuning::load(corpus("huge-period.scl"), None) {
+            Err(ScalaError::Tuning(_)) => {},
+            other => panic!("Expected a tuning error, got {:?}", other)
+        }
+    }
+
+    #[test]
+    fn missing_files_are_io_errors() {
+        match Scale::load(corpus("does-not-exist.scl")) {
+            Err(ScalaError::Io(ref e)) => assert_eq!(e.kind(), io::ErrorKind::NotFound),
+            other => panic!("Expected an IO error, got {:?}", other)
+        }
+    }
+}
@@ -1,3 +1,10 @@
+// Peaks below this are treated as silence unless told otherwise. It's
+// about 0.05 of full scale.
+pub const DEFAULT_NOISE_GATE_DB: f32 = -26.0;
+// Levels are never reported as quieter than this, so digital silence
+// doesn't come out as negative infinity.
+pub const MIN_DBFS: f32 = -120.0;
+
 #[derive(Debug, Clone)]
 pub struct Signal {
     pub samples: Vec<f32>,
@@ -32,10 +39,42 @@ impl Signal {
         &self.samples[rising_edge..]
     }
 
-    pub fn is_silence(&self) -> bool {
-        self.samples.iter().all(|&x| x.abs() < 0.05)
+    // root mean square, on the same scale as the samples
+    pub fn rms(&self) -> f32 {
+        if self.samples.is_empty() {
+            return 0.0;
+        }
+        (self.samples.iter().map(|x| x * x).sum::<f32>() / self.samples.len() as f32).sqrt()
+    }
+
+    pub fn peak(&self) -> f32 {
+        self.samples.iter().fold(0.0, |max, x| max.max(x.abs()))
     }
 
+    // Levels in dB relative to full scale, where a sample of ±1 is
+    // 0dBFS. RMS is measured so a full scale sine wave is 0dBFS too.
+    pub fn rms_dbfs(&self) -> f32 {
+        to_dbfs(self.rms() * 2.0f32.sqrt())
+    }
+
+    pub fn peak_dbfs(&self) -> f32 {
+        to_dbfs(self.peak())
+    }
+
+    // true if the peak is at or under gate_db, so a gate of MIN_DBFS
+    // only catches digital silence
+    pub fn is_below(&self, gate_db: f32) -> bool {
+        self.peak_dbfs() <= gate_db
+    }
+}
+
+pub fn to_dbfs(amplitude: f32) -> f32 {
+    if amplitude > 0.0 {
+        (20.0 * amplitude.log10()).max(MIN_DBFS)
+    }
+    else {
+        MIN_DBFS
+    }
 }
 
 impl Default for Signal {
@@ -46,3 +85,46 @@ impl Default for Signal {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::PI;
+
+    fn sine(amplitude: f32) -> Signal {
+        let samples: Vec<f32> = (0..4410)
+            .map(|i| amplitude * (2.0 * PI * 100.0 * i as f32 / 44100.0).sin())
+            .collect();
+        Signal::new(&samples, 44100.0)
+    }
+
+    #[test]
+    fn full_scale_sine_is_zero_dbfs() {
+        let signal = sine(1.0);
+        assert!((signal.peak() - 1.0).abs() < 0.001);
+        assert!((signal.rms() - 0.5f32.sqrt()).abs() < 0.001);
+        assert!(signal.peak_dbfs().abs() < 0.01);
+        assert!(signal.rms_dbfs().abs() < 0.01);
+    }
+
+    #[test]
+    fn levels_fall_20db_per_decade() {
+        let signal = sine(0.1);
+        assert!((signal.peak_dbfs() - -20.0).abs() < 0.01);
+        assert!((signal.rms_dbfs() - -20.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn silence_has_a_floor() {
+        let signal = Signal::new(&[0.0; 100], 44100.0);
+        assert_eq!(signal.peak_dbfs(), MIN_DBFS);
+        assert_eq!(Signal::empty().rms_dbfs(), MIN_DBFS);
+    }
+
+    #[test]
+    fn gate_compares_the_peak() {
+        assert!(sine(0.04).is_below(DEFAULT_NOISE_GATE_DB));
+        assert!(!sine(0.06).is_below(DEFAULT_NOISE_GATE_DB));
+        assert!(!sine(0.04).is_below(-40.0));
+    }
+}
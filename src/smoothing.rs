@@ -0,0 +1,386 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::str::FromStr;
+
+use pitch::Pitch;
+
+// How far from a whole number of octaves a jump can be and still be
+// treated as an octave error, in octaves. A semitone.
+const OCTAVE_TOLERANCE: f32 = 1.0 / 12.0;
+// What Viterbi smoothing charges for moving a frame by an octave, in
+// the same units as a jump between frames: semitones. It's cheaper
+// than the two octave jumps an isolated error would otherwise cost,
+// but dearer than one genuine leap once the new note has lasted a few
+// frames.
+const OCTAVE_CORRECTION_COST: f32 = 4.0;
+// The most frames either side that smoothing looks at. Past this the
+// pitch lags the playing by over half a second, which is no use for
+// tuning.
+pub const MAX_LATENCY: usize = 50;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SmoothingMethod {
+    #[default]
+    Off,
+    // moves frames that are an octave away from their neighbours back
+    // into line, then takes the median
+    Median,
+    // picks the octave of each frame that makes for the smoothest path
+    // through the window
+    Viterbi
+}
+
+impl SmoothingMethod {
+    pub fn all() -> Vec<SmoothingMethod> {
+        vec!(SmoothingMethod::Off, SmoothingMethod::Median, SmoothingMethod::Viterbi)
+    }
+
+    pub fn id(&self) -> &'static str {
+        match *self {
+            SmoothingMethod::Off => "off",
+            SmoothingMethod::Median => "median",
+            SmoothingMethod::Viterbi => "viterbi"
+        }
+    }
+}
+
+impl fmt::Display for SmoothingMethod {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match *self {
+            SmoothingMethod::Off => "No smoothing",
+            SmoothingMethod::Median => "Median",
+            SmoothingMethod::Viterbi => "Viterbi"
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for SmoothingMethod {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<SmoothingMethod, String> {
+        SmoothingMethod::all().into_iter()
+            .find(|m| m.id() == s.to_lowercase())
+            .ok_or_else(|| format!("Unknown smoothing method: {}", s))
+    }
+}
+
+// Latency is how many frames after a frame arrives its smoothed pitch
+// comes out. Each frame is smoothed using that many frames either
+// side of it, so a longer latency rides out longer runs of bad frames
+// at the cost of lagging behind the playing.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Smoothing {
+    pub method: SmoothingMethod,
+    pub latency: usize
+}
+
+impl Default for Smoothing {
+    fn default() -> Smoothing {
+        Smoothing {
+            method: SmoothingMethod::default(),
+            latency: 3
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct SmoothedPitch {
+    pub time: f32,
+    pub pitch: Option<Pitch>
+}
+
+// Cleans up the pitches coming out of Model::from_signal one frame at
+// a time, using the frames around each one to correct octave errors
+// and steady the jitter.
+#[derive(Debug, Clone)]
+pub struct PitchSmoother {
+    smoothing: Smoothing,
+    frames: VecDeque<SmoothedPitch>,
+    // index in frames of the next one to be smoothed
+    next: usize
+}
+
+impl PitchSmoother {
+    pub fn new(smoothing: Smoothing) -> PitchSmoother {
+        PitchSmoother {
+            smoothing: smoothing,
+            frames: VecDeque::new(),
+            next: 0
+        }
+    }
+
+    pub fn smoothing(&self) -> Smoothing {
+        self.smoothing
+    }
+
+    // Changing the smoothing starts afresh, dropping any frames that
+    // haven't come out yet.
+    pub fn set_smoothing(&mut self, smoothing: Smoothing) {
+        if smoothing != self.smoothing {
+            *self = PitchSmoother::new(smoothing);
+        }
+    }
+
+    pub fn latency(&self) -> usize {
+        match self.smoothing.method {
+            SmoothingMethod::Off => 0,
+            _ => self.smoothing.latency
+        }
+    }
+
+    // Takes the latest frame, and returns the smoothed frame from
+    // latency frames ago once there is one.
+    pub fn push(&mut self, time: f32, pitch: Option<Pitch>) -> Option<SmoothedPitch> {
+        self.frames.push_back(SmoothedPitch {
+            time: time,
+            pitch: pitch
+        });
+        let latency = self.latency();
+        if self.next + latency >= self.frames.len() {
+            return None;
+        }
+        let smoothed = self.smooth(self.next);
+        self.next += 1;
+        while self.frames.len() > 2 * latency + 1 {
+            self.frames.pop_front();
+            self.next -= 1;
+        }
+        Some(smoothed)
+    }
+
+    // Smooths the frames still waiting on frames after them, using
+    // what there is. Call this at the end of a recording.
+    pub fn finish(&mut self) -> Vec<SmoothedPitch> {
+        let smoothed = (self.next..self.frames.len()).map(|i| self.smooth(i)).collect();
+        self.frames.clear();
+        self.next = 0;
+        smoothed
+    }
+
+    fn smooth(&self, centre: usize) -> SmoothedPitch {
+        let latency = self.latency();
+        let start = centre.saturating_sub(latency);
+        let end = (centre + latency + 1).min(self.frames.len());
+        let window: Vec<Option<Pitch>> = self.frames.iter().skip(start).take(end - start).map(|f| f.pitch).collect();
+        let pitch = match self.smoothing.method {
+            SmoothingMethod::Off => window[centre - start],
+            SmoothingMethod::Median => median_smooth(&window, centre - start),
+            SmoothingMethod::Viterbi => viterbi_smooth(&window, centre - start)
+        };
+        SmoothedPitch {
+            time: self.frames[centre].time,
+            pitch: pitch
+        }
+    }
+}
+
+impl Default for PitchSmoother {
+    fn default() -> PitchSmoother {
+        PitchSmoother::new(Smoothing::default())
+    }
+}
+
+// Pitches are compared in octaves, so that an octave error is always a
+// whole number away whatever the note.
+fn octaves(pitch: Pitch) -> f32 {
+    pitch.hz.log2()
+}
+
+fn with_octaves(pitch: Pitch, octaves: f32) -> Pitch {
+    Pitch::with_reference(2.0f32.powf(octaves), pitch.reference)
+}
+
+fn usable(pitch: Option<Pitch>) -> Option<Pitch> {
+    pitch.filter(|p| p.hz.is_finite() && p.hz > 0.0)
+}
+
+fn median(values: &mut [f32]) -> f32 {
+    values.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    values[values.len() / 2]
+}
+
+// Moves value by whole octaves towards reference, if it's close
+// enough to a whole number of octaves away to be an octave error.
+fn fold_octaves(value: f32, reference: f32) -> f32 {
+    let shift = (reference - value).round();
+    if (reference - value - shift).abs() < OCTAVE_TOLERANCE {
+        value + shift
+    }
+    else {
+        value
+    }
+}
+
+fn median_smooth(window: &[Option<Pitch>], centre: usize) -> Option<Pitch> {
+    let pitch = usable(window[centre])?;
+    let mut values: Vec<f32> = window.iter().filter_map(|&p| usable(p)).map(octaves).collect();
+    let reference = median(&mut values);
+    let mut folded: Vec<f32> = values.iter().map(|&v| fold_octaves(v, reference)).collect();
+    Some(with_octaves(pitch, median(&mut folded)))
+}
+
+fn viterbi_smooth(window: &[Option<Pitch>], centre: usize) -> Option<Pitch> {
+    let pitch = usable(window[centre])?;
+    // only the unbroken run of pitches around the centre is relevant
+    let first = window[..centre].iter().rposition(|&p| usable(p).is_none()).map_or(0, |i| i + 1);
+    let last = window[centre..].iter().position(|&p| usable(p).is_none()).map_or(window.len(), |i| centre + i);
+    let run: Vec<f32> = window[first..last].iter().filter_map(|&p| usable(p)).map(octaves).collect();
+
+    // each frame can be moved up to two octaves either way, since
+    // detectors can lock on to the fourth harmonic as well as the second
+    let shifts = [-2.0, -1.0, 0.0, 1.0, 2.0];
+    let correction_cost = |shift: f32| shift.abs() * OCTAVE_CORRECTION_COST;
+    let mut costs: Vec<f32> = shifts.iter().map(|&s| correction_cost(s)).collect();
+    let mut backtrack: Vec<Vec<usize>> = Vec::with_capacity(run.len());
+    for i in 1..run.len() {
+        let mut new_costs = Vec::with_capacity(shifts.len());
+        let mut from = Vec::with_capacity(shifts.len());
+        for &shift in &shifts {
+            let value = run[i] + shift;
+            let (best, cost) = shifts.iter()
+                .enumerate()
+                .map(|(j, &previous_shift)| {
+                    let jump = (value - run[i - 1] - previous_shift).abs() * 12.0;
+                    (j, costs[j] + jump)
+                })
+                .fold((0, f32::INFINITY), |(bi, bc), (j, c)| if c < bc { (j, c) } else { (bi, bc) });
+            new_costs.push(cost + correction_cost(shift));
+            from.push(best);
+        }
+        costs = new_costs;
+        backtrack.push(from);
+    }
+
+    let mut state = costs.iter()
+        .enumerate()
+        .fold((0, f32::INFINITY), |(bi, bc), (j, &c)| if c < bc { (j, c) } else { (bi, bc) })
+        .0;
+    for i in (centre - first..run.len() - 1).rev() {
+        state = backtrack[i][state];
+    }
+    Some(with_octaves(pitch, run[centre - first] + shifts[state]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn smooth_all(smoothing: Smoothing, hz: &[Option<f32>]) -> Vec<Option<f32>> {
+        let mut smoother = PitchSmoother::new(smoothing);
+        let mut smoothed: Vec<SmoothedPitch> = hz.iter()
+            .enumerate()
+            .filter_map(|(i, hz)| smoother.push(i as f32, hz.map(Pitch::new)))
+            .collect();
+        smoothed.extend(smoother.finish());
+        for (i, frame) in smoothed.iter().enumerate() {
+            assert_eq!(frame.time, i as f32, "frames came out of order");
+        }
+        smoothed.into_iter().map(|f| f.pitch.map(|p| p.hz)).collect()
+    }
+
+    fn assert_near(actual: &[Option<f32>], expected: &[Option<f32>]) {
+        assert_eq!(actual.len(), expected.len());
+        for (i, (a, e)) in actual.iter().zip(expected.iter()).enumerate() {
+            match (*a, *e) {
+                (Some(a), Some(e)) => assert!((a - e).abs() < 0.01, "frame {}: {} != {}", i, a, e),
+                (None, None) => {},
+                _ => panic!("frame {}: {:?} != {:?}", i, a, e)
+            }
+        }
+    }
+
+    fn smoothing(method: SmoothingMethod, latency: usize) -> Smoothing {
+        Smoothing {
+            method: method,
+            latency: latency
+        }
+    }
+
+    // a steady A with octave errors injected at the given frames
+    fn with_errors(len: usize, errors: &[(usize, f32)]) -> Vec<Option<f32>> {
+        (0..len)
+            .map(|i| Some(errors.iter().find(|e| e.0 == i).map_or(440.0, |e| e.1)))
+            .collect()
+    }
+
+    #[test]
+    fn off_passes_frames_straight_through() {
+        let hz = with_errors(10, &[(4, 880.0)]);
+        let mut smoother = PitchSmoother::new(smoothing(SmoothingMethod::Off, 5));
+        assert!(smoother.push(0.0, Some(Pitch::new(440.0))).is_some());
+        assert_near(&smooth_all(smoothing(SmoothingMethod::Off, 5), &hz), &hz);
+    }
+
+    #[test]
+    fn output_lags_by_the_latency() {
+        let mut smoother = PitchSmoother::new(smoothing(SmoothingMethod::Median, 2));
+        assert!(smoother.push(0.0, Some(Pitch::new(440.0))).is_none());
+        assert!(smoother.push(1.0, Some(Pitch::new(440.0))).is_none());
+        assert_eq!(smoother.push(2.0, Some(Pitch::new(440.0))).map(|f| f.time), Some(0.0));
+        assert_eq!(smoother.finish().len(), 2);
+    }
+
+    #[test]
+    fn every_method_fixes_isolated_octave_errors() {
+        let hz = with_errors(20, &[(3, 880.0), (9, 220.0), (15, 1760.0)]);
+        for method in [SmoothingMethod::Median, SmoothingMethod::Viterbi] {
+            assert_near(&smooth_all(smoothing(method, 2), &hz), &[Some(440.0); 20]);
+        }
+    }
+
+    #[test]
+    fn viterbi_fixes_runs_of_octave_errors() {
+        let hz = with_errors(20, &[(8, 880.0), (9, 880.0), (10, 880.0)]);
+        assert_near(&smooth_all(smoothing(SmoothingMethod::Viterbi, 4), &hz), &[Some(440.0); 20]);
+    }
+
+    #[test]
+    fn longer_latency_rides_out_longer_errors() {
+        let hz = with_errors(20, &[(8, 880.0), (9, 880.0)]);
+        let short = smooth_all(smoothing(SmoothingMethod::Median, 1), &hz);
+        assert!(short.contains(&Some(880.0)));
+        assert_near(&smooth_all(smoothing(SmoothingMethod::Median, 3), &hz), &[Some(440.0); 20]);
+    }
+
+    #[test]
+    fn genuine_octave_leaps_are_kept() {
+        let hz: Vec<Option<f32>> = (0..20).map(|i| Some(if i < 10 { 220.0 } else { 440.0 })).collect();
+        for method in [SmoothingMethod::Median, SmoothingMethod::Viterbi] {
+            let smoothed = smooth_all(smoothing(method, 3), &hz);
+            assert_near(&smoothed[..8], &hz[..8]);
+            assert_near(&smoothed[12..], &hz[12..]);
+        }
+    }
+
+    #[test]
+    fn other_note_changes_are_not_folded() {
+        // a scale, one note every five frames
+        let scale = [440.0, 493.88, 554.37, 587.33];
+        let hz: Vec<Option<f32>> = (0..20).map(|i| Some(scale[i / 5])).collect();
+        for method in [SmoothingMethod::Median, SmoothingMethod::Viterbi] {
+            assert_near(&smooth_all(smoothing(method, 2), &hz), &hz);
+        }
+    }
+
+    #[test]
+    fn unvoiced_frames_stay_unvoiced() {
+        let mut hz = with_errors(12, &[(7, 880.0)]);
+        hz[5] = None;
+        hz[6] = None;
+        for method in [SmoothingMethod::Median, SmoothingMethod::Viterbi] {
+            let smoothed = smooth_all(smoothing(method, 2), &hz);
+            assert!(smoothed[5].is_none() && smoothed[6].is_none());
+            assert_eq!(smoothed.iter().filter(|p| p.is_some()).count(), 10);
+            assert_eq!(smoothed[7], Some(440.0));
+        }
+    }
+
+    #[test]
+    fn methods_parse_from_their_ids() {
+        for method in SmoothingMethod::all() {
+            assert_eq!(method.id().parse::<SmoothingMethod>(), Ok(method));
+        }
+        assert!("kalman".parse::<SmoothingMethod>().is_err());
+    }
+}
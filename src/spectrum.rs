@@ -0,0 +1,217 @@
+use std::collections::VecDeque;
+use std::f32::consts::PI;
+use std::iter;
+
+use signal::Signal;
+use fft::{Complex, fft};
+
+// Anything quieter than this is drawn as silence, and keeps log10
+// away from zero.
+pub const FLOOR_DB: f32 = -120.0;
+
+#[derive(Debug, Default, Clone)]
+pub struct Spectrum {
+    // Magnitude of each bin from 0Hz up to the Nyquist frequency, in
+    // dB relative to a full scale sine wave.
+    pub decibels: Vec<f32>,
+    pub bin_width: f32
+}
+
+impl Spectrum {
+    pub fn from_signal(signal: &Signal) -> Spectrum {
+        let samples = &signal.samples;
+        if samples.is_empty() {
+            return Spectrum::default();
+        }
+
+        let fft_len = samples.len().next_power_of_two();
+        let window = hann_window(samples.len());
+        let window_sum: f32 = window.iter().sum();
+
+        let mut buffer: Vec<Complex> = samples.iter()
+            .zip(window.iter())
+            .map(|(&x, &w)| Complex::new(x * w, 0.0))
+            .chain(iter::repeat(Complex::default()))
+            .take(fft_len)
+            .collect();
+        fft(&mut buffer);
+
+        // Only half the energy of a real sine lands in the positive
+        // frequencies, hence the factor of 2.
+        let scale = if window_sum > 0.0 { 2.0 / window_sum } else { 0.0 };
+        Spectrum {
+            decibels: buffer.iter()
+                .take(fft_len / 2 + 1)
+                .map(|c| (20.0 * (c.norm() * scale).log10()).max(FLOOR_DB))
+                .collect(),
+            bin_width: signal.sample_rate / fft_len as f32
+        }
+    }
+
+    pub fn frequency(&self, bin: usize) -> f32 {
+        bin as f32 * self.bin_width
+    }
+
+    pub fn max_frequency(&self) -> f32 {
+        self.frequency(self.decibels.len().saturating_sub(1))
+    }
+
+    pub fn decibels_at(&self, hz: f32) -> f32 {
+        if self.bin_width <= 0.0 {
+            return FLOOR_DB;
+        }
+        let bin = (hz / self.bin_width).round();
+        if bin < 0.0 {
+            FLOOR_DB
+        }
+        else {
+            self.decibels.get(bin as usize).cloned().unwrap_or(FLOOR_DB)
+        }
+    }
+
+    pub fn peak_frequency(&self) -> Option<f32> {
+        self.decibels.iter()
+            .enumerate()
+            .skip(1)
+            .fold(None, |max: Option<(usize, f32)>, (i, &db)| match max {
+                Some((_, max_db)) if max_db >= db => max,
+                _ => Some((i, db))
+            })
+            .map(|(i, _)| self.frequency(i))
+    }
+
+    // Integer multiples of the fundamental that fit in the spectrum.
+    pub fn harmonics(&self, fundamental: f32) -> Vec<f32> {
+        if !fundamental.is_finite() || fundamental <= 0.0 {
+            return Vec::new();
+        }
+        let max_frequency = self.max_frequency();
+        (1..)
+            .map(|i| i as f32 * fundamental)
+            .take_while(|&hz| hz <= max_frequency)
+            .collect()
+    }
+}
+
+fn hann_window(len: usize) -> Vec<f32> {
+    if len < 2 {
+        return vec!(1.0; len);
+    }
+    (0..len)
+        .map(|i| 0.5 - 0.5 * (2.0 * PI * i as f32 / (len - 1) as f32).cos())
+        .collect()
+}
+
+// Where a frequency falls on a logarithmic axis, as a fraction from 0
+// at min_hz to 1 at max_hz.
+pub fn log_frequency_position(hz: f32, min_hz: f32, max_hz: f32) -> f32 {
+    (hz / min_hz).ln() / (max_hz / min_hz).ln()
+}
+
+// The inverse of log_frequency_position.
+pub fn log_frequency_at(position: f32, min_hz: f32, max_hz: f32) -> f32 {
+    min_hz * (max_hz / min_hz).powf(position)
+}
+
+#[derive(Debug, Clone)]
+pub struct Spectrogram {
+    pub capacity: usize,
+    columns: VecDeque<Spectrum>
+}
+
+impl Spectrogram {
+    pub fn new(capacity: usize) -> Spectrogram {
+        Spectrogram {
+            capacity: capacity,
+            columns: VecDeque::with_capacity(capacity)
+        }
+    }
+
+    pub fn push(&mut self, spectrum: Spectrum) {
+        while self.columns.len() >= self.capacity && !self.columns.is_empty() {
+            self.columns.pop_front();
+        }
+        if self.capacity > 0 {
+            self.columns.push_back(spectrum);
+        }
+    }
+
+    // oldest first
+    pub fn columns(&self) -> &VecDeque<Spectrum> {
+        &self.columns
+    }
+}
+
+impl Default for Spectrogram {
+    fn default() -> Spectrogram {
+        Spectrogram::new(200)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: f32 = 44100.0;
+
+    fn sine(frequency: f32, amplitude: f32, len: usize) -> Signal {
+        let samples: Vec<f32> = (0..len)
+            .map(|i| amplitude * (2.0 * PI * frequency * i as f32 / SAMPLE_RATE).sin())
+            .collect();
+        Signal::new(&samples, SAMPLE_RATE)
+    }
+
+    #[test]
+    fn sine_peaks_at_its_frequency() {
+        let spectrum = Spectrum::from_signal(&sine(1000.0, 1.0, 2048));
+        let peak = spectrum.peak_frequency().unwrap();
+        assert!((peak - 1000.0).abs() <= spectrum.bin_width, "peak={}", peak);
+    }
+
+    #[test]
+    fn full_scale_sine_is_near_zero_db() {
+        // exactly on a bin, so there's no scalloping loss
+        let bin_width = SAMPLE_RATE / 2048.0;
+        let spectrum = Spectrum::from_signal(&sine(bin_width * 40.0, 1.0, 2048));
+        let db = spectrum.decibels_at(bin_width * 40.0);
+        assert!(db.abs() < 0.1, "db={}", db);
+
+        let quieter = Spectrum::from_signal(&sine(bin_width * 40.0, 0.1, 2048));
+        assert!((quieter.decibels_at(bin_width * 40.0) - -20.0).abs() < 0.1);
+    }
+
+    #[test]
+    fn spectrum_covers_dc_to_nyquist() {
+        let spectrum = Spectrum::from_signal(&sine(440.0, 1.0, 512));
+        assert_eq!(spectrum.decibels.len(), 257);
+        assert_eq!(spectrum.max_frequency(), SAMPLE_RATE / 2.0);
+    }
+
+    #[test]
+    fn harmonics_stop_at_nyquist() {
+        let spectrum = Spectrum::from_signal(&sine(440.0, 1.0, 512));
+        let harmonics = spectrum.harmonics(5000.0);
+        assert_eq!(harmonics, vec!(5000.0, 10000.0, 15000.0, 20000.0));
+        assert!(spectrum.harmonics(f32::NAN).is_empty());
+    }
+
+    #[test]
+    fn log_frequency_axis_round_trips() {
+        assert_eq!(log_frequency_position(50.0, 50.0, 5000.0), 0.0);
+        assert!((log_frequency_position(500.0, 50.0, 5000.0) - 0.5).abs() < 0.0001);
+        assert!((log_frequency_at(0.5, 50.0, 5000.0) - 500.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn spectrogram_keeps_latest_columns() {
+        let mut spectrogram = Spectrogram::new(3);
+        for i in 0..5 {
+            spectrogram.push(Spectrum {
+                decibels: vec!(i as f32),
+                bin_width: 1.0
+            });
+        }
+        let columns: Vec<f32> = spectrogram.columns().iter().map(|c| c.decibels[0]).collect();
+        assert_eq!(columns, vec!(2.0, 3.0, 4.0));
+    }
+}
@@ -0,0 +1,20 @@
+// Synthetic signals shared by the tests of the detectors.
+
+use std::f32::consts::PI;
+
+use signal::Signal;
+
+pub const SAMPLE_RATE: f32 = 44100.0;
+
+// A tone with the given amplitude for each harmonic, starting with the
+// fundamental.
+pub fn sample_harmonics(frequency: f32, harmonics: &[f32], frames: usize) -> Signal {
+    let samples: Vec<f32> = (0..frames)
+        .map(|x| {
+            let t = x as f32 / SAMPLE_RATE;
+            harmonics.iter().enumerate()
+                .map(|(i, amplitude)| amplitude * (2.0 * PI * frequency * (i + 1) as f32 * t).sin())
+                .sum()
+        }).collect();
+    Signal::new(&samples, SAMPLE_RATE)
+}
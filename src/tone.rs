@@ -0,0 +1,175 @@
+use std::f64::consts::PI;
+use std::fmt;
+use std::str::FromStr;
+
+use pitch::Pitch;
+use signal::Signal;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Waveform {
+    Sine,
+    // Every harmonic up to the Nyquist frequency, falling off as 1/n
+    // like a sawtooth. Much easier to hear beats against than a sine.
+    #[default]
+    Rich
+}
+
+impl Waveform {
+    pub fn all() -> Vec<Waveform> {
+        vec!(Waveform::Sine, Waveform::Rich)
+    }
+
+    pub fn id(&self) -> &'static str {
+        match *self {
+            Waveform::Sine => "sine",
+            Waveform::Rich => "rich"
+        }
+    }
+}
+
+impl fmt::Display for Waveform {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Waveform::Sine => write!(f, "Sine"),
+            Waveform::Rich => write!(f, "Rich")
+        }
+    }
+}
+
+impl FromStr for Waveform {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Waveform, String> {
+        Waveform::all().into_iter()
+            .find(|w| w.id() == s.to_lowercase())
+            .ok_or_else(|| format!("Unknown waveform {}, expected sine or rich", s))
+    }
+}
+
+// Samples in the single cycle wavetable. Plenty for linear
+// interpolation to be inaudible.
+const TABLE_SIZE: usize = 4096;
+
+#[derive(Debug, Clone)]
+pub struct ToneGenerator {
+    pub hz: f32,
+    pub waveform: Waveform,
+    pub amplitude: f32,
+    pub sample_rate: f32,
+    // in cycles, kept between 0 and 1 so long drones don't lose
+    // precision
+    phase: f64,
+    // One cycle of the waveform, worked out up front since summing
+    // hundreds of harmonics per sample is too slow for an audio
+    // callback on low notes.
+    table: Vec<f32>
+}
+
+impl ToneGenerator {
+    pub fn new(pitch: Pitch, waveform: Waveform, sample_rate: f32) -> ToneGenerator {
+        let harmonics = match waveform {
+            Waveform::Sine => 1,
+            Waveform::Rich if pitch.hz > 0.0 => (sample_rate / 2.0 / pitch.hz).floor().max(1.0) as usize,
+            Waveform::Rich => 1
+        };
+        // Scale so the harmonics can't add up to more than the
+        // amplitude. Real peaks are lower, but this is safe.
+        let total: f64 = (1..harmonics + 1).map(|n| 1.0 / n as f64).sum();
+        let table = (0..TABLE_SIZE)
+            .map(|i| {
+                let phase = i as f64 / TABLE_SIZE as f64;
+                let value: f64 = (1..harmonics + 1)
+                    .map(|n| (2.0 * PI * n as f64 * phase).sin() / n as f64)
+                    .sum();
+                (value / total) as f32
+            })
+            .collect();
+
+        ToneGenerator {
+            hz: pitch.hz,
+            waveform: waveform,
+            amplitude: 0.5,
+            sample_rate: sample_rate,
+            phase: 0.0,
+            table: table
+        }
+    }
+
+    pub fn fill(&mut self, buffer: &mut [f32]) {
+        let step = f64::from(self.hz) / f64::from(self.sample_rate);
+        for sample in buffer.iter_mut() {
+            let position = self.phase * TABLE_SIZE as f64;
+            let index = position.floor() as usize % TABLE_SIZE;
+            let next = (index + 1) % TABLE_SIZE;
+            let fraction = position.fract() as f32;
+            let value = self.table[index] + (self.table[next] - self.table[index]) * fraction;
+            *sample = self.amplitude * value;
+            self.phase = (self.phase + step).fract();
+        }
+    }
+
+    pub fn render(&mut self, len: usize) -> Signal {
+        let mut samples = vec!(0.0; len);
+        self.fill(&mut samples);
+        Signal::new(&samples, self.sample_rate)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pitch::TuningReference;
+    use model::{Model, AnalysisSettings};
+
+    const SAMPLE_RATE: f32 = 44100.0;
+
+    #[test]
+    fn rendered_tones_are_detected_at_their_note() {
+        for waveform in Waveform::all() {
+            for &(semitone, octave, name) in &[(9, 4, "A 4"), (10, 3, "B♭3"), (0, 5, "C 5")] {
+                let pitch = Pitch::from_note(semitone, octave, TuningReference::default());
+                let signal = ToneGenerator::new(pitch, waveform, SAMPLE_RATE).render(2048);
+                let model = Model::from_signal(signal, &AnalysisSettings::default());
+                let detected = model.pitch.expect("Expected a pitch from the generated tone");
+                assert_eq!(format!("{}", detected), name, "{} wave", waveform);
+                assert!(detected.cents_error().abs() < 5.0, "{} wave was {} cents out", waveform, detected.cents_error());
+            }
+        }
+    }
+
+    #[test]
+    fn tone_is_continuous_across_buffers() {
+        let pitch = Pitch::new(440.0);
+        let whole = ToneGenerator::new(pitch, Waveform::Rich, SAMPLE_RATE).render(1000);
+
+        let mut generator = ToneGenerator::new(pitch, Waveform::Rich, SAMPLE_RATE);
+        let mut pieces = vec!(0.0; 1000);
+        for chunk in pieces.chunks_mut(300) {
+            generator.fill(chunk);
+        }
+        let pieces = Signal::new(&pieces, SAMPLE_RATE);
+
+        for (a, b) in whole.samples.iter().zip(pieces.samples.iter()) {
+            assert!((a - b).abs() < 0.0001);
+        }
+    }
+
+    #[test]
+    fn tone_stays_within_amplitude() {
+        for waveform in Waveform::all() {
+            let mut samples = vec!(0.0; 4096);
+            ToneGenerator::new(Pitch::new(110.0), waveform, SAMPLE_RATE).fill(&mut samples);
+            assert!(samples.iter().all(|x| x.abs() <= 0.5 + 0.0001));
+        }
+    }
+
+    #[test]
+    fn rich_tone_has_harmonics_sine_does_not() {
+        use spectrum::Spectrum;
+        let pitch = Pitch::new(440.0);
+        let sine = Spectrum::from_signal(&ToneGenerator::new(pitch, Waveform::Sine, SAMPLE_RATE).render(4096));
+        let rich = Spectrum::from_signal(&ToneGenerator::new(pitch, Waveform::Rich, SAMPLE_RATE).render(4096));
+        assert!(sine.decibels_at(1320.0) < -60.0);
+        assert!(rich.decibels_at(1320.0) > -40.0);
+    }
+}
@@ -0,0 +1,174 @@
+use std::fmt;
+
+use model::{Model, AnalysisSettings};
+use pitch::{Pitch, Transposition, NoteNaming};
+use scala::ScalaTuning;
+use frames::SlidingWindow;
+use smoothing::PitchSmoother;
+use wav::Wav;
+
+#[derive(Debug, Clone, Copy)]
+pub struct TrackPoint {
+    // seconds from the start of the recording to the middle of the
+    // analysed frame
+    pub time: f32,
+    pub pitch: Option<Pitch>,
+    pub clarity: f32,
+    // RMS of the frame
+    pub level: f32
+}
+
+impl TrackPoint {
+    pub fn from_model(time: f32, model: &Model) -> TrackPoint {
+        TrackPoint {
+            time: time,
+            pitch: model.pitch,
+            clarity: model.estimate.clarity,
+            level: model.signal.rms()
+        }
+    }
+
+    // One tab separated line, matching TRACK_HEADER. The frequency is
+    // always concert pitch, but the note is written for the
+    // instrument. Frames without a pitch have their pitch columns
+    // left as "-".
+    pub fn row(&self, transposition: Transposition, naming: &NoteNaming) -> String {
+        match self.pitch {
+            Some(pitch) => format!("{:.3}\t{:.2}\t{}\t{:+.1}\t{:.2}",
+                                   self.time, pitch.hz, pitch.written(transposition).name(naming).replace(' ', ""),
+                                   pitch.cents_error(), self.clarity),
+            None => format!("{:.3}\t-\t-\t-\t{:.2}", self.time, self.clarity)
+        }
+    }
+
+    // The same row, but with the note and cents measured against a
+    // tuning from Scala files.
+    pub fn row_in(&self, tuning: &ScalaTuning, naming: &NoteNaming) -> String {
+        match self.pitch.and_then(|pitch| pitch.nearest_in(tuning).map(|note| (pitch, note))) {
+            Some((pitch, note)) => format!("{:.3}\t{:.2}\t{}\t{:+.1}\t{:.2}",
+                                           self.time, pitch.hz, note.name(naming).replace(' ', ""),
+                                           note.cents_error, self.clarity),
+            None => format!("{:.3}\t-\t-\t-\t{:.2}", self.time, self.clarity)
+        }
+    }
+}
+
+pub const TRACK_HEADER: &str = "time\thz\tnote\tcents\tclarity";
+
+impl fmt::Display for TrackPoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.row(Transposition::C, &NoteNaming::default()))
+    }
+}
+
+pub fn analyze_samples(samples: &[f32], sample_rate: f32, frame_size: usize, hop_size: usize, settings: &AnalysisSettings) -> Vec<TrackPoint> {
+    if frame_size == 0 || hop_size == 0 {
+        return Vec::new();
+    }
+
+    let mut window = SlidingWindow::new(frame_size, hop_size);
+    window.push(samples);
+    let mut track: Vec<TrackPoint> = window.frames(sample_rate).into_iter()
+        .map(|frame| {
+            let time = frame.centre_time();
+            let model = Model::from_signal(frame.signal, settings);
+            TrackPoint::from_model(time, &model)
+        })
+        .collect();
+
+    // the smoother gives back one pitch per frame, in order, so they
+    // can be matched back up with the points they came from
+    let mut smoother = PitchSmoother::new(settings.smoothing);
+    let mut smoothed: Vec<_> = track.iter().filter_map(|p| smoother.push(p.time, p.pitch)).collect();
+    smoothed.extend(smoother.finish());
+    for (point, smoothed) in track.iter_mut().zip(smoothed) {
+        point.pitch = smoothed.pitch;
+    }
+    track
+}
+
+pub fn analyze_wav(wav: &Wav, frame_size: usize, hop_size: usize, settings: &AnalysisSettings) -> Vec<TrackPoint> {
+    analyze_samples(&wav.samples, wav.sample_rate as f32, frame_size, hop_size, settings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::PI;
+    use frames::DEFAULT_FRAME_SIZE;
+    use smoothing::{Smoothing, SmoothingMethod};
+    use pitch::NamingSystem;
+
+    const SAMPLE_RATE: f32 = 44100.0;
+
+    fn tone_then_silence(frequency: f32, tone_seconds: f32, silence_seconds: f32) -> Vec<f32> {
+        let tone_samples = (tone_seconds * SAMPLE_RATE) as usize;
+        let silence_samples = (silence_seconds * SAMPLE_RATE) as usize;
+        (0..tone_samples)
+            .map(|i| 0.5 * (2.0 * PI * frequency * i as f32 / SAMPLE_RATE).sin())
+            .chain((0..silence_samples).map(|_| 0.0))
+            .collect()
+    }
+
+    #[test]
+    fn track_follows_tone_and_silence() {
+        let samples = tone_then_silence(440.0, 0.5, 0.5);
+        let track = analyze_samples(&samples, SAMPLE_RATE, DEFAULT_FRAME_SIZE, DEFAULT_FRAME_SIZE, &AnalysisSettings::default());
+
+        assert_eq!(track.len(), samples.len() / DEFAULT_FRAME_SIZE);
+        for point in track.iter().filter(|p| p.time < 0.45) {
+            let pitch = point.pitch.expect("Expected a pitch during the tone");
+            assert_eq!(format!("{}", pitch), "A 4");
+        }
+        for point in track.iter().filter(|p| p.time > 0.55) {
+            assert!(point.pitch.is_none(), "Expected no pitch during silence at {}", point.time);
+        }
+    }
+
+    #[test]
+    fn smoothing_keeps_every_frame() {
+        let samples = tone_then_silence(440.0, 0.5, 0.5);
+        let settings = AnalysisSettings {
+            smoothing: Smoothing {
+                method: SmoothingMethod::Viterbi,
+                latency: 4
+            },
+            ..AnalysisSettings::default()
+        };
+        let unsmoothed = analyze_samples(&samples, SAMPLE_RATE, DEFAULT_FRAME_SIZE, 512, &AnalysisSettings::default());
+        let smoothed = analyze_samples(&samples, SAMPLE_RATE, DEFAULT_FRAME_SIZE, 512, &settings);
+        assert_eq!(smoothed.len(), unsmoothed.len());
+        for (a, b) in smoothed.iter().zip(unsmoothed.iter()) {
+            assert_eq!(a.time, b.time);
+            assert_eq!(a.pitch.is_some(), b.pitch.is_some(), "at {}", a.time);
+        }
+    }
+
+    #[test]
+    fn track_times_are_frame_centres() {
+        let samples = vec!(0.0; 4096);
+        let track = analyze_samples(&samples, 1000.0, 1000, 500, &AnalysisSettings::default());
+        let times: Vec<f32> = track.iter().map(|p| p.time).collect();
+        assert_eq!(times, vec!(0.5, 1.0, 1.5, 2.0, 2.5, 3.0, 3.5));
+    }
+
+    #[test]
+    fn short_recordings_have_empty_tracks() {
+        let track = analyze_samples(&[0.1; 10], SAMPLE_RATE, DEFAULT_FRAME_SIZE, DEFAULT_FRAME_SIZE, &AnalysisSettings::default());
+        assert!(track.is_empty());
+    }
+
+    #[test]
+    fn track_points_format_as_rows() {
+        let point = TrackPoint {
+            time: 1.5,
+            pitch: Some(Pitch::new(440.0)),
+            clarity: 0.95,
+            level: 0.5
+        };
+        assert_eq!(format!("{}", point), "1.500\t440.00\tA4\t+0.0\t0.95");
+        assert_eq!(point.row(Transposition::BFlat, &NoteNaming::default()), "1.500\t440.00\tB4\t+0.0\t0.95");
+        let german = NoteNaming { system: NamingSystem::German, ..NoteNaming::default() };
+        assert_eq!(point.row(Transposition::BFlat, &german), "1.500\t440.00\tH4\t+0.0\t0.95");
+    }
+}
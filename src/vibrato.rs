@@ -0,0 +1,250 @@
+use std::fmt;
+
+use detector::parabolic_interpolation;
+use history::PitchHistory;
+
+// Slower than this is a wobble rather than vibrato, and faster is a
+// trill or tremolo.
+const MIN_RATE: f32 = 3.0;
+const MAX_RATE: f32 = 10.0;
+// Shallower than this is just the normal unsteadiness of a held note.
+const MIN_DEPTH: f32 = 5.0;
+const MIN_REGULARITY: f32 = 0.5;
+// pitch jumps bigger than this mean a new note
+const NOTE_CHANGE_CENTS: f32 = 100.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Vibrato {
+    // cycles per second
+    pub rate: f32,
+    // how far the pitch swings either side of its centre, in cents
+    pub depth: f32,
+    // 1 for a perfectly even vibrato, falling towards 0 as the cycles
+    // vary in speed and size
+    pub regularity: f32
+}
+
+impl Vibrato {
+    // Looks for periodic modulation in a run of cents values, which
+    // don't need to be evenly spaced in time. Cents can be measured
+    // from anything, as long as it's the same thing throughout.
+    pub fn detect(times: &[f32], cents: &[f32]) -> Option<Vibrato> {
+        let len = times.len().min(cents.len());
+        if len < 4 {
+            return None;
+        }
+        let duration = times[len - 1] - times[0];
+        // need at least two cycles at the slowest rate
+        if duration < 2.0 / MIN_RATE {
+            return None;
+        }
+
+        let step = duration / (len - 1) as f32;
+        let samples = detrend(&resample(&times[..len], &cents[..len], step));
+
+        let min_lag = (1.0 / MAX_RATE / step).floor().max(1.0) as usize;
+        let max_lag = ((1.0 / MIN_RATE / step).ceil() as usize).min(samples.len() / 2);
+        if min_lag + 2 > max_lag {
+            return None;
+        }
+
+        let correlation: Vec<f32> = (0..max_lag + 2)
+            .map(|lag| normalized_autocorrelation(&samples, lag))
+            .collect();
+        // Multiples of the period correlate nearly as well as the
+        // period itself, so take the first peak that's close to the
+        // best rather than the best.
+        let peaks: Vec<usize> = (min_lag..max_lag + 1)
+            .filter(|&lag| correlation[lag] >= correlation[lag - 1] && correlation[lag] >= correlation[lag + 1])
+            .collect();
+        let highest = peaks.iter().map(|&lag| correlation[lag]).fold(0.0, f32::max);
+        let peak = peaks.into_iter().find(|&lag| correlation[lag] >= 0.9 * highest)?;
+
+        let period = parabolic_interpolation(&correlation, peak) * step;
+        let regularity = correlation[peak].clamp(0.0, 1.0);
+        // the standard deviation of a sine wave is its amplitude over √2
+        let variance = samples.iter().map(|x| x * x).sum::<f32>() / samples.len() as f32;
+        let depth = variance.sqrt() * 2.0f32.sqrt();
+
+        if regularity < MIN_REGULARITY || depth < MIN_DEPTH {
+            return None;
+        }
+        Some(Vibrato {
+            rate: 1.0 / period,
+            depth: depth,
+            regularity: regularity
+        })
+    }
+
+    // Vibrato in the note currently being played, looking back at most
+    // the given number of seconds.
+    pub fn from_history(history: &PitchHistory, seconds: f32) -> Option<Vibrato> {
+        let latest = history.latest_time()?;
+
+        let mut times = Vec::new();
+        let mut cents = Vec::new();
+        for point in history.points().iter().rev().take_while(|p| p.time >= latest - seconds) {
+            let point_cents = match point.pitch {
+                Some(pitch) => pitch.midi_number() * 100.0,
+                None => break
+            };
+            if cents.last().is_some_and(|&last: &f32| (last - point_cents).abs() > NOTE_CHANGE_CENTS) {
+                break;
+            }
+            times.push(point.time);
+            cents.push(point_cents);
+        }
+        times.reverse();
+        cents.reverse();
+        Vibrato::detect(&times, &cents)
+    }
+}
+
+impl fmt::Display for Vibrato {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Vibrato {:.1} Hz ±{:.0} cents, {:.0}% regular", self.rate, self.depth, self.regularity * 100.0)
+    }
+}
+
+// Linear interpolation onto an even grid, starting at the first time.
+fn resample(times: &[f32], values: &[f32], step: f32) -> Vec<f32> {
+    let len = ((times[times.len() - 1] - times[0]) / step).round() as usize + 1;
+    let mut source = 0;
+    (0..len)
+        .map(|i| {
+            let time = times[0] + i as f32 * step;
+            while source + 2 < times.len() && times[source + 1] < time {
+                source += 1;
+            }
+            let span = times[source + 1] - times[source];
+            let fraction = if span > 0.0 { ((time - times[source]) / span).clamp(0.0, 1.0) } else { 0.0 };
+            values[source] + (values[source + 1] - values[source]) * fraction
+        })
+        .collect()
+}
+
+// Removes the straight line of best fit, so a note that drifts
+// doesn't look like one long slow cycle.
+fn detrend(values: &[f32]) -> Vec<f32> {
+    let n = values.len() as f32;
+    let mean_x = (n - 1.0) / 2.0;
+    let mean_y = values.iter().sum::<f32>() / n;
+    let covariance: f32 = values.iter().enumerate().map(|(i, y)| (i as f32 - mean_x) * (y - mean_y)).sum();
+    let variance: f32 = (0..values.len()).map(|i| (i as f32 - mean_x).powi(2)).sum();
+    let slope = if variance > 0.0 { covariance / variance } else { 0.0 };
+    values.iter()
+        .enumerate()
+        .map(|(i, y)| y - mean_y - slope * (i as f32 - mean_x))
+        .collect()
+}
+
+fn normalized_autocorrelation(values: &[f32], lag: usize) -> f32 {
+    if lag >= values.len() {
+        return 0.0;
+    }
+    let (a, b) = (&values[..values.len() - lag], &values[lag..]);
+    let product: f32 = a.iter().zip(b.iter()).map(|(x, y)| x * y).sum();
+    let energy = a.iter().map(|x| x * x).sum::<f32>() * b.iter().map(|x| x * x).sum::<f32>();
+    if energy > 0.0 {
+        product / energy.sqrt()
+    }
+    else {
+        0.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::PI;
+    use model::AnalysisSettings;
+    use pitch::Pitch;
+    use track;
+
+    const SAMPLE_RATE: f32 = 44100.0;
+
+    // A sine wave whose frequency swings depth cents either side of
+    // hz, rate times a second.
+    fn frequency_modulated(hz: f32, rate: f32, depth: f32, seconds: f32) -> Vec<f32> {
+        let mut phase = 0.0f64;
+        (0..(seconds * SAMPLE_RATE) as usize)
+            .map(|i| {
+                let t = i as f32 / SAMPLE_RATE;
+                let cents = depth * (2.0 * PI * rate * t).sin();
+                let instantaneous = hz * 2.0f32.powf(cents / 1200.0);
+                phase += f64::from(instantaneous / SAMPLE_RATE);
+                0.5 * (2.0 * ::std::f64::consts::PI * phase).sin() as f32
+            })
+            .collect()
+    }
+
+    fn vibrato_in_recording(samples: &[f32]) -> Option<Vibrato> {
+        let track = track::analyze_samples(samples, SAMPLE_RATE, 2048, 512, &AnalysisSettings::default());
+        let mut history = PitchHistory::new(10.0);
+        for point in track {
+            history.push(point.time, point.pitch);
+        }
+        Vibrato::from_history(&history, 2.0)
+    }
+
+    #[test]
+    fn finds_vibrato_in_frequency_modulated_tone() {
+        let vibrato = vibrato_in_recording(&frequency_modulated(466.16, 5.5, 30.0, 2.5))
+            .expect("Expected vibrato");
+        assert!((vibrato.rate - 5.5).abs() < 0.3, "rate={}", vibrato.rate);
+        // the analysis frames smear the pitch a little, so the depth
+        // reads slightly low
+        assert!(vibrato.depth > 20.0 && vibrato.depth < 35.0, "depth={}", vibrato.depth);
+        assert!(vibrato.regularity > 0.8, "regularity={}", vibrato.regularity);
+    }
+
+    #[test]
+    fn steady_tone_has_no_vibrato() {
+        assert_eq!(vibrato_in_recording(&frequency_modulated(466.16, 5.5, 0.0, 2.5)), None);
+    }
+
+    fn sampled<F: Fn(f32) -> f32>(seconds: f32, step: f32, cents_at: F) -> (Vec<f32>, Vec<f32>) {
+        let times: Vec<f32> = (0..(seconds / step) as usize).map(|i| i as f32 * step).collect();
+        let cents = times.iter().map(|&t| cents_at(t)).collect();
+        (times, cents)
+    }
+
+    #[test]
+    fn measures_rate_and_depth() {
+        for &(rate, depth) in &[(4.0, 20.0), (6.0, 50.0), (8.0, 10.0)] {
+            let (times, cents) = sampled(2.0, 0.01, |t| depth * (2.0 * PI * rate * t).sin());
+            let vibrato = Vibrato::detect(&times, &cents).expect("Expected vibrato");
+            assert!((vibrato.rate - rate).abs() < 0.1, "rate={} expected {}", vibrato.rate, rate);
+            assert!((vibrato.depth - depth).abs() < depth * 0.05, "depth={} expected {}", vibrato.depth, depth);
+        }
+    }
+
+    #[test]
+    fn drifting_pitch_is_not_vibrato() {
+        let (times, cents) = sampled(2.0, 0.01, |t| 40.0 * t);
+        assert_eq!(Vibrato::detect(&times, &cents), None);
+    }
+
+    #[test]
+    fn irregular_wobble_is_less_regular() {
+        let (times, even) = sampled(2.0, 0.01, |t| 30.0 * (2.0 * PI * 5.0 * t).sin());
+        // speeding up from 4 to 7 Hz
+        let (_, uneven) = sampled(2.0, 0.01, |t| 30.0 * (2.0 * PI * (4.0 + 0.75 * t) * t).sin());
+        let even = Vibrato::detect(&times, &even).unwrap();
+        let uneven = Vibrato::detect(&times, &uneven).map_or(0.0, |v| v.regularity);
+        assert!(uneven < even.regularity);
+    }
+
+    #[test]
+    fn history_stops_at_note_changes() {
+        let mut history = PitchHistory::new(10.0);
+        for i in 0..200 {
+            let t = i as f32 * 0.01;
+            let cents = if t < 1.0 { 0.0 } else { 30.0 * (2.0 * PI * 5.0 * t).sin() };
+            let hz = if t < 1.0 { 220.0 } else { 440.0 * 2.0f32.powf(cents / 1200.0) };
+            history.push(t, Some(Pitch::new(hz)));
+        }
+        let vibrato = Vibrato::from_history(&history, 2.0).expect("Expected vibrato");
+        assert!((vibrato.rate - 5.0).abs() < 0.2);
+    }
+}
@@ -1,11 +1,16 @@
-use model::Model;
+use model::{Model, AnalysisSettings};
+use detector::Rejection;
 use signal::Signal;
-use pitch::Pitch;
+use analyzer::Analyzer;
+use spectrum::Spectrum;
+use vibrato::Vibrato;
+use pitch::{Pitch, TuningReference, NoteNaming, NamingSystem, Spelling, Accidentals, OctaveNotation};
 
 use std::os::raw::c_char;
 use std::os::raw::c_void;
 use std::mem;
 use std::ffi::CString;
+use std::ptr;
 use std::slice;
 use std::f32;
 
@@ -39,25 +44,67 @@ pub extern "C" fn find_fundamental_frequency(signal_ptr: *const f32, signal_leng
         &slice::from_raw_parts(signal_ptr, signal_length)
     };
     let signal = Signal::new(signal_slice, sample_rate);
-    let model = Model::from_signal(signal);
+    let model = Model::from_signal(signal, &AnalysisSettings::default());
 
     model.pitch.map_or(f32::NAN, |p| p.hz)
 }
 
+#[repr(C)]
+pub struct PitchEstimateResult {
+    hz: f32,
+    clarity: f32,
+    rejection: i32
+}
+
+fn rejection_code(rejection: Option<Rejection>) -> i32 {
+    match rejection {
+        None => 0,
+        Some(Rejection::Silence) => 1,
+        Some(Rejection::NoZeroCrossing) => 2,
+        Some(Rejection::Noise) => 3,
+        Some(Rejection::OutOfRange) => 4
+    }
+}
+
 #[no_mangle]
-pub extern "C" fn hz_to_cents_error(hz: f32) -> f32 {
-    let pitch = Pitch::new(hz);
+pub extern "C" fn find_pitch_estimate(signal_ptr: *const f32, signal_length: usize, sample_rate: f32, result_ptr: *mut PitchEstimateResult) {
+    let signal_slice = unsafe {
+        &slice::from_raw_parts(signal_ptr, signal_length)
+    };
+    let signal = Signal::new(signal_slice, sample_rate);
+    let estimate = Model::from_signal(signal, &AnalysisSettings::default()).estimate;
+
+    unsafe {
+        *result_ptr = PitchEstimateResult {
+            hz: estimate.hz,
+            clarity: estimate.clarity,
+            rejection: rejection_code(estimate.rejection)
+        };
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn hz_to_cents_error(hz: f32, a4_hz: f32) -> f32 {
+    let pitch = Pitch::with_reference(hz, TuningReference::new(a4_hz));
     pitch.cents_error()
 }
 
 #[no_mangle]
-pub extern "C" fn hz_to_pitch(hz: f32) -> *mut c_char {
-    let pitch = Pitch::new(hz);
+pub extern "C" fn hz_to_pitch(hz: f32, a4_hz: f32) -> *mut c_char {
+    let pitch = Pitch::with_reference(hz, TuningReference::new(a4_hz));
     CString::new(format!("{}", pitch))
 		.unwrap()
         .into_raw()
 }
 
+#[no_mangle]
+pub extern "C" fn hz_to_written_pitch(hz: f32, a4_hz: f32, transposition_semitones: i32) -> *mut c_char {
+    let pitch = Pitch::with_reference(hz, TuningReference::new(a4_hz));
+    CString::new(format!("{}", pitch.transposed(transposition_semitones)))
+		.unwrap()
+        .into_raw()
+}
+
 #[no_mangle]
 pub extern "C" fn correlation(signal_ptr: *mut f32, signal_length: usize, sample_rate: f32) {
     let signal_slice = unsafe {
@@ -65,7 +112,7 @@ pub extern "C" fn correlation(signal_ptr: *mut f32, signal_length: usize, sample
     };
 
     let signal = Signal::new(signal_slice, sample_rate);
-    let model = Model::from_signal(signal);
+    let model = Model::from_signal(signal, &AnalysisSettings::default());
 
     unsafe {
         for (i, cor) in model.correlation.value.iter().enumerate() {
@@ -73,3 +120,217 @@ pub extern "C" fn correlation(signal_ptr: *mut f32, signal_length: usize, sample
         }
     }
 }
+
+// Overwrites the start of the signal with its magnitude spectrum in
+// dB, from 0Hz up to the Nyquist frequency, and returns how many bins
+// were written. There are never more bins than samples.
+#[no_mangle]
+pub extern "C" fn spectrum(signal_ptr: *mut f32, signal_length: usize, sample_rate: f32) -> usize {
+    let signal_slice = unsafe {
+        &slice::from_raw_parts(signal_ptr, signal_length)
+    };
+
+    let signal = Signal::new(signal_slice, sample_rate);
+    let spectrum = Spectrum::from_signal(&signal);
+
+    unsafe {
+        for (i, db) in spectrum.decibels.iter().enumerate() {
+            *signal_ptr.add(i) = *db;
+        }
+    }
+    spectrum.decibels.len()
+}
+
+#[repr(C)]
+pub struct VibratoResult {
+    detected: i32,
+    rate: f32,
+    depth: f32,
+    regularity: f32
+}
+
+// Looks for vibrato in a run of pitch readings for one note, given as
+// times in seconds and pitches in Hz. Readings without a pitch should
+// be left out rather than passed as NaN.
+#[no_mangle]
+pub extern "C" fn detect_vibrato(times_ptr: *const f32, hz_ptr: *const f32, length: usize, result_ptr: *mut VibratoResult) {
+    let (times, hz) = unsafe {
+        (slice::from_raw_parts(times_ptr, length), slice::from_raw_parts(hz_ptr, length))
+    };
+    let cents: Vec<f32> = hz.iter().map(|&hz| Pitch::new(hz).midi_number() * 100.0).collect();
+    let vibrato = Vibrato::detect(times, &cents);
+
+    unsafe {
+        *result_ptr = match vibrato {
+            Some(vibrato) => VibratoResult {
+                detected: 1,
+                rate: vibrato.rate,
+                depth: vibrato.depth,
+                regularity: vibrato.regularity
+            },
+            None => VibratoResult {
+                detected: 0,
+                rate: f32::NAN,
+                depth: f32::NAN,
+                regularity: 0.0
+            }
+        };
+    }
+}
+
+#[repr(C)]
+pub struct AnalyzerResult {
+    // NaN when there's no pitch
+    hz: f32,
+    clarity: f32,
+    // NaN when there's no pitch
+    cents: f32,
+    level_db: f32,
+    rejection: i32
+}
+
+impl AnalyzerResult {
+    fn empty() -> AnalyzerResult {
+        AnalyzerResult {
+            hz: f32::NAN,
+            clarity: 0.0,
+            cents: f32::NAN,
+            level_db: ::signal::MIN_DBFS,
+            rejection: rejection_code(Some(Rejection::Silence))
+        }
+    }
+}
+
+// An analyzer for JS to keep between calls. It owns the buffer that
+// JS copies samples into, so JS never has to allocate, and keeps its
+// result at a fixed address inside it.
+pub struct AnalyzerHandle {
+    analyzer: Analyzer,
+    input: Vec<f32>,
+    result: AnalyzerResult,
+    naming: NoteNaming,
+    note: CString
+}
+
+#[no_mangle]
+pub extern "C" fn analyzer_create(sample_rate: f32, a4_hz: f32) -> *mut AnalyzerHandle {
+    let settings = AnalysisSettings {
+        reference: TuningReference::new(a4_hz),
+        ..AnalysisSettings::default()
+    };
+    let handle = AnalyzerHandle {
+        analyzer: Analyzer::new(sample_rate, settings),
+        input: Vec::new(),
+        result: AnalyzerResult::empty(),
+        naming: NoteNaming::default(),
+        note: CString::default()
+    };
+    Box::into_raw(Box::new(handle))
+}
+
+#[no_mangle]
+pub extern "C" fn analyzer_destroy(handle: *mut AnalyzerHandle) {
+    if !handle.is_null() {
+        unsafe {
+            let _ = Box::from_raw(handle);
+        }
+    }
+}
+
+// Where to copy the next `length` samples before calling
+// analyzer_feed. The buffer may move when it grows, and views on
+// wasm memory are invalidated when the memory grows, so get this
+// again before every copy.
+#[no_mangle]
+pub extern "C" fn analyzer_input_buffer(handle: *mut AnalyzerHandle, length: usize) -> *mut f32 {
+    match unsafe { handle.as_mut() } {
+        Some(handle) => {
+            handle.input.resize(length, 0.0);
+            handle.input.as_mut_ptr()
+        },
+        None => ptr::null_mut()
+    }
+}
+
+// Adds the first `length` samples of the input buffer to the stream.
+// Returns 1 if the result was updated, 0 if not.
+#[no_mangle]
+pub extern "C" fn analyzer_feed(handle: *mut AnalyzerHandle, length: usize) -> i32 {
+    let handle = match unsafe { handle.as_mut() } {
+        Some(handle) => handle,
+        None => {return 0;}
+    };
+    let length = length.min(handle.input.len());
+    if !handle.analyzer.feed(&handle.input[..length]) {
+        return 0;
+    }
+
+    let reading = handle.analyzer.reading();
+    handle.result = AnalyzerResult {
+        hz: reading.pitch.map_or(f32::NAN, |p| p.hz),
+        clarity: reading.estimate.clarity,
+        cents: reading.pitch.map_or(f32::NAN, |p| p.cents_error()),
+        level_db: reading.level_db,
+        rejection: rejection_code(reading.estimate.rejection)
+    };
+    1
+}
+
+// The latest result. The pointer stays valid until the analyzer is
+// destroyed.
+#[no_mangle]
+pub extern "C" fn analyzer_result(handle: *const AnalyzerHandle) -> *const AnalyzerResult {
+    match unsafe { handle.as_ref() } {
+        Some(handle) => &handle.result,
+        None => ptr::null()
+    }
+}
+
+// The name of the latest note as written for a transposing
+// instrument, as a 0 terminated UTF-8 string that's empty when there's
+// no pitch. It belongs to the analyzer and is overwritten by the next
+// call, so it must not be freed.
+#[no_mangle]
+pub extern "C" fn analyzer_note(handle: *mut AnalyzerHandle, transposition_semitones: i32) -> *const c_char {
+    let handle = match unsafe { handle.as_mut() } {
+        Some(handle) => handle,
+        None => {return ptr::null();}
+    };
+    let note = handle.analyzer.reading().pitch
+        .map_or(String::new(), |p| p.transposed(transposition_semitones).name(&handle.naming));
+    handle.note = CString::new(note).unwrap_or_default();
+    handle.note.as_ptr()
+}
+
+#[no_mangle]
+pub extern "C" fn analyzer_set_reference(handle: *mut AnalyzerHandle, a4_hz: f32) {
+    if let Some(handle) = unsafe { handle.as_mut() } {
+        handle.analyzer.settings.reference = TuningReference::new(a4_hz);
+    }
+}
+
+// How analyzer_note names notes. Each option is its position in the
+// Rust enum's list, so system is 0 for letters, 1 for German and 2 for
+// solfège, spelling is 0 for mixed, 1 for sharps, 2 for flats and 3
+// for the key signature of `key` semitones above C, accidentals is 0
+// for ♯ and ♭ and 1 for # and b, and octaves is 0 for scientific and
+// 1 for Helmholtz. Anything out of range is left at its default.
+#[no_mangle]
+pub extern "C" fn analyzer_set_naming(handle: *mut AnalyzerHandle, system: i32, spelling: i32, key: i32, accidentals: i32, octaves: i32) {
+    fn nth<T: Default>(all: Vec<T>, index: i32) -> T {
+        if index < 0 {
+            return T::default();
+        }
+        all.into_iter().nth(index as usize).unwrap_or_default()
+    }
+
+    if let Some(handle) = unsafe { handle.as_mut() } {
+        handle.naming = NoteNaming {
+            system: nth(NamingSystem::all(), system),
+            spelling: nth(Spelling::all(), spelling),
+            key: key.rem_euclid(12),
+            accidentals: nth(Accidentals::all(), accidentals),
+            octaves: nth(OctaveNotation::all(), octaves)
+        };
+    }
+}
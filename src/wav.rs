@@ -0,0 +1,300 @@
+use std::error;
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::io::Read;
+use std::path::Path;
+
+const FORMAT_PCM: u16 = 1;
+const FORMAT_IEEE_FLOAT: u16 = 3;
+const FORMAT_EXTENSIBLE: u16 = 0xFFFE;
+
+#[derive(Debug)]
+pub enum WavError {
+    Io(io::Error),
+    NotRiffWave,
+    MissingChunk(&'static str),
+    Truncated,
+    UnsupportedFormat { format_tag: u16, bits_per_sample: u16 },
+    NoChannels,
+    NoSampleRate
+}
+
+impl fmt::Display for WavError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            WavError::Io(ref e) => write!(f, "{}", e),
+            WavError::NotRiffWave => write!(f, "Not a RIFF WAVE file"),
+            WavError::MissingChunk(id) => write!(f, "WAV file has no {} chunk", id),
+            WavError::Truncated => write!(f, "WAV file is truncated"),
+            WavError::UnsupportedFormat { format_tag, bits_per_sample } =>
+                write!(f, "Unsupported WAV sample format {} with {} bits per sample", format_tag, bits_per_sample),
+            WavError::NoChannels => write!(f, "WAV file has no channels"),
+            WavError::NoSampleRate => write!(f, "WAV file has a sample rate of 0")
+        }
+    }
+}
+
+impl error::Error for WavError {}
+
+impl From<io::Error> for WavError {
+    fn from(e: io::Error) -> WavError {
+        WavError::Io(e)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum SampleFormat {
+    Int,
+    Float
+}
+
+#[derive(Debug, Clone)]
+pub struct Wav {
+    pub sample_rate: u32,
+    pub channels: u16,
+    // Channels are averaged together, since pitch detection only
+    // needs a single channel.
+    pub samples: Vec<f32>
+}
+
+impl Wav {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Wav, WavError> {
+        let file = File::open(path)?;
+        Wav::read(io::BufReader::new(file))
+    }
+
+    pub fn read<R: Read>(mut reader: R) -> Result<Wav, WavError> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+        Wav::parse(&bytes)
+    }
+
+    pub fn duration(&self) -> f32 {
+        self.samples.len() as f32 / self.sample_rate as f32
+    }
+
+    fn parse(bytes: &[u8]) -> Result<Wav, WavError> {
+        if bytes.len() < 12 || &bytes[0..4] != b"RIFF" || &bytes[8..12] != b"WAVE" {
+            return Err(WavError::NotRiffWave);
+        }
+
+        let mut format = None;
+        let mut data = None;
+        let mut position = 12;
+        while position + 8 <= bytes.len() {
+            let id = &bytes[position..position + 4];
+            let size = read_u32(bytes, position + 4) as usize;
+            let start = position + 8;
+            // Some writers leave the data size as 0 or too large when
+            // they can't seek back to fill it in, so take what's there.
+            // The sum is checked since usize is only 32 bits on wasm32.
+            let end = if size == 0 && id == b"data" {
+                bytes.len()
+            } else {
+                start.checked_add(size).map_or(bytes.len(), |end| end.min(bytes.len()))
+            };
+            let body = &bytes[start..end];
+
+            match id {
+                b"fmt " => format = Some(body),
+                b"data" => data = Some(body),
+                _ => {}
+            }
+
+            // chunks are padded to an even number of bytes
+            position = end.saturating_add(size % 2);
+        }
+
+        let format = format.ok_or(WavError::MissingChunk("fmt"))?;
+        let data = data.ok_or(WavError::MissingChunk("data"))?;
+        if format.len() < 16 {
+            return Err(WavError::Truncated);
+        }
+
+        let mut format_tag = read_u16(format, 0);
+        let channels = read_u16(format, 2);
+        let sample_rate = read_u32(format, 4);
+        let bits_per_sample = read_u16(format, 14);
+        if format_tag == FORMAT_EXTENSIBLE {
+            if format.len() < 26 {
+                return Err(WavError::Truncated);
+            }
+            // the first two bytes of the subformat GUID are the real
+            // format tag
+            format_tag = read_u16(format, 24);
+        }
+        if channels == 0 {
+            return Err(WavError::NoChannels);
+        }
+        if sample_rate == 0 {
+            return Err(WavError::NoSampleRate);
+        }
+
+        let sample_format = match (format_tag, bits_per_sample) {
+            (FORMAT_PCM, 8) | (FORMAT_PCM, 16) | (FORMAT_PCM, 24) | (FORMAT_PCM, 32) => SampleFormat::Int,
+            (FORMAT_IEEE_FLOAT, 32) | (FORMAT_IEEE_FLOAT, 64) => SampleFormat::Float,
+            _ => return Err(WavError::UnsupportedFormat { format_tag: format_tag, bits_per_sample: bits_per_sample })
+        };
+
+        let bytes_per_sample = bits_per_sample as usize / 8;
+        let frame_size = bytes_per_sample * channels as usize;
+        let samples = data.chunks(frame_size)
+            .filter(|frame| frame.len() == frame_size)
+            .map(|frame| {
+                let sum: f32 = frame.chunks(bytes_per_sample)
+                    .map(|sample| decode_sample(sample, sample_format))
+                    .sum();
+                sum / f32::from(channels)
+            })
+            .collect();
+
+        Ok(Wav {
+            sample_rate: sample_rate,
+            channels: channels,
+            samples: samples
+        })
+    }
+}
+
+fn read_u16(bytes: &[u8], offset: usize) -> u16 {
+    u16::from(bytes[offset]) | u16::from(bytes[offset + 1]) << 8
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from(read_u16(bytes, offset)) | u32::from(read_u16(bytes, offset + 2)) << 16
+}
+
+fn decode_sample(bytes: &[u8], format: SampleFormat) -> f32 {
+    match (format, bytes.len()) {
+        // 8 bit WAV is the odd one out, being unsigned
+        (SampleFormat::Int, 1) => (f32::from(bytes[0]) - 128.0) / 128.0,
+        (SampleFormat::Int, 2) => f32::from(read_u16(bytes, 0) as i16) / 32_768.0,
+        (SampleFormat::Int, 3) => {
+            // shift up to fill an i32 so the sign bit lands in place
+            let value = (u32::from(bytes[0]) << 8 | u32::from(bytes[1]) << 16 | u32::from(bytes[2]) << 24) as i32;
+            (value >> 8) as f32 / 8_388_608.0
+        },
+        (SampleFormat::Int, 4) => read_u32(bytes, 0) as i32 as f32 / 2_147_483_648.0,
+        (SampleFormat::Float, 4) => f32::from_bits(read_u32(bytes, 0)),
+        (SampleFormat::Float, 8) => {
+            let bits = u64::from(read_u32(bytes, 0)) | u64::from(read_u32(bytes, 4)) << 32;
+            f64::from_bits(bits) as f32
+        },
+        _ => 0.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn wav_bytes(format_tag: u16, channels: u16, bits_per_sample: u16, data: &[u8]) -> Vec<u8> {
+        wav_bytes_at(44100, format_tag, channels, bits_per_sample, data)
+    }
+
+    fn wav_bytes_at(sample_rate: u32, format_tag: u16, channels: u16, bits_per_sample: u16, data: &[u8]) -> Vec<u8> {
+        let block_align = channels * bits_per_sample / 8;
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(b"RIFF");
+        bytes.extend_from_slice(&u32_le(36 + data.len() as u32));
+        bytes.extend_from_slice(b"WAVE");
+        bytes.extend_from_slice(b"fmt ");
+        bytes.extend_from_slice(&u32_le(16));
+        bytes.extend_from_slice(&u16_le(format_tag));
+        bytes.extend_from_slice(&u16_le(channels));
+        bytes.extend_from_slice(&u32_le(sample_rate));
+        bytes.extend_from_slice(&u32_le(sample_rate * u32::from(block_align)));
+        bytes.extend_from_slice(&u16_le(block_align));
+        bytes.extend_from_slice(&u16_le(bits_per_sample));
+        bytes.extend_from_slice(b"data");
+        bytes.extend_from_slice(&u32_le(data.len() as u32));
+        bytes.extend_from_slice(data);
+        bytes
+    }
+
+    fn u16_le(x: u16) -> [u8; 2] {
+        [x as u8, (x >> 8) as u8]
+    }
+
+    fn u32_le(x: u32) -> [u8; 4] {
+        [x as u8, (x >> 8) as u8, (x >> 16) as u8, (x >> 24) as u8]
+    }
+
+    #[test]
+    fn reads_16_bit_pcm() {
+        let data: Vec<u8> = [0i16, 16384, -32768].iter().flat_map(|&x| u16_le(x as u16).to_vec()).collect();
+        let wav = Wav::read(&wav_bytes(FORMAT_PCM, 1, 16, &data)[..]).unwrap();
+        assert_eq!(wav.sample_rate, 44100);
+        assert_eq!(wav.samples, vec!(0.0, 0.5, -1.0));
+    }
+
+    #[test]
+    fn reads_24_bit_pcm() {
+        let data = [0x00, 0x00, 0x40, 0x00, 0x00, 0xC0, 0xFF, 0xFF, 0xFF];
+        let wav = Wav::read(&wav_bytes(FORMAT_PCM, 1, 24, &data)[..]).unwrap();
+        assert_eq!(wav.samples, vec!(0.5, -0.5, -1.0 / 8_388_608.0));
+    }
+
+    #[test]
+    fn reads_float() {
+        let data: Vec<u8> = [0.25f32, -0.75].iter().flat_map(|x| u32_le(x.to_bits()).to_vec()).collect();
+        let wav = Wav::read(&wav_bytes(FORMAT_IEEE_FLOAT, 1, 32, &data)[..]).unwrap();
+        assert_eq!(wav.samples, vec!(0.25, -0.75));
+    }
+
+    #[test]
+    fn mixes_stereo_down_to_mono() {
+        let data: Vec<u8> = [16384i16, 0, -16384, -16384].iter().flat_map(|&x| u16_le(x as u16).to_vec()).collect();
+        let wav = Wav::read(&wav_bytes(FORMAT_PCM, 2, 16, &data)[..]).unwrap();
+        assert_eq!(wav.channels, 2);
+        assert_eq!(wav.samples, vec!(0.25, -0.5));
+    }
+
+    #[test]
+    fn skips_unknown_chunks() {
+        let mut bytes = wav_bytes(FORMAT_PCM, 1, 16, &u16_le(16384));
+        // an odd sized LIST chunk, with its padding byte, before the fmt chunk
+        let list = [b'L', b'I', b'S', b'T', 3, 0, 0, 0, b'a', b'b', b'c', 0];
+        for (i, &b) in list.iter().enumerate() {
+            bytes.insert(12 + i, b);
+        }
+        let wav = Wav::read(&bytes[..]).unwrap();
+        assert_eq!(wav.samples, vec!(0.5));
+    }
+
+    #[test]
+    fn rejects_files_that_are_not_wav() {
+        match Wav::read(&b"ID3 not a wave file at all"[..]) {
+            Err(WavError::NotRiffWave) => {},
+            other => panic!("Expected NotRiffWave, got {:?}", other)
+        }
+    }
+
+    #[test]
+    fn rejects_a_sample_rate_of_zero() {
+        match Wav::read(&wav_bytes_at(0, FORMAT_PCM, 1, 16, &[0, 0])[..]) {
+            Err(WavError::NoSampleRate) => {},
+            other => panic!("Expected NoSampleRate, got {:?}", other)
+        }
+    }
+
+    #[test]
+    fn huge_chunk_sizes_are_clamped() {
+        let mut bytes = wav_bytes(FORMAT_PCM, 1, 16, &u16_le(16384));
+        let list = [b'L', b'I', b'S', b'T', 0xff, 0xff, 0xff, 0xff];
+        let end = bytes.len();
+        bytes.splice(end..end, list.iter().cloned());
+        let wav = Wav::read(&bytes[..]).unwrap();
+        assert_eq!(wav.samples, vec!(0.5));
+    }
+
+    #[test]
+    fn rejects_unsupported_formats() {
+        // format 2 is Microsoft ADPCM
+        match Wav::read(&wav_bytes(2, 1, 4, &[0, 0])[..]) {
+            Err(WavError::UnsupportedFormat { format_tag: 2, bits_per_sample: 4 }) => {},
+            other => panic!("Expected UnsupportedFormat, got {:?}", other)
+        }
+    }
+}
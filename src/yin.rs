@@ -0,0 +1,114 @@
+use std::f32;
+
+use signal::{Signal, MIN_DBFS};
+use detector::{FrequencyRange, PitchDetector, PitchEstimate, Rejection, parabolic_interpolation};
+
+// De Cheveigné and Kawahara's YIN estimator. It looks for the first
+// dip in the cumulative mean normalized difference function that
+// falls below the threshold.
+#[derive(Debug, Clone, Copy)]
+pub struct Yin {
+    pub threshold: f32
+}
+
+impl Default for Yin {
+    fn default() -> Yin {
+        Yin {
+            threshold: 0.15
+        }
+    }
+}
+
+impl Yin {
+    pub fn cumulative_mean_normalized_difference(samples: &[f32]) -> Vec<f32> {
+        let max_lag = samples.len() / 2;
+        let window = samples.len() - max_lag;
+
+        let difference: Vec<f32> = (0..max_lag).map(|lag| {
+            samples.iter().take(window)
+                .zip(samples.iter().skip(lag))
+                .map(|(x, y)| (x - y) * (x - y))
+                .sum()
+        }).collect();
+
+        let mut running_sum = 0.0;
+        difference.iter().enumerate().map(|(lag, &d)| {
+            if lag == 0 {
+                return 1.0;
+            }
+            running_sum += d;
+            if running_sum > 0.0 {
+                d * lag as f32 / running_sum
+            }
+            else {
+                1.0
+            }
+        }).collect()
+    }
+
+    fn find_dip(&self, normalized_difference: &[f32], shortest: usize) -> Option<usize> {
+        normalized_difference.iter()
+            .skip(shortest)
+            .position(|&d| d < self.threshold)
+            .map(|p| Yin::local_minimum(normalized_difference, p + shortest))
+    }
+
+    fn local_minimum(normalized_difference: &[f32], start: usize) -> usize {
+        normalized_difference.iter()
+            .enumerate()
+            .skip(start)
+            .take_while(|&(i, &d)| i == start || d < normalized_difference[i - 1])
+            .map(|(i, _)| i)
+            .last()
+            .unwrap_or(start)
+    }
+
+    fn global_minimum(normalized_difference: &[f32], shortest: usize) -> usize {
+        normalized_difference.iter()
+            .enumerate()
+            .skip(shortest)
+            .fold((shortest, f32::INFINITY), |(xi, x), (yi, &y)| if y < x { (yi, y) } else { (xi, x) })
+            .0
+    }
+}
+
+impl PitchDetector for Yin {
+    fn find_fundamental_frequency_in(&self, signal: &Signal, range: FrequencyRange) -> PitchEstimate {
+        if signal.is_below(MIN_DBFS) {
+            return PitchEstimate::rejected(Rejection::Silence);
+        }
+
+        let normalized_difference = Yin::cumulative_mean_normalized_difference(&signal.samples);
+        if normalized_difference.len() < 3 {
+            return PitchEstimate::rejected(Rejection::NoZeroCrossing);
+        }
+        let estimate_at = |dip: usize| {
+            let period = parabolic_interpolation(&normalized_difference, dip);
+            let clarity = (1.0 - normalized_difference[dip]).clamp(0.0, 1.0);
+            (signal.sample_rate / period, clarity)
+        };
+
+        let (shortest, longest) = range.lags(signal.sample_rate, normalized_difference.len() - 1);
+        let shortest = shortest.max(2);
+        // the dips are only looked for within the range, but the
+        // whole function is kept for interpolation
+        let searchable = &normalized_difference[..longest.max(shortest) + 1];
+
+        match self.find_dip(searchable, shortest) {
+            Some(dip) if dip == longest && longest + 1 < normalized_difference.len() => {
+                // still falling at the longest period allowed
+                PitchEstimate::rejected(Rejection::OutOfRange)
+            },
+            Some(dip) => {
+                let (hz, clarity) = estimate_at(dip);
+                PitchEstimate::new(hz, clarity)
+            },
+            None => {
+                // nothing dipped below the threshold, so the lowest
+                // point is offered as an unvoiced guess
+                let (hz, clarity) = estimate_at(Yin::global_minimum(searchable, shortest));
+                PitchEstimate::noise(hz, clarity)
+            }
+        }
+    }
+}
@@ -0,0 +1,80 @@
+/* Exercises the C API the way a native practice tool would. Built and
+ * run by tests/c_api.rs. */
+#include <math.h>
+#include <stdio.h>
+#include <string.h>
+
+#include "rusty_microphone.h"
+
+#define SAMPLE_RATE 44100.0f
+#define BLOCK_SIZE 512
+
+static int failures = 0;
+
+static void check(int condition, const char *description) {
+    if (!condition) {
+        fprintf(stderr, "FAILED: %s\n", description);
+        failures += 1;
+    }
+}
+
+/* Feeds in blocks of a sine, carrying on from where the last call
+ * left off. */
+static void feed_sine(RmAnalyzer *analyzer, float hz, int blocks) {
+    static size_t start = 0;
+    float block[BLOCK_SIZE];
+    int i;
+    for (; blocks > 0; blocks--) {
+        for (i = 0; i < BLOCK_SIZE; i++) {
+            block[i] = 0.5f * sinf(2.0f * 3.14159265f * hz * (float)(start + i) / SAMPLE_RATE);
+        }
+        start += BLOCK_SIZE;
+        check(rm_analyzer_feed(analyzer, block, BLOCK_SIZE) == RM_OK, "audio can be fed");
+    }
+}
+
+int main(void) {
+    float block[BLOCK_SIZE] = { 0.0f };
+    float hz = 0.0f;
+    float clarity = 0.0f;
+    float cents = 0.0f;
+    char name[16];
+    char tiny[2];
+    int status;
+
+    RmAnalyzer *analyzer = rm_analyzer_new(SAMPLE_RATE, 440.0f);
+    check(analyzer != NULL, "an analyzer can be created");
+    check(rm_analyzer_new(0.0f, 440.0f) == NULL, "a zero sample rate is refused");
+
+    check(rm_analyzer_pitch(analyzer, &hz, &clarity) == RM_NO_PITCH, "there's no pitch before any audio");
+    check(isnan(hz), "hz is NaN without a pitch");
+
+    /* a quarter tone sharp of A4 */
+    feed_sine(analyzer, 452.89f, 8);
+
+    check(rm_analyzer_pitch(analyzer, &hz, &clarity) == RM_OK, "a pitch is found");
+    check(fabsf(hz - 452.89f) < 1.0f, "the pitch is right");
+    check(clarity > 0.9f, "a sine is clear");
+    check(rm_analyzer_cents(analyzer, &cents) == RM_OK, "cents are found");
+    check(fabsf(cents - 50.0f) < 2.0f || fabsf(cents + 50.0f) < 2.0f, "a quarter tone is 50 cents out");
+
+    check(rm_analyzer_set_reference(analyzer, 452.89f) == RM_OK, "the reference can be changed");
+    feed_sine(analyzer, 452.89f, 1);
+    rm_analyzer_cents(analyzer, &cents);
+    check(fabsf(cents) < 2.0f, "cents follow the reference");
+
+    check(rm_analyzer_note_name(analyzer, 0, name, sizeof(name)) == RM_OK, "the note can be named");
+    check(strcmp(name, "A 4") == 0, "the note is A 4");
+    check(rm_analyzer_note_name(analyzer, 2, name, sizeof(name)) == RM_OK, "the note can be named for a B flat instrument");
+    check(strcmp(name, "B 4") == 0, "a B flat instrument reads a concert A as B");
+    status = rm_analyzer_note_name(analyzer, 0, tiny, sizeof(tiny));
+    check(status == RM_BUFFER_TOO_SMALL, "small buffers are refused");
+    check(tiny[0] == '\0', "small buffers are left empty");
+    check(strlen(rm_status_message(status)) > 0, "statuses have messages");
+
+    check(rm_analyzer_feed(NULL, block, BLOCK_SIZE) == RM_NULL_POINTER, "NULL analyzers are refused");
+    rm_analyzer_free(analyzer);
+    rm_analyzer_free(NULL);
+
+    return failures == 0 ? 0 : 1;
+}
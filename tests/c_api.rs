@@ -0,0 +1,51 @@
+// Compiles tests/c/analyzer_test.c against the header and
+// the shared library, then runs it. Set CC to use a compiler other
+// than cc.
+//
+// Cargo only builds the rlib for tests, so the shared library is built
+// here first.
+
+use std::env;
+use std::path::PathBuf;
+use std::process::Command;
+
+// target/debug or target/release, where cargo puts the library built
+// for these tests
+fn target_dir() -> PathBuf {
+    let exe = env::current_exe().unwrap();
+    exe.parent().and_then(|deps| deps.parent()).unwrap().to_path_buf()
+}
+
+#[test]
+fn c_program_can_use_the_library() {
+    let manifest_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let target_dir = target_dir();
+    let program = target_dir.join("c_analyzer_test");
+    let compiler = env::var("CC").unwrap_or_else(|_| "cc".to_string());
+
+    let mut build = Command::new(env!("CARGO"));
+    build.arg("build").arg("--lib")
+        .arg("--manifest-path").arg(manifest_dir.join("Cargo.toml"));
+    // so the library lands next to this test under `cargo test --release`
+    if target_dir.ends_with("release") {
+        build.arg("--release");
+    }
+    let status = build.status().unwrap();
+    assert!(status.success(), "Failed to build the library");
+
+    let status = Command::new(&compiler)
+        .arg(manifest_dir.join("tests").join("c").join("analyzer_test.c"))
+        .arg("-I").arg(manifest_dir.join("include"))
+        .arg("-L").arg(&target_dir)
+        .arg(format!("-Wl,-rpath,{}", target_dir.display()))
+        .arg("-lrusty_microphone")
+        .arg("-lm")
+        .arg("-Wall").arg("-Werror")
+        .arg("-o").arg(&program)
+        .status()
+        .unwrap_or_else(|e| panic!("Couldn't run {}: {}", compiler, e));
+    assert!(status.success(), "{} failed to build the C test program", compiler);
+
+    let status = Command::new(&program).status().unwrap();
+    assert!(status.success(), "The C test program failed");
+}
@@ -38,140 +38,103 @@ function checkBrowserSupport(supportedCallback) {
 }
 
 /**
- * Puts at javascript float array onto the heap and provides a pointer
+ * Wraps an analyzer from the Webassembly module
  *
- * Webassembly's input types are limited to integers and floating
- * point numbers. You can get around this limitation by putting the
- * data you want (a f32 array in this case) onto the heap, and passing
- * Webassembly a pointer and length. A callback is used for the actual
- * Webassembly call so that the heap memory can be automatically freed
- * afterwards.
+ * The analyzer lives in Webassembly memory between calls. It owns the
+ * buffer that samples are copied into, and remembers the audio it has
+ * already been fed, so samples should be fed in order as they arrive,
+ * without gaps or overlaps. Call destroy when finished with it.
  *
- * @param {array} jsArray - A javascript array, or Float32Array, of numbers
- * @param {function} callback - The function to call with a pointer and length of the array
+ * @param {number} sampleRate - The sample rate of the audio that will be fed in
+ * @param {number} referenceHz - The frequency of A4
  */
-function jsArrayToF32ArrayPtr(jsArray, callback) {
-    var data = (jsArray instanceof Float32Array) ? jsArray : new Float32Array(jsArray);
-    var nDataBytes = data.length * data.BYTES_PER_ELEMENT;
-    var dataPtr = mod.exports.malloc(nDataBytes);
-
-    var dataHeap = new Uint8Array(mod.exports.memory.buffer, dataPtr, nDataBytes);
-    dataHeap.set(new Uint8Array(data.buffer));
-
-    var result = callback(dataPtr, jsArray.length);
-
-    mod.exports.free(dataPtr, nDataBytes);
-    
-    return result;
-}
-
-/**
- * Puts at javascript float array onto the heap and provides a pointer, for functions that mutate the array in place
- *
- * Webassembly's input types are limited to integers and floating
- * point numbers. You can get around this limitation by putting the
- * data you want (a f32 array in this case) onto the heap, and passing
- * Webassembly a pointer and length. In this case, we also want to
- * return an array of the same lenght, so our Webassembly function
- * mutates the input array in place. A callback is used for the actual
- * Webassembly call so that the heap memory can be automatically freed
- * afterwards.
- *
- * @param {array} jsArray - A javascript array, or Float32Array, of numbers
- * @param {function} callback - The function to call with a pointer and length of the array
- */
-function jsArrayToF32ArrayPtrMutateInPlace(jsArray, mutate) {
-    var data = new Float32Array(jsArray);
-    var nDataBytes = data.length * data.BYTES_PER_ELEMENT;
-    var dataPtr = mod.exports.malloc(nDataBytes);
+function createAnalyzer(sampleRate, referenceHz) {
+    var handle = mod.exports.analyzer_create(sampleRate, referenceHz);
+
+    function feed(samples) {
+        // Views on the memory are invalidated whenever it grows, so
+        // they're made fresh for every call.
+        var inputPtr = mod.exports.analyzer_input_buffer(handle, samples.length);
+        new Float32Array(mod.exports.memory.buffer, inputPtr, samples.length).set(samples);
+        mod.exports.analyzer_feed(handle, samples.length);
+    }
 
-    var dataHeap = new Uint8Array(mod.exports.memory.buffer, dataPtr, nDataBytes);
-    dataHeap.set(new Uint8Array(data.buffer));
+    /**
+     * The latest result. hz and cents are NaN when there's no pitch.
+     *
+     * Rejection codes are 0 for an accepted pitch, 1 for silence, 2 for
+     * no zero crossing, 3 for noise, and 4 for a pitch outside the
+     * instrument's range.
+     */
+    function result() {
+        var resultPtr = mod.exports.analyzer_result(handle);
+        var floats = new Float32Array(mod.exports.memory.buffer, resultPtr, 4);
+        var ints = new Int32Array(mod.exports.memory.buffer, resultPtr + 16, 1);
+        return {
+            hz: floats[0],
+            clarity: floats[1],
+            cents: floats[2],
+            levelDb: floats[3],
+            rejection: ints[0]
+        };
+    }
 
-    mutate(dataPtr, jsArray.length);
+    function note(transpositionSemitones) {
+        return readCStr(mod.exports.analyzer_note(handle, transpositionSemitones));
+    }
 
-    var mutatedData = new Float32Array(mod.exports.memory.buffer, dataPtr, jsArray.length);
-    var result = Array.prototype.slice.call(mutatedData);
-    
-    mod.exports.free(dataPtr, nDataBytes);
-    
-    return result;
-}
+    function setReference(referenceHz) {
+        mod.exports.analyzer_set_reference(handle, referenceHz);
+    }
 
-function findFundamentalFrequency(data, samplingRate) {
-    return jsArrayToF32ArrayPtr(data, function(dataPtr, dataLength) {
-        return mod.exports.find_fundamental_frequency(dataPtr, dataLength, samplingRate);
-    });
-}
+    /**
+     * How notes are named. Each option is an index, in the same order
+     * as the options in index.html.
+     */
+    function setNaming(naming) {
+        mod.exports.analyzer_set_naming(handle, naming.system, naming.spelling, naming.key, naming.accidentals, naming.octaves);
+    }
 
-var nDataBytes = null;
-var dataPtr = null;
-var dataHeap = null;
-/**
- * Does the same thing as findFundamentalFrequency, except
- * 1. assumes the array is already a Float32Array
- * 2. assumes that the array will always be the same length of subsequent calls
- * 3. does not free the allocated memory on the heap
- * 4. reuses the allocated heap memory on subsequent calls
- */
-function findFundamentalFrequencyNoFree(data, samplingRate) {
-    if (!dataPtr) {
-        nDataBytes = data.length * data.BYTES_PER_ELEMENT;
-        dataPtr = mod.exports.malloc(nDataBytes);
-        dataHeap = new Uint8Array(mod.exports.memory.buffer, dataPtr, nDataBytes);
+    function destroy() {
+        mod.exports.analyzer_destroy(handle);
+        handle = 0;
     }
-    dataHeap.set(new Uint8Array(data.buffer, data.buffer.byteLength - nDataBytes));
-    return mod.exports.find_fundamental_frequency(dataPtr, data.length, samplingRate);    
+
+    return {
+        feed: feed,
+        result: result,
+        note: note,
+        setReference: setReference,
+        setNaming: setNaming,
+        destroy: destroy
+    };
 }
 
 /**
- * Takes a pointer to a C-style string (ends in a 0), and interprets it as UTF-8.
+ * Takes a pointer to a C-style string (ends in a 0), and interprets
+ * it as UTF-8. The string still belongs to Webassembly.
  */
-function copyCStr(ptr) {
-    var iter = ptr;
-
-    // ye olde 0 terminated string
-    function* collectCString() {
-        var memory = new Uint8Array(mod.exports.memory.buffer);
-        while (memory[iter] !== 0) {
-            if (memory[iter] === undefined) {
-                throw new Error("Tried to read undef mem");
-            }
-            yield memory[iter];
-            iter += 1;
+function readCStr(ptr) {
+    var memory = new Uint8Array(mod.exports.memory.buffer);
+    var end = ptr;
+    while (memory[end] !== 0) {
+        if (memory[end] === undefined) {
+            throw new Error("Tried to read undef mem");
         }
-    };
+        end += 1;
+    }
 
-    var buffer_as_u8 = new Uint8Array(collectCString());
     var utf8Decoder = new TextDecoder("UTF-8");
-    var buffer_as_utf8 = utf8Decoder.decode(buffer_as_u8);
-    mod.exports.free_str(ptr);
-    return buffer_as_utf8;
+    return utf8Decoder.decode(memory.subarray(ptr, end));
 }
 
+function update(view, analyzer, signal, timestamp) {
+    analyzer.setReference(view.referenceHz());
+    analyzer.setNaming(view.naming());
+    var result = analyzer.result();
+    var pitch = analyzer.note(view.transposition());
 
-function hzToCentsError(hz) {
-    return mod.exports.hz_to_cents_error(hz);
-}
-
-function hzToPitch(hz) {
-    var strPtr = mod.exports.hz_to_pitch(hz);
-    return copyCStr(strPtr);
-};
-
-function correlation(data, samplingRate) {
-    return jsArrayToF32ArrayPtrMutateInPlace(data, function(dataPtr, dataLength) {
-        mod.exports.correlation(dataPtr, dataLength, samplingRate);
-    });
-}
-
-function update(view, signal, sampleRate, timestamp) {
-    var fundamental = findFundamentalFrequencyNoFree(signal, sampleRate);
-
-    var pitch = hzToPitch(fundamental);
-    var error = hzToCentsError(fundamental);
-
-    view.draw(signal, timestamp, pitch, error);
+    view.draw(signal, timestamp, pitch, result.cents, result.clarity);
 }
 
 function initView() {
@@ -181,6 +144,13 @@ function initView() {
     var frameRateLabel = document.getElementById('frame-rate');
 
     var pitchLabel = document.getElementById('pitch-label');
+    var referenceSelect = document.getElementById('reference-select');
+    var transpositionSelect = document.getElementById('transposition-select');
+    var namingSystemSelect = document.getElementById('naming-system-select');
+    var spellingSelect = document.getElementById('spelling-select');
+    var keySelect = document.getElementById('key-select');
+    var octavesSelect = document.getElementById('octaves-select');
+    var asciiCheckbox = document.getElementById('ascii-checkbox');
 
     var pitchIndicatorBar = document.getElementById('pitch-indicator-bar');
     var flatIndicator = document.getElementById('flat-indicator');
@@ -195,9 +165,27 @@ function initView() {
     document.getElementById('rusty-microphone').removeAttribute('style');
     drawDebugGraph([]);
 
-    function draw(signal, timestamp, pitch, error) {
+    function referenceHz() {
+        return parseFloat(referenceSelect.value);
+    }
+
+    function transposition() {
+        return parseInt(transpositionSelect.value, 10);
+    }
+
+    function naming() {
+        return {
+            system: parseInt(namingSystemSelect.value, 10),
+            spelling: parseInt(spellingSelect.value, 10),
+            key: parseInt(keySelect.value, 10),
+            accidentals: asciiCheckbox.checked ? 1 : 0,
+            octaves: parseInt(octavesSelect.value, 10)
+        };
+    }
+
+    function draw(signal, timestamp, pitch, error, clarity) {
         drawDebugGraph(signal);
-        updatePitchIndicators(pitch, error);
+        updatePitchIndicators(pitch, error, clarity);
         updateFramerate(timestamp);
     }
 
@@ -212,8 +200,10 @@ function initView() {
         }
     }
 
-    function updatePitchIndicators(pitch, error) {
+    function updatePitchIndicators(pitch, error, clarity) {
         pitchLabel.innerText = pitch;
+        // fade uncertain readings rather than letting them flicker
+        pitchLabel.style.opacity = (0.2 + 0.8 * clarity).toFixed(2);
 
         if (isNaN(error)) {
             pitchIndicatorBar.setAttribute('style', 'visibility: hidden');
@@ -263,7 +253,10 @@ function initView() {
     };
 
     return {
-        draw: draw
+        draw: draw,
+        referenceHz: referenceHz,
+        transposition: transposition,
+        naming: naming
     };
 }
 
@@ -280,20 +273,28 @@ function main() {
             .then(function(stream) {
                 var context = new AudioContext();
                 var input = context.createMediaStreamSource(stream);
-                var analyser = context.createAnalyser();
-                analyser.fftSize = 512;
-                analyser.smoothingTimeConstant = 0;
-                input.connect(analyser);
-
-                var dataArray = new Float32Array(analyser.fftSize);
-
-                function analyserNodeCallback(timestamp) {
-                    analyser.getFloatTimeDomainData(dataArray);
-                    update(view, dataArray, context.sampleRate, timestamp);
-                    window.requestAnimationFrame(analyserNodeCallback);
+                var analyzer = createAnalyzer(context.sampleRate, view.referenceHz());
+
+                // The processor hands over every block of samples in
+                // order, which the analyzer needs to overlap its frames.
+                var processor = context.createScriptProcessor(1024, 1, 1);
+                var signal = new Float32Array(processor.bufferSize);
+                processor.onaudioprocess = function(event) {
+                    signal.set(event.inputBuffer.getChannelData(0));
+                    analyzer.feed(signal);
+                };
+                input.connect(processor);
+                // Some browsers only run a processor that's connected
+                // to an output. It doesn't write anything, so this is
+                // silent.
+                processor.connect(context.destination);
+
+                function animationCallback(timestamp) {
+                    update(view, analyzer, signal, timestamp);
+                    window.requestAnimationFrame(animationCallback);
                 }
 
-                window.requestAnimationFrame(analyserNodeCallback);
+                window.requestAnimationFrame(animationCallback);
             })
             .catch(function(err) {
                 document.getElementById('loading').setAttribute('style', 'display:none');